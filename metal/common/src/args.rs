@@ -0,0 +1,9 @@
+// the value after a flag that takes one, shared by every example's
+// hand rolled argument parser
+pub fn next_value(
+    iter: &mut impl Iterator<Item = String>,
+    flag: &str,
+) -> Result<String, String> {
+    iter.next()
+        .ok_or_else(|| format!("missing value for {}", flag))
+}
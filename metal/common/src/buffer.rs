@@ -0,0 +1,76 @@
+use std::mem::size_of;
+
+use half::f16;
+use metal::{Buffer, BufferRef, DeviceRef, MTLResourceOptions};
+
+use crate::error::MetalError;
+
+/// # Safety
+///
+/// every bit pattern of the type's size has to be a valid value, gpu memory
+/// is reinterpreted as it without any validation. floats, integers and
+/// repr(C) structs of them are fine, bools, enums and references aren't
+pub unsafe trait AnyBitPattern: Copy {}
+
+unsafe impl AnyBitPattern for f32 {}
+unsafe impl AnyBitPattern for f16 {}
+unsafe impl AnyBitPattern for u32 {}
+unsafe impl AnyBitPattern for i32 {}
+unsafe impl AnyBitPattern for u16 {}
+unsafe impl AnyBitPattern for u8 {}
+unsafe impl<T: AnyBitPattern, const N: usize> AnyBitPattern for [T; N] {}
+
+// copies the first len elements out after checking they lie within the
+// buffer and that the buffer has cpu visible contents at all, private
+// buffers have to go through a readback first
+pub fn buffer_to_vec<T: AnyBitPattern>(
+    buffer: &BufferRef,
+    len: usize,
+) -> Result<Vec<T>, MetalError> {
+    let length = len.checked_mul(size_of::<T>()).ok_or_else(|| {
+        MetalError::BufferRead(format!("{} elements overflow usize", len))
+    })?;
+    if length as u64 > buffer.length() {
+        return Err(MetalError::BufferRead(format!(
+            "{} bytes requested from a {} byte buffer",
+            length,
+            buffer.length()
+        )));
+    }
+    let contents = buffer.contents() as *const T;
+    if contents.is_null() {
+        return Err(MetalError::BufferRead(
+            "buffer has no cpu visible contents".to_string(),
+        ));
+    }
+    // in bounds and any bit pattern is a valid T
+    Ok(unsafe { std::slice::from_raw_parts(contents, len) }.to_vec())
+}
+
+// new_buffer hands back nil instead of failing when the device can't back
+// the allocation, and writing through its contents then crashes
+pub fn allocate_buffer(
+    device: &DeviceRef,
+    length: u64,
+    options: MTLResourceOptions,
+) -> Result<Buffer, MetalError> {
+    let max_length = device.max_buffer_length();
+    if length > max_length {
+        return Err(MetalError::AllocationFailed(format!(
+            "{} bytes exceeds the device limit of {} bytes",
+            length, max_length
+        )));
+    }
+    let buffer = device.new_buffer(length, options);
+    // messaging a nil buffer reads back zero for both of these, private
+    // buffers never have cpu visible contents to check
+    let cpu_visible = !options.contains(MTLResourceOptions::StorageModePrivate);
+    if buffer.length() < length || (cpu_visible && buffer.contents().is_null())
+    {
+        return Err(MetalError::AllocationFailed(format!(
+            "device returned no buffer for {} bytes",
+            length
+        )));
+    }
+    Ok(buffer)
+}
@@ -0,0 +1,51 @@
+use std::ops::{Add, Mul, Sub};
+
+use crate::buffer::AnyBitPattern;
+
+// matches float2 in the shaders, re then im
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32,
+}
+
+// two floats with no padding between them
+unsafe impl AnyBitPattern for Complex {}
+
+impl Complex {
+    pub fn new(re: f32, im: f32) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn abs(self) -> f32 {
+        self.re.hypot(self.im)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
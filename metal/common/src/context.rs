@@ -0,0 +1,148 @@
+use std::mem::size_of_val;
+use std::path::PathBuf;
+
+use log::debug;
+use metal::*;
+
+use crate::buffer::allocate_buffer;
+use crate::device::{DevicePreference, select_device};
+use crate::dispatch::check_completion;
+use crate::error::MetalError;
+
+// metal source compiled at runtime, or a .metallib built ahead of time with
+// `xcrun metal` so the compiler isn't needed on the target machine
+pub enum ShaderSource<'a> {
+    Source(&'a str),
+    Library(PathBuf),
+}
+
+pub struct MetalContext {
+    pub device: Device,
+    pub command_queue: CommandQueue,
+}
+
+impl MetalContext {
+    pub fn new(preference: &DevicePreference) -> Result<Self, MetalError> {
+        let device = select_device(preference)?;
+        let command_queue = device.new_command_queue();
+        debug!("Created command queue on {}", device.name());
+
+        Ok(MetalContext {
+            device,
+            command_queue,
+        })
+    }
+
+    pub fn compile_library(&self, source: &str) -> Result<Library, MetalError> {
+        debug!("Compiling shader library ({} bytes)", source.len());
+        self.device
+            .new_library_with_source(source, &CompileOptions::new())
+            .map_err(MetalError::ShaderCompilation)
+    }
+
+    pub fn load_library(
+        &self,
+        shader_source: &ShaderSource,
+    ) -> Result<Library, MetalError> {
+        match shader_source {
+            ShaderSource::Source(source) => self.compile_library(source),
+            ShaderSource::Library(path) => {
+                debug!("Loading shader library {}", path.display());
+                self.device
+                    .new_library_with_file(path)
+                    .map_err(MetalError::LibraryLoad)
+            }
+        }
+    }
+
+    pub fn make_pipeline(
+        &self,
+        library: &LibraryRef,
+        name: &str,
+    ) -> Result<ComputePipelineState, MetalError> {
+        let function = library
+            .get_function(name, None)
+            .map_err(MetalError::FunctionNotFound)?;
+        let pipeline_state = self
+            .device
+            .new_compute_pipeline_state_with_function(&function)
+            .map_err(MetalError::PipelineCreation)?;
+        debug!(
+            "Created {} pipeline, max {} threads per threadgroup",
+            name,
+            pipeline_state.max_total_threads_per_threadgroup()
+        );
+        Ok(pipeline_state)
+    }
+
+    pub fn make_shared_buffer<T>(
+        &self,
+        data: &[T],
+    ) -> Result<Buffer, MetalError> {
+        let buffer = self.new_buffer(
+            size_of_val(data) as u64,
+            MTLResourceOptions::StorageModeShared,
+        )?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                buffer.contents() as *mut T,
+                data.len(),
+            );
+        }
+        Ok(buffer)
+    }
+
+    pub fn new_buffer(
+        &self,
+        length: u64,
+        options: MTLResourceOptions,
+    ) -> Result<Buffer, MetalError> {
+        allocate_buffer(&self.device, length, options)
+    }
+
+    pub fn make_private_buffer<T>(
+        &self,
+        data: &[T],
+    ) -> Result<Buffer, MetalError> {
+        let staging = self.make_shared_buffer(data)?;
+        let buffer = self.new_buffer(
+            staging.length(),
+            MTLResourceOptions::StorageModePrivate,
+        )?;
+        self.blit_copy(&staging, &buffer)?;
+        Ok(buffer)
+    }
+
+    pub fn readback_buffer(
+        &self,
+        buffer: &BufferRef,
+    ) -> Result<Buffer, MetalError> {
+        let staging = self.new_buffer(
+            buffer.length(),
+            MTLResourceOptions::StorageModeShared,
+        )?;
+        self.blit_copy(buffer, &staging)?;
+        Ok(staging)
+    }
+
+    fn blit_copy(
+        &self,
+        source: &BufferRef,
+        destination: &BufferRef,
+    ) -> Result<(), MetalError> {
+        let command_buffer = self.command_queue.new_command_buffer();
+        let blit_encoder = command_buffer.new_blit_command_encoder();
+        blit_encoder.copy_from_buffer(
+            source,
+            0,
+            destination,
+            0,
+            source.length(),
+        );
+        blit_encoder.end_encoding();
+        command_buffer.commit();
+        command_buffer.wait_until_completed();
+        check_completion(command_buffer)
+    }
+}
@@ -0,0 +1,146 @@
+use std::str::FromStr;
+
+use log::debug;
+use metal::{ComputePipelineStateRef, Device, DeviceRef, MTLGPUFamily};
+
+use crate::error::MetalError;
+
+const GPU_FAMILIES: [MTLGPUFamily; 17] = [
+    MTLGPUFamily::Common1,
+    MTLGPUFamily::Common2,
+    MTLGPUFamily::Common3,
+    MTLGPUFamily::Apple1,
+    MTLGPUFamily::Apple2,
+    MTLGPUFamily::Apple3,
+    MTLGPUFamily::Apple4,
+    MTLGPUFamily::Apple5,
+    MTLGPUFamily::Apple6,
+    MTLGPUFamily::Apple7,
+    MTLGPUFamily::Apple8,
+    MTLGPUFamily::Apple9,
+    MTLGPUFamily::Mac1,
+    MTLGPUFamily::Mac2,
+    MTLGPUFamily::MacCatalyst1,
+    MTLGPUFamily::MacCatalyst2,
+    MTLGPUFamily::Metal3,
+];
+
+const GIB: f64 = (1u64 << 30) as f64;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DevicePreference {
+    #[default]
+    SystemDefault,
+    HighPerformance,
+    LowPower,
+    ByName(String),
+}
+
+impl FromStr for DevicePreference {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "default" => DevicePreference::SystemDefault,
+            "high" | "high-performance" => DevicePreference::HighPerformance,
+            "low" | "low-power" => DevicePreference::LowPower,
+            name => DevicePreference::ByName(name.to_string()),
+        })
+    }
+}
+
+pub fn select_device(
+    preference: &DevicePreference,
+) -> Result<Device, MetalError> {
+    let devices = Device::all();
+    for device in &devices {
+        debug!(
+            "Found device: {}{}",
+            device.name(),
+            if device.is_low_power() {
+                " (low power)"
+            } else {
+                ""
+            }
+        );
+    }
+    debug!("Selecting device by {:?}", preference);
+    let selected = match preference {
+        DevicePreference::SystemDefault => None,
+        DevicePreference::HighPerformance => {
+            devices.into_iter().find(|device| !device.is_low_power())
+        }
+        DevicePreference::LowPower => {
+            devices.into_iter().find(|device| device.is_low_power())
+        }
+        DevicePreference::ByName(name) => {
+            let name = name.to_lowercase();
+            let device = devices
+                .into_iter()
+                .find(|device| device.name().to_lowercase().contains(&name));
+            return device
+                .ok_or_else(|| MetalError::DeviceNotFound(name.clone()));
+        }
+    };
+
+    selected
+        .or_else(Device::system_default)
+        .ok_or(MetalError::NoDevice)
+}
+
+// dispatch_threads relies on the gpu trimming the last threadgroup, which
+// older families can't do; those have to dispatch whole threadgroups and
+// bounds check in the kernel instead
+pub fn supports_non_uniform_threadgroups(device: &DeviceRef) -> bool {
+    device.supports_family(MTLGPUFamily::Apple4)
+        || device.supports_family(MTLGPUFamily::Mac2)
+}
+
+// the encoder takes an oversized length without complaint and the kernel
+// then indexes past the shared memory it got. the pipeline's own
+// threadgroup arrays come out of the same budget, so they count too. checked
+// once while setting up, before any encoder exists, since one dropped
+// without end_encoding is its own crash
+pub fn check_threadgroup_memory(
+    device: &DeviceRef,
+    pipeline: &ComputePipelineStateRef,
+    length: u64,
+) -> Result<u64, MetalError> {
+    let limit = device.max_threadgroup_memory_length();
+    let requested = length + pipeline.static_threadgroup_memory_length();
+    if requested > limit {
+        return Err(MetalError::ThreadgroupMemoryExceeded { requested, limit });
+    }
+    Ok(length)
+}
+
+// the limits that decide whether a workload fits, max_buffer_length caps a
+// single allocation while the working set size is only a recommendation
+pub fn print_device_info(device: &DeviceRef) {
+    let threads = device.max_threads_per_threadgroup();
+    let families = GPU_FAMILIES
+        .iter()
+        .filter(|&&family| device.supports_family(family))
+        .map(|family| format!("{:?}", family))
+        .collect::<Vec<_>>();
+
+    println!("Device: {}", device.name());
+    println!(
+        "  max threads per threadgroup: {}x{}x{}",
+        threads.width, threads.height, threads.depth
+    );
+    println!(
+        "  max threadgroup memory: {} KiB",
+        device.max_threadgroup_memory_length() / 1024
+    );
+    println!(
+        "  max buffer length: {:.2} GiB",
+        device.max_buffer_length() as f64 / GIB
+    );
+    println!(
+        "  recommended max working set size: {:.2} GiB",
+        device.recommended_max_working_set_size() as f64 / GIB
+    );
+    println!("  unified memory: {}", device.has_unified_memory());
+    println!("  gpu families: {}", families.join(", "));
+}
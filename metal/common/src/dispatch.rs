@@ -0,0 +1,79 @@
+use std::ffi::{CStr, c_char, c_void};
+use std::mem::size_of;
+use std::sync::mpsc::{self, Receiver};
+
+use block::ConcreteBlock;
+use metal::objc::runtime::Object;
+use metal::objc::{msg_send, sel, sel_impl};
+use metal::*;
+
+use crate::error::MetalError;
+
+pub struct CompletionToken {
+    command_buffer: CommandBuffer,
+    done: Receiver<()>,
+}
+
+pub fn dispatch_async(
+    command_queue: &CommandQueueRef,
+    encode: impl FnOnce(&ComputeCommandEncoderRef),
+) -> CompletionToken {
+    let command_buffer = command_queue.new_command_buffer().to_owned();
+    let compute_encoder = command_buffer.new_compute_command_encoder();
+    encode(compute_encoder);
+    compute_encoder.end_encoding();
+
+    let (sender, done) = mpsc::channel();
+    let handler = ConcreteBlock::new(move |_: &CommandBufferRef| {
+        let _ = sender.send(());
+    })
+    .copy();
+    command_buffer.add_completed_handler(&handler);
+    command_buffer.commit();
+
+    CompletionToken {
+        command_buffer,
+        done,
+    }
+}
+
+pub fn wait(token: CompletionToken) -> CommandBuffer {
+    let _ = token.done.recv();
+    token.command_buffer
+}
+
+// a gpu fault still completes the wait, it just leaves the command buffer in
+// the error state with an NSError describing what went wrong
+pub fn check_completion(
+    command_buffer: &CommandBufferRef,
+) -> Result<(), MetalError> {
+    let status = command_buffer.status();
+    if status == MTLCommandBufferStatus::Completed {
+        return Ok(());
+    }
+    let description = unsafe {
+        let error: *mut Object = msg_send![command_buffer, error];
+        if error.is_null() {
+            format!("status {:?}", status)
+        } else {
+            let description: *mut Object =
+                msg_send![error, localizedDescription];
+            let bytes: *const c_char = msg_send![description, UTF8String];
+            CStr::from_ptr(bytes).to_string_lossy().into_owned()
+        }
+    };
+    Err(MetalError::CommandBufferFailed(description))
+}
+
+// binds a single u32 argument, counts and modes the kernels take by value
+pub fn set_u32(
+    compute_encoder: &ComputeCommandEncoderRef,
+    index: u64,
+    value: u32,
+) {
+    compute_encoder.set_bytes(
+        index,
+        size_of::<u32>() as u64,
+        &value as *const u32 as *const c_void,
+    );
+}
@@ -0,0 +1,77 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum MetalError {
+    NoDevice,
+    DeviceNotFound(String),
+    ShaderCompilation(String),
+    LibraryLoad(String),
+    FunctionNotFound(String),
+    PipelineCreation(String),
+    MeshLoad(String),
+    ImageWrite(String),
+    TextureLoad(String),
+    AllocationFailed(String),
+    BufferRead(String),
+    ResultsWrite(String),
+    CommandBufferFailed(String),
+    VerificationFailed(String),
+    ThreadgroupMemoryExceeded { requested: u64, limit: u64 },
+}
+
+impl fmt::Display for MetalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetalError::NoDevice => write!(f, "no Metal device found"),
+            MetalError::DeviceNotFound(name) => {
+                write!(f, "no Metal device matching '{}'", name)
+            }
+            MetalError::ShaderCompilation(msg) => {
+                write!(f, "failed to compile shader: {}", msg)
+            }
+            MetalError::LibraryLoad(msg) => {
+                write!(f, "failed to load shader library: {}", msg)
+            }
+            MetalError::FunctionNotFound(msg) => {
+                write!(f, "failed to find shader function: {}", msg)
+            }
+            MetalError::PipelineCreation(msg) => {
+                write!(f, "failed to create pipeline state: {}", msg)
+            }
+            MetalError::MeshLoad(msg) => {
+                write!(f, "failed to load mesh: {}", msg)
+            }
+            MetalError::ImageWrite(msg) => {
+                write!(f, "failed to write image: {}", msg)
+            }
+            MetalError::TextureLoad(msg) => {
+                write!(f, "failed to load texture: {}", msg)
+            }
+            MetalError::AllocationFailed(msg) => {
+                write!(f, "failed to allocate buffer: {}", msg)
+            }
+            MetalError::BufferRead(msg) => {
+                write!(f, "failed to read buffer: {}", msg)
+            }
+            MetalError::ResultsWrite(msg) => {
+                write!(f, "failed to write results: {}", msg)
+            }
+            MetalError::CommandBufferFailed(msg) => {
+                write!(f, "command buffer failed: {}", msg)
+            }
+            MetalError::VerificationFailed(msg) => {
+                write!(f, "verification failed: {}", msg)
+            }
+            MetalError::ThreadgroupMemoryExceeded { requested, limit } => {
+                write!(
+                    f,
+                    "{} bytes of threadgroup memory requested, the device \
+                     allows {}",
+                    requested, limit
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for MetalError {}
@@ -0,0 +1,26 @@
+pub mod args;
+pub mod buffer;
+pub mod complex;
+pub mod context;
+pub mod device;
+pub mod dispatch;
+pub mod error;
+pub mod pattern;
+pub mod semaphore;
+pub mod timing;
+
+pub use args::next_value;
+pub use buffer::{AnyBitPattern, allocate_buffer, buffer_to_vec};
+pub use complex::Complex;
+pub use context::{MetalContext, ShaderSource};
+pub use device::{
+    DevicePreference, check_threadgroup_memory, print_device_info,
+    select_device, supports_non_uniform_threadgroups,
+};
+pub use dispatch::{
+    CompletionToken, check_completion, dispatch_async, set_u32, wait,
+};
+pub use error::MetalError;
+pub use pattern::compute_pattern;
+pub use semaphore::FrameSemaphore;
+pub use timing::{GpuTimer, command_buffer_gpu_time_ms};
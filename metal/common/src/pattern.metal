@@ -0,0 +1,33 @@
+#include <metal_stdlib>
+using namespace metal;
+
+constant uint MAX_ITERATIONS = 256;
+
+kernel void mandelbrot(texture2d<float, access::write> output [[texture(0)]],
+                       uint2 gid [[thread_position_in_grid]])
+{
+    uint width = output.get_width();
+    uint height = output.get_height();
+    // whole threadgroups can run past the edges of the texture
+    if (gid.x >= width || gid.y >= height)
+    {
+        return;
+    }
+
+    float2 c = float2(float(gid.x) / width * 3.0 - 2.25,
+                      float(gid.y) / height * 3.0 - 1.5);
+    float2 z = float2(0.0);
+    uint iteration = 0;
+    for (; iteration < MAX_ITERATIONS && dot(z, z) <= 4.0; iteration++)
+    {
+        z = float2(z.x * z.x - z.y * z.y, 2.0 * z.x * z.y) + c;
+    }
+
+    // escape time picks a color off a cosine palette, points inside the set
+    // never escape and stay black
+    float t = float(iteration) / MAX_ITERATIONS;
+    float3 color = iteration == MAX_ITERATIONS
+        ? float3(0.0)
+        : 0.5 + 0.5 * cos(6.28318 * (4.0 * t + float3(0.0, 0.33, 0.67)));
+    output.write(float4(color, 1.0), gid);
+}
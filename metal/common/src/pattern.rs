@@ -0,0 +1,58 @@
+use log::debug;
+use metal::*;
+
+use crate::context::MetalContext;
+use crate::dispatch::check_completion;
+use crate::error::MetalError;
+
+const PATTERN_PIXEL_FORMAT: MTLPixelFormat = MTLPixelFormat::RGBA8Unorm;
+const TILE_SIZE: u64 = 16;
+
+// renders a mandelbrot into a new texture on the gpu, writable by the kernel
+// and readable by shaders or through srgb views afterwards
+pub fn compute_pattern(
+    context: &MetalContext,
+    width: u32,
+    height: u32,
+) -> Result<Texture, MetalError> {
+    let device = &context.device;
+    let library = context.compile_library(include_str!("pattern.metal"))?;
+    let function = library
+        .get_function("mandelbrot", None)
+        .map_err(MetalError::FunctionNotFound)?;
+    let pipeline_state = device
+        .new_compute_pipeline_state_with_function(&function)
+        .map_err(MetalError::PipelineCreation)?;
+
+    let descriptor = TextureDescriptor::new();
+    descriptor.set_texture_type(MTLTextureType::D2);
+    descriptor.set_pixel_format(PATTERN_PIXEL_FORMAT);
+    descriptor.set_width(width as u64);
+    descriptor.set_height(height as u64);
+    descriptor.set_storage_mode(MTLStorageMode::Private);
+    descriptor.set_usage(
+        MTLTextureUsage::ShaderWrite
+            | MTLTextureUsage::ShaderRead
+            | MTLTextureUsage::PixelFormatView,
+    );
+    let texture = device.new_texture(&descriptor);
+
+    let command_buffer = context.command_queue.new_command_buffer();
+    let compute_encoder = command_buffer.new_compute_command_encoder();
+    compute_encoder.set_compute_pipeline_state(&pipeline_state);
+    compute_encoder.set_texture(0, Some(&texture));
+    let threadgroup_size = MTLSize::new(TILE_SIZE, TILE_SIZE, 1);
+    let threadgroup_count = MTLSize::new(
+        (width as u64).div_ceil(TILE_SIZE),
+        (height as u64).div_ceil(TILE_SIZE),
+        1,
+    );
+    compute_encoder.dispatch_thread_groups(threadgroup_count, threadgroup_size);
+    compute_encoder.end_encoding();
+    command_buffer.commit();
+    command_buffer.wait_until_completed();
+    check_completion(command_buffer)?;
+
+    debug!("Computed {}x{} pattern texture", width, height);
+    Ok(texture)
+}
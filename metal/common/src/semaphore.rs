@@ -0,0 +1,47 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+use block::ConcreteBlock;
+use metal::*;
+
+// counting semaphore the gpu signals from a command buffer's completion
+// handler, the std equivalent of the dispatch_semaphore apple's samples
+// throttle frames in flight with
+#[derive(Clone)]
+pub struct FrameSemaphore {
+    state: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl FrameSemaphore {
+    pub fn new(count: usize) -> Self {
+        FrameSemaphore {
+            state: Arc::new((Mutex::new(count), Condvar::new())),
+        }
+    }
+
+    // blocks until a slot is free and takes it
+    pub fn wait(&self) {
+        let (count, available) = &*self.state;
+        let mut count = count.lock().unwrap();
+        while *count == 0 {
+            count = available.wait(count).unwrap();
+        }
+        *count -= 1;
+    }
+
+    pub fn signal(&self) {
+        let (count, available) = &*self.state;
+        *count.lock().unwrap() += 1;
+        available.notify_one();
+    }
+
+    // gives the slot back once the gpu is done with the command buffer, has
+    // to be added before it is committed
+    pub fn signal_on_completion(&self, command_buffer: &CommandBufferRef) {
+        let semaphore = self.clone();
+        let handler = ConcreteBlock::new(move |_: &CommandBufferRef| {
+            semaphore.signal();
+        })
+        .copy();
+        command_buffer.add_completed_handler(&handler);
+    }
+}
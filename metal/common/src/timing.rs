@@ -0,0 +1,110 @@
+use metal::objc::runtime::Object;
+use metal::objc::{msg_send, sel, sel_impl};
+use metal::*;
+
+const TIMESTAMP_COUNTER_SET: &str = "timestamp";
+
+pub struct GpuTimer {
+    device: Device,
+    sample_buffer: Option<CounterSampleBuffer>,
+    calibration: (u64, u64),
+    last_gpu_time_ms: f64,
+}
+
+impl GpuTimer {
+    pub fn new(device: &DeviceRef) -> Self {
+        GpuTimer {
+            device: device.to_owned(),
+            sample_buffer: new_timestamp_sample_buffer(device),
+            calibration: (0, 0),
+            last_gpu_time_ms: 0.0,
+        }
+    }
+
+    pub fn uses_counters(&self) -> bool {
+        self.sample_buffer.is_some()
+    }
+
+    pub fn begin(&mut self, encoder: &ComputeCommandEncoderRef) {
+        if let Some(sample_buffer) = &self.sample_buffer {
+            let (mut cpu, mut gpu) = (0, 0);
+            self.device.sample_timestamps(&mut cpu, &mut gpu);
+            self.calibration = (cpu, gpu);
+            encoder.sample_counters_in_buffer(sample_buffer, 0, true);
+        }
+    }
+
+    pub fn end(&self, encoder: &ComputeCommandEncoderRef) {
+        if let Some(sample_buffer) = &self.sample_buffer {
+            encoder.sample_counters_in_buffer(sample_buffer, 1, true);
+        }
+    }
+
+    pub fn resolve(&mut self, command_buffer: &CommandBufferRef) {
+        self.last_gpu_time_ms = match &self.sample_buffer {
+            Some(sample_buffer) => {
+                let [start, end] = resolve_timestamps(sample_buffer);
+                let (mut cpu, mut gpu) = (0, 0);
+                self.device.sample_timestamps(&mut cpu, &mut gpu);
+                let cpu_span = cpu.saturating_sub(self.calibration.0) as f64;
+                let gpu_span = gpu.saturating_sub(self.calibration.1) as f64;
+                let ticks = end.saturating_sub(start) as f64;
+                if gpu_span > 0.0 {
+                    ticks * cpu_span / gpu_span / 1_000_000.0
+                } else {
+                    command_buffer_gpu_time_ms(command_buffer)
+                }
+            }
+            None => command_buffer_gpu_time_ms(command_buffer),
+        };
+    }
+
+    pub fn last_gpu_time_ms(&self) -> f64 {
+        self.last_gpu_time_ms
+    }
+}
+
+pub fn command_buffer_gpu_time_ms(command_buffer: &CommandBufferRef) -> f64 {
+    let (start, end): (f64, f64) = unsafe {
+        (
+            msg_send![command_buffer, GPUStartTime],
+            msg_send![command_buffer, GPUEndTime],
+        )
+    };
+    (end - start) * 1000.0
+}
+
+fn new_timestamp_sample_buffer(
+    device: &DeviceRef,
+) -> Option<CounterSampleBuffer> {
+    if !device
+        .supports_counter_sampling(MTLCounterSamplingPoint::AtDispatchBoundary)
+    {
+        return None;
+    }
+
+    let counter_set = device
+        .counter_sets()
+        .into_iter()
+        .find(|set| set.name() == TIMESTAMP_COUNTER_SET)?;
+
+    let descriptor = CounterSampleBufferDescriptor::new();
+    descriptor.set_counter_set(&counter_set);
+    descriptor.set_sample_count(2);
+    descriptor.set_storage_mode(MTLStorageMode::Shared);
+    device
+        .new_counter_sample_buffer_with_descriptor(&descriptor)
+        .ok()
+}
+
+fn resolve_timestamps(sample_buffer: &CounterSampleBufferRef) -> [u64; 2] {
+    unsafe {
+        let data: *mut Object =
+            msg_send![sample_buffer, resolveCounterRange: NSRange::new(0, 2)];
+        if data.is_null() {
+            return [0, 0];
+        }
+        let bytes: *const u64 = msg_send![data, bytes];
+        [*bytes, *bytes.add(1)]
+    }
+}
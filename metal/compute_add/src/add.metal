@@ -1,7 +1,278 @@
-kernel void add_arrays(device const float* inA,
-                       device const float* inB,
-                       device float* result,
-                       uint index [[thread_position_in_grid]])
+#include <metal_stdlib>
+using namespace metal;
+
+enum BinaryOp : uint
 {
-    result[index] = inA[index] + inB[index];
+    BinaryOpAdd = 0,
+    BinaryOpSub = 1,
+    BinaryOpMul = 2,
+    BinaryOpDiv = 3,
+    BinaryOpMax = 4,
+    BinaryOpMin = 5,
+};
+
+enum UnaryOp : uint
+{
+    UnaryOpAbs = 0,
+    UnaryOpSqrt = 1,
+    UnaryOpExp = 2,
+    UnaryOpSin = 3,
+    UnaryOpNeg = 4,
+};
+
+struct ClampRange
+{
+    float min;
+    float max;
+};
+
+kernel void binary_op(device const float* inA,
+                      device const float* inB,
+                      device float* result,
+                      constant uint& op,
+                      constant uint& length,
+                      uint index [[thread_position_in_grid]])
+{
+    // whole threadgroup dispatches can round the grid past the buffers
+    if (index >= length)
+    {
+        return;
+    }
+
+    float a = inA[index];
+    float b = inB[index];
+
+    switch (op)
+    {
+        case BinaryOpAdd: result[index] = a + b; break;
+        case BinaryOpSub: result[index] = a - b; break;
+        case BinaryOpMul: result[index] = a * b; break;
+        case BinaryOpDiv: result[index] = a / b; break;
+        case BinaryOpMax: result[index] = max(a, b); break;
+        case BinaryOpMin: result[index] = min(a, b); break;
+    }
+}
+
+kernel void binary_op_half(device const half* inA,
+                           device const half* inB,
+                           device half* result,
+                           constant uint& op,
+                           constant uint& length,
+                           uint index [[thread_position_in_grid]])
+{
+    if (index >= length)
+    {
+        return;
+    }
+
+    half a = inA[index];
+    half b = inB[index];
+
+    switch (op)
+    {
+        case BinaryOpAdd: result[index] = a + b; break;
+        case BinaryOpSub: result[index] = a - b; break;
+        case BinaryOpMul: result[index] = a * b; break;
+        case BinaryOpDiv: result[index] = a / b; break;
+        case BinaryOpMax: result[index] = max(a, b); break;
+        case BinaryOpMin: result[index] = min(a, b); break;
+    }
+}
+
+kernel void unary_op(device const float* input,
+                     device float* result,
+                     constant uint& op,
+                     constant uint& length,
+                     uint index [[thread_position_in_grid]])
+{
+    if (index >= length)
+    {
+        return;
+    }
+
+    float x = input[index];
+
+    switch (op)
+    {
+        case UnaryOpAbs: result[index] = abs(x); break;
+        case UnaryOpSqrt: result[index] = sqrt(x); break;
+        case UnaryOpExp: result[index] = exp(x); break;
+        case UnaryOpSin: result[index] = sin(x); break;
+        case UnaryOpNeg: result[index] = -x; break;
+    }
+}
+
+kernel void clamp_values(device float* values,
+                         constant ClampRange& range,
+                         constant uint& length,
+                         uint index [[thread_position_in_grid]])
+{
+    if (index >= length)
+    {
+        return;
+    }
+
+    values[index] = clamp(values[index], range.min, range.max);
+}
+
+kernel void clamp_values_half(device half* values,
+                              constant ClampRange& range,
+                              constant uint& length,
+                              uint index [[thread_position_in_grid]])
+{
+    if (index >= length)
+    {
+        return;
+    }
+
+    values[index] = clamp(values[index], half(range.min), half(range.max));
+}
+
+// the three operands reached through one argument buffer instead of three
+// separate buffer bindings
+struct Operands
+{
+    device const float* a [[id(0)]];
+    device const float* b [[id(1)]];
+    device float* result [[id(2)]];
+};
+
+struct OperandsHalf
+{
+    device const half* a [[id(0)]];
+    device const half* b [[id(1)]];
+    device half* result [[id(2)]];
+};
+
+kernel void binary_op_arguments(device const Operands& operands,
+                                constant uint& op,
+                                constant uint& length,
+                                uint index [[thread_position_in_grid]])
+{
+    if (index >= length)
+    {
+        return;
+    }
+
+    float a = operands.a[index];
+    float b = operands.b[index];
+
+    switch (op)
+    {
+        case BinaryOpAdd: operands.result[index] = a + b; break;
+        case BinaryOpSub: operands.result[index] = a - b; break;
+        case BinaryOpMul: operands.result[index] = a * b; break;
+        case BinaryOpDiv: operands.result[index] = a / b; break;
+        case BinaryOpMax: operands.result[index] = max(a, b); break;
+        case BinaryOpMin: operands.result[index] = min(a, b); break;
+    }
+}
+
+kernel void binary_op_arguments_half(device const OperandsHalf& operands,
+                                     constant uint& op,
+                                     constant uint& length,
+                                     uint index [[thread_position_in_grid]])
+{
+    if (index >= length)
+    {
+        return;
+    }
+
+    half a = operands.a[index];
+    half b = operands.b[index];
+
+    switch (op)
+    {
+        case BinaryOpAdd: operands.result[index] = a + b; break;
+        case BinaryOpSub: operands.result[index] = a - b; break;
+        case BinaryOpMul: operands.result[index] = a * b; break;
+        case BinaryOpDiv: operands.result[index] = a / b; break;
+        case BinaryOpMax: operands.result[index] = max(a, b); break;
+        case BinaryOpMin: operands.result[index] = min(a, b); break;
+    }
+}
+
+// the same op over an array of structs, each thread reads and writes one
+// element so its loads and store share a cache line with its neighbors'
+struct Element
+{
+    float a;
+    float b;
+    float result;
+};
+
+struct ElementHalf
+{
+    half a;
+    half b;
+    half result;
+};
+
+kernel void binary_op_aos(device Element* elements,
+                          constant uint& op,
+                          constant uint& length,
+                          uint index [[thread_position_in_grid]])
+{
+    if (index >= length)
+    {
+        return;
+    }
+
+    float a = elements[index].a;
+    float b = elements[index].b;
+
+    switch (op)
+    {
+        case BinaryOpAdd: elements[index].result = a + b; break;
+        case BinaryOpSub: elements[index].result = a - b; break;
+        case BinaryOpMul: elements[index].result = a * b; break;
+        case BinaryOpDiv: elements[index].result = a / b; break;
+        case BinaryOpMax: elements[index].result = max(a, b); break;
+        case BinaryOpMin: elements[index].result = min(a, b); break;
+    }
+}
+
+kernel void binary_op_aos_half(device ElementHalf* elements,
+                               constant uint& op,
+                               constant uint& length,
+                               uint index [[thread_position_in_grid]])
+{
+    if (index >= length)
+    {
+        return;
+    }
+
+    half a = elements[index].a;
+    half b = elements[index].b;
+
+    switch (op)
+    {
+        case BinaryOpAdd: elements[index].result = a + b; break;
+        case BinaryOpSub: elements[index].result = a - b; break;
+        case BinaryOpMul: elements[index].result = a * b; break;
+        case BinaryOpDiv: elements[index].result = a / b; break;
+        case BinaryOpMax: elements[index].result = max(a, b); break;
+        case BinaryOpMin: elements[index].result = min(a, b); break;
+    }
+}
+
+// a and b hold interleaved (re, im) pairs, the product is split into two
+// planar outputs so both can be read back as plain float arrays
+kernel void complex_mul(device const float2* inA,
+                        device const float2* inB,
+                        device float* resultRe,
+                        device float* resultIm,
+                        constant uint& length,
+                        uint index [[thread_position_in_grid]])
+{
+    if (index >= length)
+    {
+        return;
+    }
+
+    float2 a = inA[index];
+    float2 b = inB[index];
+
+    resultRe[index] = a.x * b.x - a.y * b.y;
+    resultIm[index] = a.x * b.y + a.y * b.x;
 }
@@ -0,0 +1,196 @@
+use std::path::PathBuf;
+
+use metal_common::{DevicePreference, next_value};
+
+use crate::dtype::Dtype;
+use crate::export::ExportFormat;
+use crate::layout::Layout;
+use crate::ops::{BinaryOp, ClampRange, UnaryOp};
+
+pub const USAGE: &str = "usage: compute_add \
+    [--device default|high|low|<name>] [--op add|sub|mul|div|max|min] \
+    [--warmup] [--len N] [--private] [--async N] [--batch] \
+    [--headless-ok] [--dtype f32|f16] [--iters N] [--tg N] \
+    [--metallib add.metallib] [--clamp min,max] [--storage-bench] [--info] \
+    [--complex] [--seed N] [--layout soa|aos] [--argument-buffer] \
+    [--out results.csv|results.bin] [--max-mismatches N] \
+    [--map abs|sqrt|exp|sin|neg]";
+
+const DEFAULT_ARRAY_LENGTH: usize = 1024;
+const DEFAULT_MAX_MISMATCHES: usize = 10;
+
+pub struct Args {
+    pub device: DevicePreference,
+    pub op: BinaryOp,
+    pub warmup: bool,
+    pub array_length: usize,
+    pub private: bool,
+    pub async_dispatches: usize,
+    pub batch: bool,
+    pub headless_ok: bool,
+    pub dtype: Dtype,
+    pub iters: usize,
+    pub threadgroup_width: Option<u64>,
+    pub metallib: Option<PathBuf>,
+    pub clamp: Option<ClampRange>,
+    pub storage_bench: bool,
+    pub info: bool,
+    pub complex: bool,
+    pub seed: Option<u64>,
+    pub layout: Layout,
+    pub argument_buffer: bool,
+    pub out: Option<PathBuf>,
+    // mismatching results printed before only counting the rest
+    pub max_mismatches: usize,
+    pub map: Option<UnaryOp>,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            device: DevicePreference::default(),
+            op: BinaryOp::default(),
+            warmup: false,
+            array_length: DEFAULT_ARRAY_LENGTH,
+            private: false,
+            async_dispatches: 0,
+            batch: false,
+            headless_ok: false,
+            dtype: Dtype::default(),
+            iters: 0,
+            threadgroup_width: None,
+            metallib: None,
+            clamp: None,
+            storage_bench: false,
+            info: false,
+            complex: false,
+            seed: None,
+            layout: Layout::default(),
+            argument_buffer: false,
+            out: None,
+            max_mismatches: DEFAULT_MAX_MISMATCHES,
+            map: None,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from(
+        iter: impl IntoIterator<Item = String>,
+    ) -> Result<Self, String> {
+        let mut args = Args::default();
+        let mut iter = iter.into_iter();
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--device" => {
+                    let Ok(device) = next_value(&mut iter, &arg)?.parse();
+                    args.device = device;
+                }
+                "--op" => args.op = next_value(&mut iter, &arg)?.parse()?,
+                "--map" => {
+                    args.map = Some(next_value(&mut iter, &arg)?.parse()?)
+                }
+                "--dtype" => {
+                    args.dtype = next_value(&mut iter, &arg)?.parse()?
+                }
+                "--layout" => {
+                    args.layout = next_value(&mut iter, &arg)?.parse()?
+                }
+                "--warmup" => args.warmup = true,
+                "--private" => args.private = true,
+                "--batch" => args.batch = true,
+                "--headless-ok" => args.headless_ok = true,
+                "--storage-bench" => args.storage_bench = true,
+                "--info" => args.info = true,
+                "--complex" => args.complex = true,
+                "--argument-buffer" => args.argument_buffer = true,
+                "--len" => {
+                    args.array_length = next_value(&mut iter, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|&len| len > 0)
+                        .ok_or("--len must be a positive integer")?;
+                }
+                "--async" => {
+                    args.async_dispatches =
+                        next_value(&mut iter, &arg)?.parse().map_err(
+                            |_| "--async must be a non-negative integer",
+                        )?;
+                }
+                "--clamp" => {
+                    args.clamp = Some(next_value(&mut iter, &arg)?.parse()?)
+                }
+                "--metallib" => {
+                    args.metallib =
+                        Some(PathBuf::from(next_value(&mut iter, &arg)?));
+                }
+                "--out" => {
+                    let path = PathBuf::from(next_value(&mut iter, &arg)?);
+                    if ExportFormat::from_path(&path).is_none() {
+                        return Err(
+                            "--out must end in .csv or .bin".to_string()
+                        );
+                    }
+                    args.out = Some(path);
+                }
+                "--tg" => {
+                    args.threadgroup_width = Some(
+                        next_value(&mut iter, &arg)?
+                            .parse()
+                            .ok()
+                            .filter(|&width| width > 0)
+                            .ok_or("--tg must be a positive integer")?,
+                    );
+                }
+                "--seed" => {
+                    args.seed = Some(
+                        next_value(&mut iter, &arg)?
+                            .parse()
+                            .map_err(|_| "--seed must be a u64")?,
+                    );
+                }
+                "--max-mismatches" => {
+                    args.max_mismatches =
+                        next_value(&mut iter, &arg)?.parse().map_err(|_| {
+                            "--max-mismatches must be a non-negative integer"
+                        })?;
+                }
+                "--iters" => {
+                    args.iters = next_value(&mut iter, &arg)?.parse().map_err(
+                        |_| "--iters must be a non-negative integer",
+                    )?;
+                }
+                other => return Err(format!("unknown argument: {}", other)),
+            }
+        }
+
+        // the aos path only runs and verifies the op itself
+        if args.layout == Layout::Aos
+            && (args.clamp.is_some()
+                || args.batch
+                || args.private
+                || args.storage_bench
+                || args.complex
+                || args.map.is_some()
+                || args.argument_buffer)
+        {
+            return Err("--layout aos doesn't combine with --clamp, --batch, \
+                 --private, --storage-bench, --complex, --map or \
+                 --argument-buffer"
+                .to_string());
+        }
+        // a batch binds its operands one by one for each op it adds
+        if args.argument_buffer && args.batch {
+            return Err(
+                "--argument-buffer doesn't combine with --batch".to_string()
+            );
+        }
+
+        Ok(args)
+    }
+}
@@ -0,0 +1,51 @@
+use log::info;
+use metal::*;
+use metal_common::{MetalContext, MetalError};
+
+// a, b and result encoded once into an argument buffer, so a dispatch binds
+// one buffer instead of three; ids match the Operands struct in add.metal
+pub struct OperandArguments {
+    buffer: Buffer,
+    // the gpu only sees these through the argument buffer, so every encoder
+    // has to declare them resident itself
+    resources: [(Buffer, MTLResourceUsage); 3],
+}
+
+impl OperandArguments {
+    pub fn new(
+        context: &MetalContext,
+        function: &FunctionRef,
+        a: &BufferRef,
+        b: &BufferRef,
+        result: &BufferRef,
+    ) -> Result<Self, MetalError> {
+        let tier = context.device.argument_buffers_support();
+        info!("Argument buffers: {:?}", tier);
+
+        let encoder = function.new_argument_encoder(0);
+        let buffer = context.new_buffer(
+            encoder.encoded_length(),
+            MTLResourceOptions::StorageModeShared,
+        )?;
+        encoder.set_argument_buffer(&buffer, 0);
+        encoder.set_buffer(0, a, 0);
+        encoder.set_buffer(1, b, 0);
+        encoder.set_buffer(2, result, 0);
+
+        Ok(OperandArguments {
+            buffer,
+            resources: [
+                (a.to_owned(), MTLResourceUsage::Read),
+                (b.to_owned(), MTLResourceUsage::Read),
+                (result.to_owned(), MTLResourceUsage::Write),
+            ],
+        })
+    }
+
+    pub fn bind(&self, compute_encoder: &ComputeCommandEncoderRef, index: u64) {
+        compute_encoder.set_buffer(index, Some(&self.buffer), 0);
+        for (resource, usage) in &self.resources {
+            compute_encoder.use_resource(resource, *usage);
+        }
+    }
+}
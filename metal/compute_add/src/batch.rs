@@ -0,0 +1,389 @@
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem::size_of;
+
+use log::{debug, warn};
+use metal::*;
+use metal_common::{
+    MetalError, check_completion, set_u32, supports_non_uniform_threadgroups,
+};
+
+use crate::arguments::OperandArguments;
+use crate::ops::{BinaryOp, ClampRange, UnaryOp};
+
+// the elements to cover and optionally a threadgroup width to cover them
+// with instead of the one derived from the pipeline
+#[derive(Clone, Copy)]
+pub struct Dispatch {
+    pub length: usize,
+    pub threadgroup_width: Option<u64>,
+    pub non_uniform_threadgroups: bool,
+}
+
+// what dispatch_elements encoded: size is the x extent handed to the
+// encoder, counted in threadgroups or in threads depending on how it was
+// dispatched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DispatchGrid {
+    pub threadgroup_width: u64,
+    pub size: u64,
+    pub whole_threadgroups: bool,
+}
+
+impl DispatchGrid {
+    pub fn launched_threads(self) -> u64 {
+        if self.whole_threadgroups {
+            self.size * self.threadgroup_width
+        } else {
+            self.size
+        }
+    }
+}
+
+pub struct ComputeBatch<'a, T = f32> {
+    pipeline_state: &'a ComputePipelineStateRef,
+    command_buffer: &'a CommandBufferRef,
+    compute_encoder: &'a ComputeCommandEncoderRef,
+    non_uniform_threadgroups: bool,
+    threadgroup_width: Option<u64>,
+    element: PhantomData<T>,
+}
+
+impl<'a, T> ComputeBatch<'a, T> {
+    pub fn new(
+        command_queue: &'a CommandQueueRef,
+        pipeline_state: &'a ComputePipelineStateRef,
+    ) -> Self {
+        let command_buffer = command_queue.new_command_buffer();
+        let compute_encoder = command_buffer.new_compute_command_encoder();
+        ComputeBatch {
+            pipeline_state,
+            command_buffer,
+            compute_encoder,
+            non_uniform_threadgroups: supports_non_uniform_threadgroups(
+                command_queue.device(),
+            ),
+            threadgroup_width: None,
+            element: PhantomData,
+        }
+    }
+
+    // every op added afterwards is dispatched with this width, capped by
+    // the pipeline like a single dispatch's
+    pub fn with_threadgroup_width(
+        mut self,
+        threadgroup_width: Option<u64>,
+    ) -> Self {
+        self.threadgroup_width = threadgroup_width;
+        self
+    }
+
+    pub fn add(
+        &mut self,
+        op: BinaryOp,
+        a: &BufferRef,
+        b: &BufferRef,
+        out: &BufferRef,
+    ) -> &mut Self {
+        let length = out.length() as usize / size_of::<T>();
+        encode_binary_op(
+            self.compute_encoder,
+            self.pipeline_state,
+            a,
+            b,
+            out,
+            Dispatch {
+                length,
+                threadgroup_width: self.threadgroup_width,
+                non_uniform_threadgroups: self.non_uniform_threadgroups,
+            },
+            op,
+        );
+        self
+    }
+
+    pub fn submit(self) -> Result<(), MetalError> {
+        self.compute_encoder.end_encoding();
+        self.command_buffer.commit();
+        self.command_buffer.wait_until_completed();
+        check_completion(self.command_buffer)
+    }
+}
+
+pub fn encode_binary_op(
+    compute_encoder: &ComputeCommandEncoderRef,
+    pipeline_state: &ComputePipelineStateRef,
+    buffer_a: &BufferRef,
+    buffer_b: &BufferRef,
+    result_buffer: &BufferRef,
+    dispatch: Dispatch,
+    op: BinaryOp,
+) -> DispatchGrid {
+    compute_encoder.set_compute_pipeline_state(pipeline_state);
+    compute_encoder.set_buffer(0, Some(buffer_a), 0);
+    compute_encoder.set_buffer(1, Some(buffer_b), 0);
+    compute_encoder.set_buffer(2, Some(result_buffer), 0);
+    set_u32(compute_encoder, 3, op as u32);
+    dispatch_elements(compute_encoder, pipeline_state, dispatch, 4)
+}
+
+pub fn encode_unary_op(
+    compute_encoder: &ComputeCommandEncoderRef,
+    pipeline_state: &ComputePipelineStateRef,
+    input: &BufferRef,
+    result_buffer: &BufferRef,
+    dispatch: Dispatch,
+    op: UnaryOp,
+) -> DispatchGrid {
+    compute_encoder.set_compute_pipeline_state(pipeline_state);
+    compute_encoder.set_buffer(0, Some(input), 0);
+    compute_encoder.set_buffer(1, Some(result_buffer), 0);
+    set_u32(compute_encoder, 2, op as u32);
+    dispatch_elements(compute_encoder, pipeline_state, dispatch, 3)
+}
+
+pub fn encode_binary_op_arguments(
+    compute_encoder: &ComputeCommandEncoderRef,
+    pipeline_state: &ComputePipelineStateRef,
+    operands: &OperandArguments,
+    dispatch: Dispatch,
+    op: BinaryOp,
+) -> DispatchGrid {
+    compute_encoder.set_compute_pipeline_state(pipeline_state);
+    operands.bind(compute_encoder, 0);
+    set_u32(compute_encoder, 1, op as u32);
+    dispatch_elements(compute_encoder, pipeline_state, dispatch, 2)
+}
+
+// runs after the op in the same encoder, which orders the two dispatches
+pub fn encode_clamp(
+    compute_encoder: &ComputeCommandEncoderRef,
+    pipeline_state: &ComputePipelineStateRef,
+    values: &BufferRef,
+    dispatch: Dispatch,
+    range: ClampRange,
+) -> DispatchGrid {
+    compute_encoder.set_compute_pipeline_state(pipeline_state);
+    compute_encoder.set_buffer(0, Some(values), 0);
+    compute_encoder.set_bytes(
+        1,
+        size_of::<ClampRange>() as u64,
+        &range as *const ClampRange as *const c_void,
+    );
+    dispatch_elements(compute_encoder, pipeline_state, dispatch, 2)
+}
+
+// reads a and b from and writes the result into the same elements
+pub fn encode_binary_op_aos(
+    compute_encoder: &ComputeCommandEncoderRef,
+    pipeline_state: &ComputePipelineStateRef,
+    elements: &BufferRef,
+    dispatch: Dispatch,
+    op: BinaryOp,
+) -> DispatchGrid {
+    compute_encoder.set_compute_pipeline_state(pipeline_state);
+    compute_encoder.set_buffer(0, Some(elements), 0);
+    set_u32(compute_encoder, 1, op as u32);
+    dispatch_elements(compute_encoder, pipeline_state, dispatch, 2)
+}
+
+pub fn encode_complex_mul(
+    compute_encoder: &ComputeCommandEncoderRef,
+    pipeline_state: &ComputePipelineStateRef,
+    a: &BufferRef,
+    b: &BufferRef,
+    result_re: &BufferRef,
+    result_im: &BufferRef,
+    dispatch: Dispatch,
+) -> DispatchGrid {
+    compute_encoder.set_compute_pipeline_state(pipeline_state);
+    compute_encoder.set_buffer(0, Some(a), 0);
+    compute_encoder.set_buffer(1, Some(b), 0);
+    compute_encoder.set_buffer(2, Some(result_re), 0);
+    compute_encoder.set_buffer(3, Some(result_im), 0);
+    dispatch_elements(compute_encoder, pipeline_state, dispatch, 4)
+}
+
+// binds the element count at length_index for the kernel's bounds check
+// and hands back the grid it encoded
+fn dispatch_elements(
+    compute_encoder: &ComputeCommandEncoderRef,
+    pipeline_state: &ComputePipelineStateRef,
+    dispatch: Dispatch,
+    length_index: u64,
+) -> DispatchGrid {
+    set_u32(compute_encoder, length_index, dispatch.length as u32);
+
+    let grid = dispatch_grid(
+        pipeline_state.max_total_threads_per_threadgroup(),
+        dispatch,
+    );
+    let threadgroup_size = MTLSize {
+        width: grid.threadgroup_width,
+        height: 1,
+        depth: 1,
+    };
+    let size = MTLSize {
+        width: grid.size,
+        height: 1,
+        depth: 1,
+    };
+    if grid.whole_threadgroups {
+        // the kernel skips the threads past length
+        compute_encoder.dispatch_thread_groups(size, threadgroup_size);
+    } else {
+        // dispatch_threads sizes the grid in threads rather than
+        // threadgroups, so when length isn't a multiple of the threadgroup
+        // width the last group is trimmed instead of running past the end
+        // of the buffers
+        compute_encoder.dispatch_threads(size, threadgroup_size);
+    }
+    grid
+}
+
+fn dispatch_grid(max_width: u64, dispatch: Dispatch) -> DispatchGrid {
+    let length = dispatch.length as u64;
+    let width = threadgroup_width(max_width, dispatch);
+    if whole_threadgroups(dispatch, width) {
+        DispatchGrid {
+            threadgroup_width: width,
+            size: length.div_ceil(width),
+            whole_threadgroups: true,
+        }
+    } else {
+        DispatchGrid {
+            threadgroup_width: width,
+            size: length,
+            whole_threadgroups: false,
+        }
+    }
+}
+
+// without non-uniform support, or when an explicit width is meant to be
+// measured as is, the grid is rounded up to whole threadgroups
+fn whole_threadgroups(dispatch: Dispatch, width: u64) -> bool {
+    let uneven_override = dispatch.threadgroup_width.is_some()
+        && !(dispatch.length as u64).is_multiple_of(width);
+    !dispatch.non_uniform_threadgroups || uneven_override
+}
+
+// elements no thread writes: past the launched grid, or past what the u32
+// length the kernels compare their index against can reach
+fn uncovered_elements(length: u64, launched: u64) -> u64 {
+    let reachable = launched.min(u32::MAX as u64 + 1);
+    length.saturating_sub(reachable)
+}
+
+// takes the grid a dispatch actually encoded, checked once up front rather
+// than on every dispatch since the grid is the same for all of them.
+// returns the elements left unwritten
+pub fn validate_coverage(length: usize, grid: DispatchGrid) -> u64 {
+    let length = length as u64;
+    let launched = grid.launched_threads();
+    let uncovered = uncovered_elements(length, launched);
+    if uncovered > 0 {
+        warn!(
+            "Dispatch of {} threads leaves {} of {} elements unwritten",
+            launched, uncovered, length
+        );
+    } else {
+        debug!(
+            "Dispatch of {} threads in groups of {} covers {} elements",
+            launched, grid.threadgroup_width, length
+        );
+    }
+    uncovered
+}
+
+// a requested width only has to fit the pipeline, the derived one also
+// stays within the grid
+fn threadgroup_width(max_width: u64, dispatch: Dispatch) -> u64 {
+    match dispatch.threadgroup_width {
+        Some(requested) => requested.min(max_width),
+        None => max_width.min(dispatch.length as u64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(length: usize, threadgroup_width: Option<u64>) -> Dispatch {
+        Dispatch {
+            length,
+            threadgroup_width,
+            non_uniform_threadgroups: true,
+        }
+    }
+
+    #[test]
+    fn whole_threadgroups_round_up_past_length() {
+        let uniform_only = Dispatch {
+            non_uniform_threadgroups: false,
+            ..request(1000, None)
+        };
+        let grid = dispatch_grid(256, uniform_only);
+        assert_eq!(grid.size, 4);
+        assert_eq!(grid.launched_threads(), 1024);
+        // an uneven requested width is measured as is
+        let grid = dispatch_grid(1024, request(1000, Some(256)));
+        assert!(grid.whole_threadgroups);
+        assert_eq!(grid.launched_threads(), 1024);
+        let grid = dispatch_grid(256, request(1000, None));
+        assert!(!grid.whole_threadgroups);
+        assert_eq!(grid.launched_threads(), 1000);
+    }
+
+    #[test]
+    fn requested_width_is_capped_by_the_pipeline() {
+        let grid = dispatch_grid(512, request(4096, Some(1024)));
+        assert_eq!(grid.threadgroup_width, 512);
+        let grid = dispatch_grid(512, request(100, None));
+        assert_eq!(grid.threadgroup_width, 100);
+    }
+
+    #[test]
+    fn planned_grids_cover_every_length() {
+        for length in [1, 2, 255, 256, 257, 1000, 65537] {
+            for requested in [None, Some(1), Some(32), Some(1024)] {
+                for non_uniform_threadgroups in [false, true] {
+                    let dispatch = Dispatch {
+                        non_uniform_threadgroups,
+                        ..request(length, requested)
+                    };
+                    let grid = dispatch_grid(1024, dispatch);
+                    assert_eq!(validate_coverage(length, grid), 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_short_grid_is_reported() {
+        let grid = DispatchGrid {
+            threadgroup_width: 256,
+            size: 3,
+            whole_threadgroups: true,
+        };
+        assert_eq!(validate_coverage(1000, grid), 232);
+        let grid = DispatchGrid {
+            threadgroup_width: 256,
+            size: 999,
+            whole_threadgroups: false,
+        };
+        assert_eq!(validate_coverage(1000, grid), 1);
+    }
+
+    #[test]
+    fn every_element_is_covered_by_a_full_grid() {
+        assert_eq!(uncovered_elements(1000, 1024), 0);
+        assert_eq!(uncovered_elements(1000, 1000), 0);
+        assert_eq!(uncovered_elements(1000, 768), 232);
+    }
+
+    #[test]
+    fn lengths_past_u32_are_not_covered() {
+        let length = u32::MAX as u64 + 11;
+        assert_eq!(uncovered_elements(length, length), 10);
+    }
+}
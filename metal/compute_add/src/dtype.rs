@@ -0,0 +1,70 @@
+use std::str::FromStr;
+
+use half::f16;
+use metal_common::AnyBitPattern;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dtype {
+    #[default]
+    F32,
+    F16,
+}
+
+impl FromStr for Dtype {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "f32" => Ok(Dtype::F32),
+            "f16" => Ok(Dtype::F16),
+            other => Err(format!("unknown dtype: {}", other)),
+        }
+    }
+}
+
+// results are read straight out of gpu buffers through buffer_to_vec
+pub trait Element: AnyBitPattern {
+    const KERNEL: &'static str;
+    const CLAMP_KERNEL: &'static str;
+    const AOS_KERNEL: &'static str;
+    const ARGUMENTS_KERNEL: &'static str;
+    // relative error allowed when checking gpu results against the cpu
+    const TOLERANCE: f32;
+
+    fn from_f32(value: f32) -> Self;
+    fn to_f32(self) -> f32;
+}
+
+impl Element for f32 {
+    const KERNEL: &'static str = "binary_op";
+    const CLAMP_KERNEL: &'static str = "clamp_values";
+    const AOS_KERNEL: &'static str = "binary_op_aos";
+    const ARGUMENTS_KERNEL: &'static str = "binary_op_arguments";
+    const TOLERANCE: f32 = 0.000001;
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+impl Element for f16 {
+    const KERNEL: &'static str = "binary_op_half";
+    const CLAMP_KERNEL: &'static str = "clamp_values_half";
+    const AOS_KERNEL: &'static str = "binary_op_aos_half";
+    const ARGUMENTS_KERNEL: &'static str = "binary_op_arguments_half";
+    // half has an 11 bit significand, so one ulp is ~0.001 relative; allow
+    // two to cover rounding the result and the gpu's approximate division
+    const TOLERANCE: f32 = 0.002;
+
+    fn from_f32(value: f32) -> Self {
+        f16::from_f32(value)
+    }
+
+    fn to_f32(self) -> f32 {
+        f16::to_f32(self)
+    }
+}
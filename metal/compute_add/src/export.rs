@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use metal_common::MetalError;
+
+use crate::dtype::Element;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Binary,
+}
+
+impl ExportFormat {
+    // picked by extension so `--out` needs no second flag, in any case
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        if extension.eq_ignore_ascii_case("csv") {
+            Some(ExportFormat::Csv)
+        } else if extension.eq_ignore_ascii_case("bin") {
+            Some(ExportFormat::Binary)
+        } else {
+            None
+        }
+    }
+}
+
+// writes the inputs and the verified result to disk, converted to f32 so
+// f16 runs read back the same way as f32 ones
+pub fn write_results<T: Element>(
+    path: &Path,
+    a: &[T],
+    b: &[T],
+    result: &[T],
+) -> Result<(), MetalError> {
+    let format = ExportFormat::from_path(path).ok_or_else(|| {
+        MetalError::ResultsWrite(format!(
+            "{} isn't a .csv or .bin path",
+            path.display()
+        ))
+    })?;
+    let write = || -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        match format {
+            ExportFormat::Csv => write_csv(&mut writer, a, b, result)?,
+            ExportFormat::Binary => write_binary(&mut writer, a, b, result)?,
+        }
+        writer.flush()
+    };
+    write().map_err(|err| {
+        MetalError::ResultsWrite(format!("{}: {}", path.display(), err))
+    })
+}
+
+fn write_csv<T: Element>(
+    writer: &mut impl Write,
+    a: &[T],
+    b: &[T],
+    result: &[T],
+) -> io::Result<()> {
+    writeln!(writer, "index,a,b,result")?;
+    for (i, ((a, b), result)) in a.iter().zip(b).zip(result).enumerate() {
+        writeln!(
+            writer,
+            "{},{},{},{}",
+            i,
+            a.to_f32(),
+            b.to_f32(),
+            result.to_f32()
+        )?;
+    }
+    Ok(())
+}
+
+// the three arrays back to back, each as raw little endian f32
+fn write_binary<T: Element>(
+    writer: &mut impl Write,
+    a: &[T],
+    b: &[T],
+    result: &[T],
+) -> io::Result<()> {
+    for value in a.iter().chain(b).chain(result) {
+        writer.write_all(&value.to_f32().to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use half::f16;
+
+    #[test]
+    fn format_follows_the_extension() {
+        let format = |path: &str| ExportFormat::from_path(Path::new(path));
+        assert_eq!(format("out/results.csv"), Some(ExportFormat::Csv));
+        assert_eq!(format("results.bin"), Some(ExportFormat::Binary));
+        assert_eq!(format("results.txt"), None);
+        assert_eq!(format("results"), None);
+        assert_eq!(format("RESULTS.CSV"), Some(ExportFormat::Csv));
+        assert_eq!(format("results.Bin"), Some(ExportFormat::Binary));
+    }
+
+    #[test]
+    fn csv_has_a_header_and_one_row_per_index() {
+        let mut out = Vec::new();
+        write_csv(&mut out, &[1.0f32, 2.5], &[0.5, -1.0], &[1.5, 1.5]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "index,a,b,result\n0,1,0.5,1.5\n1,2.5,-1,1.5\n"
+        );
+    }
+
+    #[test]
+    fn binary_is_little_endian_f32_even_for_half() {
+        let half = |value| f16::from_f32(value);
+        let mut out = Vec::new();
+        write_binary(&mut out, &[half(1.0)], &[half(2.0)], &[half(3.0)])
+            .unwrap();
+        let expected = [1.0f32, 2.0, 3.0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(out, expected);
+    }
+}
@@ -0,0 +1,36 @@
+use std::str::FromStr;
+
+use metal_common::AnyBitPattern;
+
+// soa keeps a, b and the result in three buffers, aos interleaves them into
+// one buffer of AosElement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    #[default]
+    Soa,
+    Aos,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "soa" => Ok(Layout::Soa),
+            "aos" => Ok(Layout::Aos),
+            other => Err(format!("unknown layout: {}", other)),
+        }
+    }
+}
+
+// matches the Element structs in add.metal
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AosElement<T> {
+    pub a: T,
+    pub b: T,
+    pub result: T,
+}
+
+// three of the same element back to back, so no padding
+unsafe impl<T: AnyBitPattern> AnyBitPattern for AosElement<T> {}
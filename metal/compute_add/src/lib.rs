@@ -0,0 +1,835 @@
+pub mod args;
+mod arguments;
+mod batch;
+mod dtype;
+mod export;
+mod layout;
+mod map;
+mod ops;
+mod stats;
+mod storage;
+mod verify;
+
+use std::cell::Cell;
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+pub use args::{Args, USAGE};
+use arguments::OperandArguments;
+use batch::{
+    ComputeBatch, Dispatch, encode_binary_op, encode_binary_op_aos,
+    encode_binary_op_arguments, encode_clamp, encode_complex_mul,
+    validate_coverage,
+};
+use dtype::{Dtype, Element};
+use export::write_results;
+use half::f16;
+use layout::{AosElement, Layout};
+use log::{info, trace, warn};
+pub use map::Mapper;
+use metal::*;
+use metal_common::{
+    Complex, GpuTimer, MetalContext, MetalError, ShaderSource, buffer_to_vec,
+    check_completion, dispatch_async, print_device_info,
+    supports_non_uniform_threadgroups, wait,
+};
+use objc::rc::autoreleasepool;
+pub use ops::UnaryOp;
+use ops::{BinaryOp, ClampRange};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use stats::Stats;
+use storage::{StorageMode, read_back, upload};
+use verify::{
+    VerifySummary, verify_aos_results, verify_complex_results,
+    verify_map_results, verify_results,
+};
+
+const STORAGE_BENCH_ITERS: usize = 20;
+const COMPLEX_MUL_KERNEL: &str = "complex_mul";
+
+pub fn run(args: &Args) -> Result<(), MetalError> {
+    autoreleasepool(|| {
+        let context = match MetalContext::new(&args.device) {
+            Err(MetalError::NoDevice) if args.headless_ok => {
+                warn!("No Metal device found, running on the CPU only");
+                return match args.dtype {
+                    Dtype::F32 => run_on_cpu::<f32>(args),
+                    Dtype::F16 => run_on_cpu::<f16>(args),
+                };
+            }
+            context => context?,
+        };
+        run_with_context(args, &context)
+    })
+}
+
+// for callers that already picked a device, e.g. the unified gpu_misc binary
+pub fn run_with_context(
+    args: &Args,
+    context: &MetalContext,
+) -> Result<(), MetalError> {
+    autoreleasepool(|| match args.dtype {
+        Dtype::F32 => run_on_gpu::<f32>(args, context),
+        Dtype::F16 => run_on_gpu::<f16>(args, context),
+    })
+}
+
+fn run_on_gpu<T: Element>(
+    args: &Args,
+    context: &MetalContext,
+) -> Result<(), MetalError> {
+    let array_length = args.array_length;
+    let device = &context.device;
+    info!("Using device: {}", device.name());
+    if args.info {
+        print_device_info(device);
+    }
+    info!("Array length: {} ({:?})", array_length, args.dtype);
+    if args.layout == Layout::Aos {
+        return run_aos::<T>(args, context, &mut input_rng(args.seed));
+    }
+
+    let buffer_size =
+        array_length.checked_mul(size_of::<T>()).ok_or_else(|| {
+            MetalError::AllocationFailed(format!(
+                "{} elements overflow the buffer size",
+                array_length
+            ))
+        })? as u64;
+
+    let buffer_a = context
+        .new_buffer(buffer_size, MTLResourceOptions::StorageModeShared)?;
+
+    let buffer_b = context
+        .new_buffer(buffer_size, MTLResourceOptions::StorageModeShared)?;
+
+    let result_storage = if args.private {
+        MTLResourceOptions::StorageModePrivate
+    } else {
+        MTLResourceOptions::StorageModeShared
+    };
+    let result_buffer = context.new_buffer(buffer_size, result_storage)?;
+
+    let mut rng = input_rng(args.seed);
+    generate_random_data::<T>(&mut rng, &buffer_a, array_length);
+    generate_random_data::<T>(&mut rng, &buffer_b, array_length);
+
+    let a = buffer_to_vec::<T>(&buffer_a, array_length)?;
+    let b = buffer_to_vec::<T>(&buffer_b, array_length)?;
+    let (gpu_buffer_a, gpu_buffer_b) = if args.private {
+        (
+            context.make_private_buffer(&a)?,
+            context.make_private_buffer(&b)?,
+        )
+    } else {
+        (buffer_a.clone(), buffer_b.clone())
+    };
+
+    let library = load_library(args, context)?;
+    let pipeline_state = context.make_pipeline(&library, T::KERNEL)?;
+    let clamp = match args.clamp {
+        Some(range) => {
+            Some((context.make_pipeline(&library, T::CLAMP_KERNEL)?, range))
+        }
+        None => None,
+    };
+    let operand_arguments = if args.argument_buffer {
+        let function = library
+            .get_function(T::ARGUMENTS_KERNEL, None)
+            .map_err(MetalError::FunctionNotFound)?;
+        Some((
+            context.make_pipeline(&library, T::ARGUMENTS_KERNEL)?,
+            OperandArguments::new(
+                context,
+                &function,
+                &gpu_buffer_a,
+                &gpu_buffer_b,
+                &result_buffer,
+            )?,
+        ))
+    } else {
+        None
+    };
+
+    let dispatch = Dispatch {
+        length: array_length,
+        threadgroup_width: args.threadgroup_width,
+        non_uniform_threadgroups: supports_non_uniform_threadgroups(device),
+    };
+    if !dispatch.non_uniform_threadgroups {
+        info!("No non-uniform threadgroup support, dispatching whole groups");
+    }
+
+    // every dispatch records the grid it encoded, which with --argument-buffer
+    // comes from the arguments pipeline
+    let op_grid = Cell::new(None);
+    let clamp_grid = Cell::new(None);
+    let encode = |compute_encoder: &ComputeCommandEncoderRef| {
+        op_grid.set(Some(match &operand_arguments {
+            Some((arguments_pipeline, operands)) => encode_binary_op_arguments(
+                compute_encoder,
+                arguments_pipeline,
+                operands,
+                dispatch,
+                args.op,
+            ),
+            None => encode_binary_op(
+                compute_encoder,
+                &pipeline_state,
+                &gpu_buffer_a,
+                &gpu_buffer_b,
+                &result_buffer,
+                dispatch,
+                args.op,
+            ),
+        }));
+        if let Some((clamp_pipeline, range)) = &clamp {
+            clamp_grid.set(Some(encode_clamp(
+                compute_encoder,
+                clamp_pipeline,
+                &result_buffer,
+                dispatch,
+                *range,
+            )));
+        }
+    };
+
+    let mut timer = GpuTimer::new(device);
+    if args.warmup {
+        time_gpu(&context.command_queue, &mut timer, encode)?;
+    }
+    let gpu_time = time_gpu(&context.command_queue, &mut timer, encode)?;
+    // the grid is the same for every later dispatch, so checking the first
+    // one is enough
+    for grid in [op_grid.get(), clamp_grid.get()].into_iter().flatten() {
+        validate_coverage(array_length, grid);
+    }
+    if let (Some(requested), Some(grid)) =
+        (args.threadgroup_width, op_grid.get())
+    {
+        println!(
+            "Threadgroup width: {} requested, {} effective",
+            requested, grid.threadgroup_width
+        );
+    }
+
+    if args.async_dispatches > 0 {
+        // every dispatch writes the same result, so they can all be in
+        // flight at once without changing what gets verified below
+        let start = Instant::now();
+        let tokens = (0..args.async_dispatches)
+            .map(|_| dispatch_async(&context.command_queue, encode))
+            .collect::<Vec<_>>();
+        for token in tokens {
+            check_completion(&wait(token))?;
+        }
+        println!(
+            "{} async dispatches in flight: {:?}",
+            args.async_dispatches,
+            start.elapsed()
+        );
+    }
+
+    // encode still borrows result_buffer for the benchmark below
+    let readback = args
+        .private
+        .then(|| context.readback_buffer(&result_buffer))
+        .transpose()?;
+    let result_buffer = readback.as_deref().unwrap_or(&result_buffer);
+
+    let result = buffer_to_vec::<T>(result_buffer, array_length)?;
+    let verified = report_verification(verify_results(
+        &a,
+        &b,
+        &result,
+        args.op,
+        args.clamp,
+        T::TOLERANCE,
+        args.max_mismatches,
+    ));
+    // written either way, a mismatch is what the dump is for
+    if let Some(path) = &args.out {
+        write_results(path, &a, &b, &result)?;
+        println!("Results written to {}", path.display());
+    }
+    verified?;
+
+    if args.batch {
+        let outputs = BinaryOp::ALL
+            .iter()
+            .map(|_| {
+                context.new_buffer(
+                    buffer_size,
+                    MTLResourceOptions::StorageModeShared,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut batch =
+            ComputeBatch::<T>::new(&context.command_queue, &pipeline_state)
+                .with_threadgroup_width(args.threadgroup_width);
+        for (op, output) in BinaryOp::ALL.iter().zip(&outputs) {
+            batch.add(*op, &gpu_buffer_a, &gpu_buffer_b, output);
+        }
+        let start = Instant::now();
+        batch.submit()?;
+        println!(
+            "Batched {} ops in one command buffer: {:?}",
+            BinaryOp::ALL.len(),
+            start.elapsed()
+        );
+        for (op, output) in BinaryOp::ALL.iter().zip(&outputs) {
+            report_verification(verify_results(
+                &a,
+                &b,
+                &buffer_to_vec::<T>(output, array_length)?,
+                *op,
+                None,
+                T::TOLERANCE,
+                args.max_mismatches,
+            ))?;
+        }
+    }
+
+    if args.complex {
+        run_complex_mul(
+            context,
+            &library,
+            dispatch,
+            &mut rng,
+            args.max_mismatches,
+        )?;
+    }
+
+    if let Some(op) = args.map {
+        run_map(context, &library, &mut rng, args, op)?;
+    }
+
+    let cpu_start = Instant::now();
+    std::hint::black_box(compute_on_cpu(&a, &b, args.op, args.clamp));
+    let cpu_time = cpu_start.elapsed();
+
+    println!("GPU time: {:?}", gpu_time);
+    println!(
+        "GPU kernel time: {:.3} ms ({})",
+        timer.last_gpu_time_ms(),
+        if timer.uses_counters() {
+            "timestamp counters"
+        } else {
+            "command buffer timestamps"
+        }
+    );
+    println!("CPU time: {:?}", cpu_time);
+    println!(
+        "Speedup: {:.2}x",
+        cpu_time.as_secs_f64() / gpu_time.as_secs_f64()
+    );
+
+    if args.iters > 0 {
+        // a and b are read and the result written once per dispatch
+        benchmark(
+            &context.command_queue,
+            &mut timer,
+            encode,
+            args.iters,
+            3 * buffer_size,
+        )?;
+    }
+
+    if args.storage_bench {
+        let iters = if args.iters > 0 {
+            args.iters
+        } else {
+            STORAGE_BENCH_ITERS
+        };
+        compare_storage_modes::<T>(
+            context,
+            &pipeline_state,
+            dispatch,
+            args.op,
+            iters,
+            &mut rng,
+        )?;
+    }
+    Ok(())
+}
+
+// the same op over one buffer of interleaved elements, timed the same way
+// as the three buffer layout so the two can be compared
+fn run_aos<T: Element>(
+    args: &Args,
+    context: &MetalContext,
+    rng: &mut StdRng,
+) -> Result<(), MetalError> {
+    let elements = (0..args.array_length)
+        .map(|_| AosElement {
+            a: T::from_f32(rng.random()),
+            b: T::from_f32(rng.random()),
+            result: T::from_f32(0.0),
+        })
+        .collect::<Vec<_>>();
+    let buffer = upload(context, StorageMode::Shared, &elements)?;
+
+    let library = load_library(args, context)?;
+    let pipeline_state = context.make_pipeline(&library, T::AOS_KERNEL)?;
+    let dispatch = Dispatch {
+        length: args.array_length,
+        threadgroup_width: args.threadgroup_width,
+        non_uniform_threadgroups: supports_non_uniform_threadgroups(
+            &context.device,
+        ),
+    };
+    let grid = Cell::new(None);
+    let encode = |compute_encoder: &ComputeCommandEncoderRef| {
+        grid.set(Some(encode_binary_op_aos(
+            compute_encoder,
+            &pipeline_state,
+            &buffer,
+            dispatch,
+            args.op,
+        )));
+    };
+
+    let mut timer = GpuTimer::new(&context.device);
+    if args.warmup {
+        time_gpu(&context.command_queue, &mut timer, encode)?;
+    }
+    let gpu_time = time_gpu(&context.command_queue, &mut timer, encode)?;
+    if let Some(grid) = grid.get() {
+        validate_coverage(args.array_length, grid);
+    }
+    println!("AoS GPU time: {:?}", gpu_time);
+    println!("AoS GPU kernel time: {:.3} ms", timer.last_gpu_time_ms());
+
+    if args.iters > 0 {
+        // every element is read and its result written back once
+        benchmark(
+            &context.command_queue,
+            &mut timer,
+            encode,
+            args.iters,
+            buffer.length(),
+        )?;
+    }
+
+    let elements = buffer_to_vec::<AosElement<T>>(&buffer, args.array_length)?;
+    let verified = report_verification(verify_aos_results(
+        &elements,
+        args.op,
+        T::TOLERANCE,
+        args.max_mismatches,
+    ));
+    if let Some(path) = &args.out {
+        let a = elements.iter().map(|element| element.a).collect::<Vec<_>>();
+        let b = elements.iter().map(|element| element.b).collect::<Vec<_>>();
+        let result = elements
+            .iter()
+            .map(|element| element.result)
+            .collect::<Vec<_>>();
+        write_results(path, &a, &b, &result)?;
+        println!("Results written to {}", path.display());
+    }
+    verified
+}
+
+// one dispatch binding two inputs and two outputs, always in f32 since the
+// pairs are read as float2
+fn run_complex_mul(
+    context: &MetalContext,
+    library: &LibraryRef,
+    dispatch: Dispatch,
+    rng: &mut StdRng,
+    max_mismatches: usize,
+) -> Result<(), MetalError> {
+    let mut random = |_| Complex {
+        re: rng.random(),
+        im: rng.random(),
+    };
+    let a = (0..dispatch.length).map(&mut random).collect::<Vec<_>>();
+    let b = (0..dispatch.length).map(&mut random).collect::<Vec<_>>();
+    let buffer_a = upload(context, StorageMode::Shared, &a)?;
+    let buffer_b = upload(context, StorageMode::Shared, &b)?;
+    let part_size = (dispatch.length * size_of::<f32>()) as u64;
+    let result_re =
+        context.new_buffer(part_size, MTLResourceOptions::StorageModeShared)?;
+    let result_im =
+        context.new_buffer(part_size, MTLResourceOptions::StorageModeShared)?;
+
+    let pipeline_state = context.make_pipeline(library, COMPLEX_MUL_KERNEL)?;
+    let mut timer = GpuTimer::new(&context.device);
+    let gpu_time =
+        time_gpu(&context.command_queue, &mut timer, |compute_encoder| {
+            encode_complex_mul(
+                compute_encoder,
+                &pipeline_state,
+                &buffer_a,
+                &buffer_b,
+                &result_re,
+                &result_im,
+                dispatch,
+            );
+        })?;
+
+    println!("Complex multiply GPU time: {:?}", gpu_time);
+    report_verification(verify_complex_results(
+        &a,
+        &b,
+        &buffer_to_vec(&result_re, dispatch.length)?,
+        &buffer_to_vec(&result_im, dispatch.length)?,
+        f32::TOLERANCE,
+        max_mismatches,
+    ))
+}
+
+// always f32, map only takes float slices
+fn run_map(
+    context: &MetalContext,
+    library: &LibraryRef,
+    rng: &mut StdRng,
+    args: &Args,
+    op: UnaryOp,
+) -> Result<(), MetalError> {
+    let mapper = Mapper::with_library(context, library)?;
+    let input = map_input(rng, args.array_length, op);
+
+    let start = Instant::now();
+    let result = mapper.map(op, &input)?;
+    println!("Map {} GPU time: {:?}", op.symbol(), start.elapsed());
+    report_verification(verify_map_results(
+        &input,
+        &result,
+        op,
+        args.max_mismatches,
+    ))
+}
+
+// the same inputs and check as the gpu map, so --map still exercises the op
+// without a device
+fn run_map_on_cpu(
+    rng: &mut StdRng,
+    args: &Args,
+    op: UnaryOp,
+) -> Result<(), MetalError> {
+    let input = map_input(rng, args.array_length, op);
+
+    let start = Instant::now();
+    let result = input.iter().map(|&x| op.apply(x)).collect::<Vec<_>>();
+    println!("Map {} CPU time: {:?}", op.symbol(), start.elapsed());
+    report_verification(verify_map_results(
+        &input,
+        &result,
+        op,
+        args.max_mismatches,
+    ))
+}
+
+fn map_input(rng: &mut StdRng, length: usize, op: UnaryOp) -> Vec<f32> {
+    (0..length)
+        .map(|_| rng.random_range(op.input_range()))
+        .collect()
+}
+
+fn run_on_cpu<T: Element>(args: &Args) -> Result<(), MetalError> {
+    let mut rng = input_rng(args.seed);
+    let a = random_values::<T>(&mut rng, args.array_length);
+    let b = random_values::<T>(&mut rng, args.array_length);
+
+    let cpu_start = Instant::now();
+    let result = compute_on_cpu(&a, &b, args.op, args.clamp);
+    let cpu_time = cpu_start.elapsed();
+
+    println!("CPU time: {:?}", cpu_time);
+    let verified = report_verification(verify_results(
+        &a,
+        &b,
+        &result,
+        args.op,
+        args.clamp,
+        T::TOLERANCE,
+        args.max_mismatches,
+    ));
+    if let Some(path) = &args.out {
+        write_results(path, &a, &b, &result)?;
+        println!("Results written to {}", path.display());
+    }
+    verified?;
+
+    if let Some(op) = args.map {
+        run_map_on_cpu(&mut rng, args, op)?;
+    }
+    Ok(())
+}
+
+fn time_gpu(
+    command_queue: &CommandQueueRef,
+    timer: &mut GpuTimer,
+    encode: impl Fn(&ComputeCommandEncoderRef),
+) -> Result<Duration, MetalError> {
+    let command_buffer = command_queue.new_command_buffer();
+    let compute_encoder = command_buffer.new_compute_command_encoder();
+    timer.begin(compute_encoder);
+    encode(compute_encoder);
+    timer.end(compute_encoder);
+    compute_encoder.end_encoding();
+
+    let start = Instant::now();
+    command_buffer.commit();
+    command_buffer.wait_until_completed();
+    let elapsed = start.elapsed();
+    check_completion(command_buffer)?;
+
+    timer.resolve(command_buffer);
+    trace!(
+        "Dispatch took {:?}, {:.3} ms on the gpu",
+        elapsed,
+        timer.last_gpu_time_ms()
+    );
+    Ok(elapsed)
+}
+
+// the same buffers are dispatched every iteration, so only the kernel itself
+// is measured
+fn benchmark(
+    command_queue: &CommandQueueRef,
+    timer: &mut GpuTimer,
+    encode: impl Fn(&ComputeCommandEncoderRef),
+    iters: usize,
+    bytes_moved: u64,
+) -> Result<(), MetalError> {
+    let Some(stats) = kernel_stats(command_queue, timer, encode, iters)? else {
+        return Ok(());
+    };
+
+    println!(
+        "{} iterations: min {:.3} ms, median {:.3} ms, p95 {:.3} ms, \
+         max {:.3} ms",
+        iters, stats.min, stats.median, stats.p95, stats.max
+    );
+    println!(
+        "Effective bandwidth: {:.2} GB/s",
+        gigabytes_per_second(bytes_moved, stats.median)
+    );
+    Ok(())
+}
+
+fn kernel_stats(
+    command_queue: &CommandQueueRef,
+    timer: &mut GpuTimer,
+    encode: impl Fn(&ComputeCommandEncoderRef),
+    iters: usize,
+) -> Result<Option<Stats>, MetalError> {
+    // untimed warmup
+    time_gpu(command_queue, timer, &encode)?;
+    let samples = (0..iters)
+        .map(|_| -> Result<f64, MetalError> {
+            let wall_time = time_gpu(command_queue, timer, &encode)?;
+            // gpu timestamps can read zero on devices that don't report them
+            Ok(match timer.last_gpu_time_ms() {
+                gpu_time_ms if gpu_time_ms > 0.0 => gpu_time_ms,
+                _ => wall_time.as_secs_f64() * 1000.0,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Stats::from_samples(&samples))
+}
+
+fn gigabytes_per_second(bytes: u64, milliseconds: f64) -> f64 {
+    bytes as f64 / (milliseconds / 1000.0) / 1e9
+}
+
+// runs the same dispatch with inputs and result in each storage mode, timing
+// the upload, the kernel and the readback separately
+fn compare_storage_modes<T: Element>(
+    context: &MetalContext,
+    pipeline_state: &ComputePipelineStateRef,
+    dispatch: Dispatch,
+    op: BinaryOp,
+    iters: usize,
+    rng: &mut StdRng,
+) -> Result<(), MetalError> {
+    let a = random_values::<T>(rng, dispatch.length);
+    let b = random_values::<T>(rng, dispatch.length);
+    let buffer_size = (dispatch.length * size_of::<T>()) as u64;
+    let mut timer = GpuTimer::new(&context.device);
+
+    println!(
+        "{:<8} {:>10} {:>12} {:>10} {:>12}  result",
+        "storage", "upload ms", "kernel ms", "GB/s", "readback ms"
+    );
+    for mode in StorageMode::ALL {
+        let start = Instant::now();
+        let buffer_a = upload(context, mode, &a)?;
+        let buffer_b = upload(context, mode, &b)?;
+        let upload_time = start.elapsed();
+        let result_buffer =
+            context.new_buffer(buffer_size, mode.resource_options())?;
+
+        let encode = |compute_encoder: &ComputeCommandEncoderRef| {
+            encode_binary_op(
+                compute_encoder,
+                pipeline_state,
+                &buffer_a,
+                &buffer_b,
+                &result_buffer,
+                dispatch,
+                op,
+            );
+        };
+        let Some(stats) =
+            kernel_stats(&context.command_queue, &mut timer, encode, iters)?
+        else {
+            return Ok(());
+        };
+
+        let start = Instant::now();
+        let result =
+            read_back::<T>(context, mode, &result_buffer, dispatch.length)?;
+        let readback_time = start.elapsed();
+        // only pass or fail fits in the table
+        let verified =
+            verify_results(&a, &b, &result, op, None, T::TOLERANCE, 0).is_ok();
+
+        println!(
+            "{:<8} {:>10.3} {:>12.3} {:>10.2} {:>12.3}  {}",
+            format!("{:?}", mode),
+            upload_time.as_secs_f64() * 1000.0,
+            stats.median,
+            // a and b are read and the result written once per dispatch
+            gigabytes_per_second(3 * buffer_size, stats.median),
+            readback_time.as_secs_f64() * 1000.0,
+            if verified { "ok" } else { "MISMATCH" }
+        );
+    }
+    Ok(())
+}
+
+fn load_library(
+    args: &Args,
+    context: &MetalContext,
+) -> Result<Library, MetalError> {
+    let shader_source = match &args.metallib {
+        Some(path) => ShaderSource::Library(path.clone()),
+        None => ShaderSource::Source(include_str!("add.metal")),
+    };
+    context.load_library(&shader_source)
+}
+
+fn compute_on_cpu<T: Element>(
+    a: &[T],
+    b: &[T],
+    op: BinaryOp,
+    clamp: Option<ClampRange>,
+) -> Vec<T> {
+    a.iter()
+        .zip(b)
+        .map(|(&a, &b)| {
+            let value = op.apply(a.to_f32(), b.to_f32());
+            T::from_f32(clamp.map_or(value, |range| range.apply(value)))
+        })
+        .collect()
+}
+
+// a --seed makes the inputs, and with them the timings and any
+// verification failure, the same on every run
+fn input_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => {
+            info!("Seeding inputs with {}", seed);
+            StdRng::seed_from_u64(seed)
+        }
+        None => StdRng::from_os_rng(),
+    }
+}
+
+fn random_values<T: Element>(rng: &mut StdRng, length: usize) -> Vec<T> {
+    (0..length).map(|_| T::from_f32(rng.random())).collect()
+}
+
+fn generate_random_data<T: Element>(
+    rng: &mut StdRng,
+    buffer: &BufferRef,
+    length: usize,
+) {
+    let data_ptr = buffer.contents() as *mut T;
+
+    unsafe {
+        for i in 0..length {
+            *data_ptr.add(i) = T::from_f32(rng.random::<f32>());
+        }
+    }
+}
+
+// the summary is printed either way, a mismatch also fails the run so the
+// exit status reflects it
+fn report_verification(summary: VerifySummary) -> Result<(), MetalError> {
+    println!("{}", summary);
+    if summary.is_ok() {
+        return Ok(());
+    }
+    Err(MetalError::VerificationFailed(
+        match &summary.length_mismatch {
+            Some(length_mismatch) => length_mismatch.to_string(),
+            None => format!(
+                "{} of {} results mismatched",
+                summary.mismatch_count, summary.checked
+            ),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_fallback_verifies_every_op_in_both_dtypes() {
+        for op in BinaryOp::ALL {
+            let args = Args {
+                op,
+                seed: Some(1),
+                ..Args::default()
+            };
+            assert!(run_on_cpu::<f32>(&args).is_ok(), "{:?}", op);
+            assert!(run_on_cpu::<f16>(&args).is_ok(), "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn cpu_fallback_applies_the_clamp() {
+        let args = Args {
+            clamp: Some("0.25,0.5".parse().unwrap()),
+            seed: Some(2),
+            ..Args::default()
+        };
+        assert!(run_on_cpu::<f32>(&args).is_ok());
+    }
+
+    #[test]
+    fn cpu_fallback_runs_every_map_op() {
+        for op in UnaryOp::ALL {
+            let args = Args {
+                map: Some(op),
+                seed: Some(3),
+                ..Args::default()
+            };
+            assert!(run_on_cpu::<f32>(&args).is_ok(), "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn mismatches_fail_the_run() {
+        let summary = verify_results(
+            &[1.0f32],
+            &[2.0],
+            &[0.0],
+            BinaryOp::Add,
+            None,
+            0.0,
+            1,
+        );
+        assert!(matches!(
+            report_verification(summary),
+            Err(MetalError::VerificationFailed(_))
+        ));
+    }
+}
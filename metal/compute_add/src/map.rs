@@ -0,0 +1,114 @@
+use std::mem::size_of_val;
+
+use metal::*;
+use metal_common::{
+    MetalContext, MetalError, ShaderSource, buffer_to_vec, check_completion,
+    supports_non_uniform_threadgroups,
+};
+
+use crate::batch::{Dispatch, encode_unary_op};
+use crate::ops::UnaryOp;
+use crate::storage::{StorageMode, upload};
+
+// applies a unary op to every element, the op is a uniform so one pipeline
+// covers all of them
+pub struct Mapper<'a> {
+    context: &'a MetalContext,
+    pipeline_state: ComputePipelineState,
+}
+
+impl<'a> Mapper<'a> {
+    // compiles the bundled kernels
+    pub fn new(context: &'a MetalContext) -> Result<Self, MetalError> {
+        let library = context
+            .load_library(&ShaderSource::Source(include_str!("add.metal")))?;
+        Self::with_library(context, &library)
+    }
+
+    pub fn with_library(
+        context: &'a MetalContext,
+        library: &LibraryRef,
+    ) -> Result<Self, MetalError> {
+        let pipeline_state = context.make_pipeline(library, "unary_op")?;
+        Ok(Mapper {
+            context,
+            pipeline_state,
+        })
+    }
+
+    pub fn map(
+        &self,
+        op: UnaryOp,
+        input: &[f32],
+    ) -> Result<Vec<f32>, MetalError> {
+        // a zero length buffer can't be allocated
+        if input.is_empty() {
+            return Ok(Vec::new());
+        }
+        let input_buffer = upload(self.context, StorageMode::Shared, input)?;
+        let result_buffer = self.context.new_buffer(
+            size_of_val(input) as u64,
+            MTLResourceOptions::StorageModeShared,
+        )?;
+        let dispatch = Dispatch {
+            length: input.len(),
+            threadgroup_width: None,
+            non_uniform_threadgroups: supports_non_uniform_threadgroups(
+                &self.context.device,
+            ),
+        };
+
+        let command_buffer = self.context.command_queue.new_command_buffer();
+        let compute_encoder = command_buffer.new_compute_command_encoder();
+        encode_unary_op(
+            compute_encoder,
+            &self.pipeline_state,
+            &input_buffer,
+            &result_buffer,
+            dispatch,
+            op,
+        );
+        compute_encoder.end_encoding();
+        command_buffer.commit();
+        command_buffer.wait_until_completed();
+        check_completion(command_buffer)?;
+
+        buffer_to_vec(&result_buffer, input.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use metal_common::DevicePreference;
+
+    use super::*;
+    use crate::verify::verify_map_results;
+
+    #[test]
+    fn maps_every_op_within_its_tolerance() {
+        const LENGTH: usize = 5000;
+        let Ok(context) = MetalContext::new(&DevicePreference::default())
+        else {
+            eprintln!("no metal device, skipping");
+            return;
+        };
+        let mapper = Mapper::new(&context).unwrap();
+        for op in UnaryOp::ALL {
+            let range = op.input_range();
+            // spread over the range and across several threadgroups
+            let input = (0..LENGTH)
+                .map(|i| {
+                    range.start
+                        + (range.end - range.start) * i as f32 / LENGTH as f32
+                })
+                .collect::<Vec<_>>();
+            let result = mapper.map(op, &input).unwrap();
+            assert!(
+                verify_map_results(&input, &result, op, 8).is_ok(),
+                "{:?}",
+                op
+            );
+        }
+        assert!(mapper.map(UnaryOp::Abs, &[]).unwrap().is_empty());
+    }
+}
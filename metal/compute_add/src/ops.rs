@@ -0,0 +1,168 @@
+use std::ops::Range;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u32)]
+pub enum BinaryOp {
+    #[default]
+    Add = 0,
+    Sub = 1,
+    Mul = 2,
+    Div = 3,
+    Max = 4,
+    Min = 5,
+}
+
+impl BinaryOp {
+    pub const ALL: [BinaryOp; 6] = [
+        BinaryOp::Add,
+        BinaryOp::Sub,
+        BinaryOp::Mul,
+        BinaryOp::Div,
+        BinaryOp::Max,
+        BinaryOp::Min,
+    ];
+
+    pub fn apply(self, a: f32, b: f32) -> f32 {
+        match self {
+            BinaryOp::Add => a + b,
+            BinaryOp::Sub => a - b,
+            BinaryOp::Mul => a * b,
+            BinaryOp::Div => a / b,
+            BinaryOp::Max => a.max(b),
+            BinaryOp::Min => a.min(b),
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "a+b",
+            BinaryOp::Sub => "a-b",
+            BinaryOp::Mul => "a*b",
+            BinaryOp::Div => "a/b",
+            BinaryOp::Max => "max(a,b)",
+            BinaryOp::Min => "min(a,b)",
+        }
+    }
+}
+
+impl FromStr for BinaryOp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "add" => Ok(BinaryOp::Add),
+            "sub" => Ok(BinaryOp::Sub),
+            "mul" => Ok(BinaryOp::Mul),
+            "div" => Ok(BinaryOp::Div),
+            "max" => Ok(BinaryOp::Max),
+            "min" => Ok(BinaryOp::Min),
+            other => Err(format!("unknown op: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum UnaryOp {
+    Abs = 0,
+    Sqrt = 1,
+    Exp = 2,
+    Sin = 3,
+    Neg = 4,
+}
+
+impl UnaryOp {
+    pub const ALL: [UnaryOp; 5] = [
+        UnaryOp::Abs,
+        UnaryOp::Sqrt,
+        UnaryOp::Exp,
+        UnaryOp::Sin,
+        UnaryOp::Neg,
+    ];
+
+    pub fn apply(self, x: f32) -> f32 {
+        match self {
+            UnaryOp::Abs => x.abs(),
+            UnaryOp::Sqrt => x.sqrt(),
+            UnaryOp::Exp => x.exp(),
+            UnaryOp::Sin => x.sin(),
+            UnaryOp::Neg => -x,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Abs => "abs(x)",
+            UnaryOp::Sqrt => "sqrt(x)",
+            UnaryOp::Exp => "exp(x)",
+            UnaryOp::Sin => "sin(x)",
+            UnaryOp::Neg => "-x",
+        }
+    }
+
+    // metal compiles with fast math, whose transcendentals are only a few
+    // ulp from std; sin is bounded in absolute error, 2^-13 within [-pi, pi]
+    // and looser past it
+    pub fn tolerance(self) -> f32 {
+        match self {
+            UnaryOp::Abs | UnaryOp::Neg => 0.0,
+            UnaryOp::Sqrt => 0.000001,
+            UnaryOp::Exp => 0.000004,
+            UnaryOp::Sin => 0.001,
+        }
+    }
+
+    // inputs the op is defined on, sqrt of a negative would only compare nan
+    pub fn input_range(self) -> Range<f32> {
+        match self {
+            UnaryOp::Sqrt => 0.0..4.0,
+            _ => -4.0..4.0,
+        }
+    }
+}
+
+impl FromStr for UnaryOp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abs" => Ok(UnaryOp::Abs),
+            "sqrt" => Ok(UnaryOp::Sqrt),
+            "exp" => Ok(UnaryOp::Exp),
+            "sin" => Ok(UnaryOp::Sin),
+            "neg" => Ok(UnaryOp::Neg),
+            other => Err(format!("unknown map op: {}", other)),
+        }
+    }
+}
+
+// bounds results to [min, max], for outputs that feed a normalized texture
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClampRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ClampRange {
+    pub fn apply(self, value: f32) -> f32 {
+        value.clamp(self.min, self.max)
+    }
+}
+
+impl FromStr for ClampRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("clamp range must be min,max: {}", s);
+        let (min, max) = s.split_once(',').ok_or_else(invalid)?;
+        let min: f32 = min.trim().parse().map_err(|_| invalid())?;
+        let max: f32 = max.trim().parse().map_err(|_| invalid())?;
+        // f32::clamp panics on these
+        if min.is_nan() || max.is_nan() || min > max {
+            return Err(invalid());
+        }
+        Ok(ClampRange { min, max })
+    }
+}
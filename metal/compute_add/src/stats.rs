@@ -0,0 +1,64 @@
+// summary of repeated timings, in whatever unit the samples were taken
+#[derive(Debug, PartialEq)]
+pub struct Stats {
+    pub min: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+impl Stats {
+    pub fn from_samples(samples: &[f64]) -> Option<Self> {
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        Some(Stats {
+            min: *sorted.first()?,
+            median: percentile(&sorted, 0.5),
+            p95: percentile(&sorted, 0.95),
+            max: *sorted.last()?,
+        })
+    }
+}
+
+// nearest rank, so the result is always one of the measured samples
+fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+    let rank = (fraction * sorted.len() as f64).ceil() as usize;
+    sorted[rank.saturating_sub(1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_samples_have_no_stats() {
+        assert_eq!(Stats::from_samples(&[]), None);
+    }
+
+    #[test]
+    fn stats_ignore_sample_order() {
+        let samples = (1..=20).rev().map(f64::from).collect::<Vec<_>>();
+        assert_eq!(
+            Stats::from_samples(&samples),
+            Some(Stats {
+                min: 1.0,
+                median: 10.0,
+                p95: 19.0,
+                max: 20.0,
+            })
+        );
+    }
+
+    #[test]
+    fn single_sample_is_every_stat() {
+        assert_eq!(
+            Stats::from_samples(&[2.5]),
+            Some(Stats {
+                min: 2.5,
+                median: 2.5,
+                p95: 2.5,
+                max: 2.5,
+            })
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use std::mem::size_of_val;
+
+use metal::*;
+use metal_common::{MetalContext, MetalError, buffer_to_vec, check_completion};
+
+use crate::dtype::Element;
+
+#[derive(Debug, Clone, Copy)]
+pub enum StorageMode {
+    Shared,
+    Private,
+    Managed,
+}
+
+impl StorageMode {
+    pub const ALL: [StorageMode; 3] = [
+        StorageMode::Shared,
+        StorageMode::Private,
+        StorageMode::Managed,
+    ];
+
+    pub fn resource_options(self) -> MTLResourceOptions {
+        match self {
+            StorageMode::Shared => MTLResourceOptions::StorageModeShared,
+            StorageMode::Private => MTLResourceOptions::StorageModePrivate,
+            StorageMode::Managed => MTLResourceOptions::StorageModeManaged,
+        }
+    }
+}
+
+pub fn upload<T>(
+    context: &MetalContext,
+    mode: StorageMode,
+    data: &[T],
+) -> Result<Buffer, MetalError> {
+    if let StorageMode::Private = mode {
+        return context.make_private_buffer(data);
+    }
+    let buffer = context
+        .new_buffer(size_of_val(data) as u64, mode.resource_options())?;
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            data.as_ptr(),
+            buffer.contents() as *mut T,
+            data.len(),
+        );
+    }
+    if let StorageMode::Managed = mode {
+        // managed buffers keep separate cpu and gpu copies, the gpu only
+        // picks up cpu writes in ranges flagged as modified
+        buffer.did_modify_range(NSRange::new(0, buffer.length()));
+    }
+    Ok(buffer)
+}
+
+pub fn read_back<T: Element>(
+    context: &MetalContext,
+    mode: StorageMode,
+    buffer: &BufferRef,
+    length: usize,
+) -> Result<Vec<T>, MetalError> {
+    let readable = match mode {
+        StorageMode::Shared => buffer.to_owned(),
+        StorageMode::Private => context.readback_buffer(buffer)?,
+        StorageMode::Managed => {
+            // and gpu writes only reach the cpu copy after a synchronize blit
+            let command_buffer = context.command_queue.new_command_buffer();
+            let blit_encoder = command_buffer.new_blit_command_encoder();
+            blit_encoder.synchronize_resource(buffer);
+            blit_encoder.end_encoding();
+            command_buffer.commit();
+            command_buffer.wait_until_completed();
+            check_completion(command_buffer)?;
+            buffer.to_owned()
+        }
+    };
+    buffer_to_vec(&readable, length)
+}
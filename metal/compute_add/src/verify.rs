@@ -0,0 +1,683 @@
+use std::fmt;
+
+use metal_common::Complex;
+
+use crate::dtype::Element;
+use crate::layout::AosElement;
+use crate::ops::{BinaryOp, ClampRange, UnaryOp};
+
+#[derive(Debug, PartialEq)]
+pub struct VerifyError {
+    pub index: usize,
+    pub result: f32,
+    pub expected: f32,
+    // what was computed, e.g. a+b
+    pub expression: &'static str,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Compute ERROR: index={} result={} vs {}={}",
+            self.index, self.result, self.expected, self.expression
+        )
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+// a slice that came back a different length than the inputs, zip would
+// quietly check only the overlap
+#[derive(Debug, PartialEq)]
+pub struct LengthMismatch {
+    pub name: &'static str,
+    pub len: usize,
+    pub expected: usize,
+}
+
+impl fmt::Display for LengthMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} has {} elements, expected {}",
+            self.name, self.len, self.expected
+        )
+    }
+}
+
+// every result is checked rather than stopping at the first bad one, so a
+// kernel that's off by one element reads differently from one that's wrong
+// everywhere. only the first max_reported mismatches are kept
+#[derive(Debug, PartialEq)]
+pub struct VerifySummary {
+    pub checked: usize,
+    pub mismatch_count: usize,
+    pub mismatches: Vec<VerifyError>,
+    // over every finite result, not just the mismatches. the relative error
+    // is absolute below 1 like the tolerance
+    pub max_abs_error: f32,
+    pub max_rel_error: f32,
+    // the overlap is still checked, but the run can't pass
+    pub length_mismatch: Option<LengthMismatch>,
+    max_reported: usize,
+}
+
+impl VerifySummary {
+    fn new(max_reported: usize) -> Self {
+        VerifySummary {
+            checked: 0,
+            mismatch_count: 0,
+            mismatches: Vec::new(),
+            max_abs_error: 0.0,
+            max_rel_error: 0.0,
+            length_mismatch: None,
+            max_reported,
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.mismatch_count == 0 && self.length_mismatch.is_none()
+    }
+
+    // keeps the first disagreement, later ones usually follow from it
+    fn check_length(
+        &mut self,
+        name: &'static str,
+        len: usize,
+        expected: usize,
+    ) {
+        if len != expected && self.length_mismatch.is_none() {
+            self.length_mismatch = Some(LengthMismatch {
+                name,
+                len,
+                expected,
+            });
+        }
+    }
+
+    fn check(
+        &mut self,
+        index: usize,
+        result: f32,
+        expected: f32,
+        expression: &'static str,
+        tolerance: f32,
+    ) {
+        self.checked += 1;
+        let abs_error = (result - expected).abs();
+        let rel_error = abs_error / expected.abs().max(1.0);
+        // max skips nan, which is counted as a mismatch below instead
+        self.max_abs_error = self.max_abs_error.max(abs_error);
+        self.max_rel_error = self.max_rel_error.max(rel_error);
+        if result.is_nan() || rel_error > tolerance {
+            self.mismatch_count += 1;
+            if self.mismatches.len() < self.max_reported {
+                self.mismatches.push(VerifyError {
+                    index,
+                    result,
+                    expected,
+                    expression,
+                });
+            }
+        }
+    }
+}
+
+impl fmt::Display for VerifySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for mismatch in &self.mismatches {
+            writeln!(f, "{}", mismatch)?;
+        }
+        if let Some(length_mismatch) = &self.length_mismatch {
+            writeln!(f, "Compute ERROR: {}", length_mismatch)?;
+        }
+        if self.is_ok() {
+            write!(f, "Compute results as expected")?;
+        } else if self.mismatch_count == 0 {
+            write!(
+                f,
+                "Compute ERROR: the {} results checked match",
+                self.checked
+            )?;
+        } else {
+            write!(
+                f,
+                "Compute ERROR: {} of {} results wrong",
+                self.mismatch_count, self.checked
+            )?;
+            let hidden = self.mismatch_count - self.mismatches.len();
+            if hidden > 0 {
+                write!(f, " ({} not shown)", hidden)?;
+            }
+        }
+        write!(
+            f,
+            ", max error {:e} absolute, {:e} relative",
+            self.max_abs_error, self.max_rel_error
+        )
+    }
+}
+
+pub fn verify_results<T: Element>(
+    a: &[T],
+    b: &[T],
+    result: &[T],
+    op: BinaryOp,
+    clamp: Option<ClampRange>,
+    tolerance: f32,
+    max_reported: usize,
+) -> VerifySummary {
+    let mut summary = VerifySummary::new(max_reported);
+    summary.check_length("b", b.len(), a.len());
+    summary.check_length("result", result.len(), a.len());
+    for (index, ((&a, &b), &result)) in a.iter().zip(b).zip(result).enumerate()
+    {
+        summary.check(
+            index,
+            result.to_f32(),
+            expected_op(a, b, op, clamp),
+            op.symbol(),
+            tolerance,
+        );
+    }
+    summary
+}
+
+// the inputs travel with each result, so there's nothing to zip
+pub fn verify_aos_results<T: Element>(
+    elements: &[AosElement<T>],
+    op: BinaryOp,
+    tolerance: f32,
+    max_reported: usize,
+) -> VerifySummary {
+    let mut summary = VerifySummary::new(max_reported);
+    for (index, element) in elements.iter().enumerate() {
+        summary.check(
+            index,
+            element.result.to_f32(),
+            expected_op(element.a, element.b, op, None),
+            op.symbol(),
+            tolerance,
+        );
+    }
+    summary
+}
+
+// the real and imaginary parts come back in separate buffers and are checked
+// on their own
+pub fn verify_complex_results(
+    a: &[Complex],
+    b: &[Complex],
+    result_re: &[f32],
+    result_im: &[f32],
+    tolerance: f32,
+    max_reported: usize,
+) -> VerifySummary {
+    let mut summary = VerifySummary::new(max_reported);
+    summary.check_length("b", b.len(), a.len());
+    summary.check_length("re(result)", result_re.len(), a.len());
+    summary.check_length("im(result)", result_im.len(), a.len());
+    let results = result_re.iter().zip(result_im);
+    for (index, ((&a, &b), (&re, &im))) in
+        a.iter().zip(b).zip(results).enumerate()
+    {
+        let expected = a * b;
+        summary.check(index, re, expected.re, "re(a*b)", tolerance);
+        summary.check(index, im, expected.im, "im(a*b)", tolerance);
+    }
+    summary
+}
+
+// each op is checked with its own tolerance, the transcendentals are
+// further from std than the exact ones
+pub fn verify_map_results(
+    input: &[f32],
+    result: &[f32],
+    op: UnaryOp,
+    max_reported: usize,
+) -> VerifySummary {
+    let mut summary = VerifySummary::new(max_reported);
+    summary.check_length("result", result.len(), input.len());
+    for (index, (&x, &result)) in input.iter().zip(result).enumerate() {
+        summary.check(index, result, op.apply(x), op.symbol(), op.tolerance());
+    }
+    summary
+}
+
+fn expected_op<T: Element>(
+    a: T,
+    b: T,
+    op: BinaryOp,
+    clamp: Option<ClampRange>,
+) -> f32 {
+    let expected = op.apply(a.to_f32(), b.to_f32());
+    clamp.map_or(expected, |range| range.apply(expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use half::f16;
+
+    use super::*;
+
+    const TOLERANCE: f32 = 0.000001;
+    const MAX_REPORTED: usize = 8;
+
+    fn inputs() -> (Vec<f32>, Vec<f32>) {
+        (vec![0.25, 1.5, -3.0, 1e6], vec![0.5, 2.0, 4.0, 3e6])
+    }
+
+    fn expected(a: &[f32], b: &[f32], op: BinaryOp) -> Vec<f32> {
+        a.iter().zip(b).map(|(&a, &b)| op.apply(a, b)).collect()
+    }
+
+    #[test]
+    fn accepts_exact_results_for_every_op() {
+        let (a, b) = inputs();
+        for op in BinaryOp::ALL {
+            let result = expected(&a, &b, op);
+            assert!(
+                verify_results(
+                    &a,
+                    &b,
+                    &result,
+                    op,
+                    None,
+                    TOLERANCE,
+                    MAX_REPORTED
+                )
+                .is_ok()
+            );
+        }
+    }
+
+    #[test]
+    fn reports_first_corrupted_index() {
+        let (a, b) = inputs();
+        let mut result = expected(&a, &b, BinaryOp::Add);
+        result[1] = 0.0;
+        result[2] = 0.0;
+
+        let err = verify_results(
+            &a,
+            &b,
+            &result,
+            BinaryOp::Add,
+            None,
+            TOLERANCE,
+            MAX_REPORTED,
+        )
+        .mismatches
+        .remove(0);
+        assert_eq!(err.index, 1);
+        assert_eq!(err.result, 0.0);
+        assert_eq!(err.expected, 3.5);
+    }
+
+    #[test]
+    fn counts_every_mismatch_but_reports_only_the_first() {
+        let a = vec![1.0; 100];
+        let b = vec![2.0; 100];
+        let result = (0..100)
+            .map(|i| if i % 10 == 0 { 3.5 } else { 3.0 })
+            .collect::<Vec<_>>();
+
+        let summary =
+            verify_results(&a, &b, &result, BinaryOp::Add, None, TOLERANCE, 3);
+        assert_eq!(summary.checked, 100);
+        assert_eq!(summary.mismatch_count, 10);
+        let indices = summary
+            .mismatches
+            .iter()
+            .map(|mismatch| mismatch.index)
+            .collect::<Vec<_>>();
+        assert_eq!(indices, [0, 10, 20]);
+        assert!(summary.to_string().contains("10 of 100 results wrong"));
+    }
+
+    #[test]
+    fn tracks_the_largest_error_even_within_tolerance() {
+        let (a, b) = ([1.0, 1e6], [1.0, 3e6]);
+        let result = [2.0 + TOLERANCE / 2.0, 4e6 + 2.0];
+
+        let summary = verify_results(
+            &a,
+            &b,
+            &result,
+            BinaryOp::Add,
+            None,
+            TOLERANCE,
+            MAX_REPORTED,
+        );
+        assert!(summary.is_ok());
+        assert_eq!(summary.max_abs_error, 2.0);
+        assert_eq!(summary.max_rel_error, 2.0 / 4e6);
+    }
+
+    #[test]
+    fn flags_nan_results() {
+        let (a, b) = inputs();
+        let mut result = expected(&a, &b, BinaryOp::Mul);
+        result[0] = f32::NAN;
+
+        let err = verify_results(
+            &a,
+            &b,
+            &result,
+            BinaryOp::Mul,
+            None,
+            TOLERANCE,
+            MAX_REPORTED,
+        )
+        .mismatches
+        .remove(0);
+        assert_eq!(err.index, 0);
+    }
+
+    #[test]
+    fn tolerance_is_absolute_below_one() {
+        let (a, b) = ([0.25], [0.5]);
+        let within = [0.75 + TOLERANCE / 2.0];
+        let outside = [0.75 + TOLERANCE * 4.0];
+
+        assert!(
+            verify_results(
+                &a,
+                &b,
+                &within,
+                BinaryOp::Add,
+                None,
+                TOLERANCE,
+                MAX_REPORTED
+            )
+            .is_ok()
+        );
+        assert!(
+            !verify_results(
+                &a,
+                &b,
+                &outside,
+                BinaryOp::Add,
+                None,
+                TOLERANCE,
+                MAX_REPORTED
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn tolerance_scales_with_large_values() {
+        let (a, b) = ([1e6], [3e6]);
+        // one ulp at 4e6 is 0.25, well inside 4e6 * 1e-6 = 4.0
+        let within = [4e6 + 0.25];
+        let outside = [4e6 + 8.0];
+
+        assert!(
+            verify_results(
+                &a,
+                &b,
+                &within,
+                BinaryOp::Add,
+                None,
+                TOLERANCE,
+                MAX_REPORTED
+            )
+            .is_ok()
+        );
+        assert!(
+            !verify_results(
+                &a,
+                &b,
+                &outside,
+                BinaryOp::Add,
+                None,
+                TOLERANCE,
+                MAX_REPORTED
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn half_results_need_half_tolerance() {
+        let (a, b) = ([f16::from_f32(1.0)], [f16::from_f32(3.0)]);
+        // 1/3 rounded to half is off by ~7e-5, far beyond the f32 tolerance
+        let result = [f16::from_f32(1.0 / 3.0)];
+
+        assert!(
+            !verify_results(
+                &a,
+                &b,
+                &result,
+                BinaryOp::Div,
+                None,
+                TOLERANCE,
+                MAX_REPORTED
+            )
+            .is_ok()
+        );
+        assert!(
+            verify_results(
+                &a,
+                &b,
+                &result,
+                BinaryOp::Div,
+                None,
+                f16::TOLERANCE,
+                MAX_REPORTED
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn zero_tolerance_rejects_one_ulp() {
+        let (a, b) = ([1.0], [2.0]);
+        let result = [f32::from_bits(3.0f32.to_bits() + 1)];
+
+        assert!(
+            !verify_results(
+                &a,
+                &b,
+                &result,
+                BinaryOp::Add,
+                None,
+                0.0,
+                MAX_REPORTED
+            )
+            .is_ok()
+        );
+        assert!(
+            verify_results(
+                &a,
+                &b,
+                &result,
+                BinaryOp::Add,
+                None,
+                TOLERANCE,
+                MAX_REPORTED
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn clamped_results_are_checked_against_the_range() {
+        let (a, b) = inputs();
+        let range = ClampRange { min: 0.0, max: 1.0 };
+        let unclamped = expected(&a, &b, BinaryOp::Add);
+        let clamped = [0.75, 1.0, 1.0, 1.0];
+
+        assert!(
+            verify_results(
+                &a,
+                &b,
+                &clamped,
+                BinaryOp::Add,
+                Some(range),
+                TOLERANCE,
+                MAX_REPORTED
+            )
+            .is_ok()
+        );
+        let err = verify_results(
+            &a,
+            &b,
+            &unclamped,
+            BinaryOp::Add,
+            Some(range),
+            TOLERANCE,
+            MAX_REPORTED,
+        )
+        .mismatches
+        .remove(0);
+        assert_eq!(err.index, 1);
+        assert_eq!(err.expected, 1.0);
+    }
+
+    #[test]
+    fn short_results_fail_even_when_the_overlap_matches() {
+        let (a, b) = inputs();
+        let result = expected(&a, &b, BinaryOp::Add);
+
+        let summary = verify_results(
+            &a,
+            &b,
+            &result[..3],
+            BinaryOp::Add,
+            None,
+            TOLERANCE,
+            MAX_REPORTED,
+        );
+        assert!(!summary.is_ok());
+        assert_eq!(summary.mismatch_count, 0);
+        assert_eq!(
+            summary.length_mismatch,
+            Some(LengthMismatch {
+                name: "result",
+                len: 3,
+                expected: 4,
+            })
+        );
+        assert!(summary.to_string().contains("result has 3 elements"));
+    }
+
+    #[test]
+    fn complex_parts_of_different_lengths_fail() {
+        let a = [Complex { re: 1.0, im: 2.0 }, Complex { re: -0.5, im: 0.0 }];
+        let b = [Complex { re: 3.0, im: -1.0 }, Complex { re: 2.0, im: 4.0 }];
+
+        let summary = verify_complex_results(
+            &a,
+            &b,
+            &[5.0, -1.0],
+            &[5.0],
+            TOLERANCE,
+            MAX_REPORTED,
+        );
+        assert!(!summary.is_ok());
+        assert_eq!(summary.length_mismatch.unwrap().name, "im(result)");
+    }
+
+    #[test]
+    fn complex_results_check_both_parts() {
+        let a = [Complex { re: 1.0, im: 2.0 }, Complex { re: -0.5, im: 0.0 }];
+        let b = [Complex { re: 3.0, im: -1.0 }, Complex { re: 2.0, im: 4.0 }];
+        // (1+2i)(3-i) = 5+5i, (-0.5)(2+4i) = -1-2i
+        let re = [5.0, -1.0];
+        let im = [5.0, -2.0];
+
+        assert!(
+            verify_complex_results(&a, &b, &re, &im, TOLERANCE, MAX_REPORTED)
+                .is_ok()
+        );
+
+        let err = verify_complex_results(
+            &a,
+            &b,
+            &re,
+            &[5.0, 2.0],
+            TOLERANCE,
+            MAX_REPORTED,
+        )
+        .mismatches
+        .remove(0);
+        assert_eq!(err.index, 1);
+        assert_eq!(err.expected, -2.0);
+        assert_eq!(err.expression, "im(a*b)");
+    }
+
+    #[test]
+    fn aos_results_are_checked_per_element() {
+        let (a, b) = inputs();
+        let mut elements = a
+            .iter()
+            .zip(&b)
+            .map(|(&a, &b)| AosElement {
+                a,
+                b,
+                result: BinaryOp::Sub.apply(a, b),
+            })
+            .collect::<Vec<_>>();
+
+        assert!(
+            verify_aos_results(
+                &elements,
+                BinaryOp::Sub,
+                TOLERANCE,
+                MAX_REPORTED
+            )
+            .is_ok()
+        );
+        elements[2].result = 0.0;
+        let err = verify_aos_results(
+            &elements,
+            BinaryOp::Sub,
+            TOLERANCE,
+            MAX_REPORTED,
+        )
+        .mismatches
+        .remove(0);
+        assert_eq!(err.index, 2);
+        assert_eq!(err.expected, -7.0);
+        assert_eq!(err.expression, "a-b");
+    }
+
+    #[test]
+    fn map_results_use_the_op_tolerance() {
+        let input = [-2.0f32, -0.5, 0.0, 1.0, 3.0];
+        for op in UnaryOp::ALL {
+            let x = input.map(|x| x.max(op.input_range().start));
+            let result = x.map(|x| op.apply(x));
+            assert!(verify_map_results(&x, &result, op, MAX_REPORTED).is_ok());
+        }
+
+        // off by more than the exact ops allow but within fast math sin
+        let result = input.map(|x| x.sin() + 0.0001);
+        assert!(
+            verify_map_results(&input, &result, UnaryOp::Sin, MAX_REPORTED)
+                .is_ok()
+        );
+        let result = input.map(|x| -x + 0.0001);
+        let summary =
+            verify_map_results(&input, &result, UnaryOp::Neg, MAX_REPORTED);
+        assert_eq!(summary.mismatch_count, input.len());
+        assert_eq!(summary.mismatches[0].expression, "-x");
+    }
+
+    #[test]
+    fn clamp_range_parses_min_and_max() {
+        assert_eq!(
+            "-1, 1".parse(),
+            Ok(ClampRange {
+                min: -1.0,
+                max: 1.0,
+            })
+        );
+        assert!("1,-1".parse::<ClampRange>().is_err());
+        assert!("nan,1".parse::<ClampRange>().is_err());
+        assert!("1".parse::<ClampRange>().is_err());
+    }
+}
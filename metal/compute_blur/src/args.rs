@@ -0,0 +1,75 @@
+use metal_common::{DevicePreference, next_value};
+
+pub const USAGE: &str = "usage: compute_blur \
+    [--device default|high|low|<name>] [--width N] [--height N] \
+    [--radius N]";
+
+const DEFAULT_WIDTH: usize = 1024;
+const DEFAULT_HEIGHT: usize = 768;
+const DEFAULT_RADIUS: usize = 4;
+// the weights go through set_bytes and each row of the tile keeps a
+// radius wide apron on both sides in threadgroup memory, so cap it
+pub const MAX_RADIUS: usize = 32;
+
+pub struct Args {
+    pub device: DevicePreference,
+    pub width: usize,
+    pub height: usize,
+    pub radius: usize,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            device: DevicePreference::default(),
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            radius: DEFAULT_RADIUS,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut args = Args::default();
+        let mut iter = std::env::args().skip(1);
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--device" => {
+                    let Ok(device) = next_value(&mut iter, &arg)?.parse();
+                    args.device = device;
+                }
+                "--width" => {
+                    args.width = next_value(&mut iter, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|&width| width > 0)
+                        .ok_or("--width must be a positive integer")?;
+                }
+                "--height" => {
+                    args.height = next_value(&mut iter, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|&height| height > 0)
+                        .ok_or("--height must be a positive integer")?;
+                }
+                "--radius" => {
+                    args.radius = next_value(&mut iter, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|&radius| radius <= MAX_RADIUS)
+                        .ok_or_else(|| {
+                            format!(
+                                "--radius must be an integer up to {}",
+                                MAX_RADIUS
+                            )
+                        })?;
+                }
+                other => return Err(format!("unknown argument: {}", other)),
+            }
+        }
+
+        Ok(args)
+    }
+}
@@ -0,0 +1,54 @@
+#include <metal_stdlib>
+using namespace metal;
+
+typedef struct
+{
+    uint width;
+    uint height;
+    uint radius;
+    uint horizontal;
+} BlurParams;
+
+// one direction of a separable gaussian. each threadgroup first copies its
+// tile plus a radius wide apron on both ends of the blur axis into
+// threadgroup memory, so every input texel is read from device memory once
+// per group instead of once per tap. reads past the image edge clamp, and
+// threads past it still help load the tile but write nothing, for devices
+// that have to dispatch whole threadgroups
+kernel void blur_pass(device const float* input,
+                      device float* output,
+                      constant BlurParams& params,
+                      constant float* weights,
+                      threadgroup float* tile [[threadgroup(0)]],
+                      uint2 gid [[thread_position_in_grid]],
+                      uint2 lid [[thread_position_in_threadgroup]],
+                      uint2 group_size [[threads_per_threadgroup]])
+{
+    bool horizontal = params.horizontal != 0;
+    uint2 axis = horizontal ? uint2(1, 0) : uint2(0, 1);
+    uint along_lid = horizontal ? lid.x : lid.y;
+    uint along_size = horizontal ? group_size.x : group_size.y;
+    uint row = horizontal ? lid.y : lid.x;
+    uint tile_length = along_size + 2 * params.radius;
+
+    int2 origin = int2(gid - lid * axis);
+    int2 limit = int2(params.width, params.height) - 1;
+    for (uint i = along_lid; i < tile_length; i += along_size)
+    {
+        int2 coord = origin + int2(axis) * (int(i) - int(params.radius));
+        coord = clamp(coord, int2(0), limit);
+        tile[row * tile_length + i] = input[coord.y * params.width + coord.x];
+    }
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+    if (gid.x >= params.width || gid.y >= params.height)
+    {
+        return;
+    }
+
+    float sum = 0.0;
+    for (uint tap = 0; tap <= 2 * params.radius; tap++)
+    {
+        sum += weights[tap] * tile[row * tile_length + along_lid + tap];
+    }
+    output[gid.y * params.width + gid.x] = sum;
+}
@@ -0,0 +1,273 @@
+mod args;
+
+use std::ffi::c_void;
+use std::mem::{size_of, size_of_val};
+use std::time::Instant;
+
+use args::{Args, USAGE};
+use log::{error, info};
+use metal::*;
+use metal_common::{
+    MetalContext, MetalError, check_completion, check_threadgroup_memory,
+    supports_non_uniform_threadgroups,
+};
+use objc::rc::autoreleasepool;
+
+const TOLERANCE: f32 = 1e-5;
+const TILE_SIZE: u64 = 16;
+
+#[repr(C)]
+struct BlurParams {
+    width: u32,
+    height: u32,
+    radius: u32,
+    horizontal: u32,
+}
+
+fn main() {
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("info"),
+    )
+    .init();
+
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(&args) {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), MetalError> {
+    let (width, height) = (args.width, args.height);
+
+    autoreleasepool(|| {
+        let context = MetalContext::new(&args.device)?;
+        let device = &context.device;
+        info!("Using device: {}", device.name());
+        info!("Image size: {}x{}, radius {}", width, height, args.radius);
+
+        let image = random_image(width * height);
+        let weights = gaussian_weights(args.radius);
+
+        let image_size = (width * height * size_of::<f32>()) as u64;
+        let input_buffer = context.make_shared_buffer(&image)?;
+        // only the gpu touches the horizontally blurred intermediate
+        let scratch_buffer = context
+            .new_buffer(image_size, MTLResourceOptions::StorageModePrivate)?;
+        let output_buffer = context
+            .new_buffer(image_size, MTLResourceOptions::StorageModeShared)?;
+
+        let library = context.compile_library(include_str!("blur.metal"))?;
+        let pipeline_state = context.make_pipeline(&library, "blur_pass")?;
+
+        let threadgroup_size = MTLSize {
+            width: TILE_SIZE,
+            height: TILE_SIZE.min(
+                pipeline_state.max_total_threads_per_threadgroup() / TILE_SIZE,
+            ),
+            depth: 1,
+        };
+        info!(
+            "Threadgroup tile: {}x{}",
+            threadgroup_size.width, threadgroup_size.height
+        );
+        let memory_length = check_threadgroup_memory(
+            device,
+            &pipeline_state,
+            tile_memory_length(threadgroup_size, args.radius),
+        )?;
+        let non_uniform = supports_non_uniform_threadgroups(device);
+
+        let command_buffer = context.command_queue.new_command_buffer();
+        for (input, output, horizontal) in [
+            (&input_buffer, &scratch_buffer, true),
+            (&scratch_buffer, &output_buffer, false),
+        ] {
+            let params = BlurParams {
+                width: width as u32,
+                height: height as u32,
+                radius: args.radius as u32,
+                horizontal: horizontal as u32,
+            };
+            encode_pass(
+                command_buffer,
+                &pipeline_state,
+                input,
+                output,
+                &params,
+                &weights,
+                threadgroup_size,
+                memory_length,
+                non_uniform,
+            );
+        }
+
+        let start = Instant::now();
+        command_buffer.commit();
+        command_buffer.wait_until_completed();
+        check_completion(command_buffer)?;
+        println!("GPU time: {:?}", start.elapsed());
+
+        let result = unsafe {
+            std::slice::from_raw_parts(
+                output_buffer.contents() as *const f32,
+                width * height,
+            )
+        };
+
+        let start = Instant::now();
+        let expected = blur_on_cpu(&image, width, height, &weights);
+        println!("CPU time: {:?}", start.elapsed());
+
+        verify_results(result, &expected, width, height, args.radius)
+    })
+}
+
+// each row of the tile along the blur axis carries the apron too, sized for
+// whichever pass needs more so both can share it
+fn tile_memory_length(threadgroup_size: MTLSize, radius: usize) -> u64 {
+    let (width, height) = (threadgroup_size.width, threadgroup_size.height);
+    let apron = 2 * radius as u64;
+    let floats = ((width + apron) * height).max((height + apron) * width);
+    (floats * size_of::<f32>() as u64).next_multiple_of(16)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn encode_pass(
+    command_buffer: &CommandBufferRef,
+    pipeline_state: &ComputePipelineStateRef,
+    input: &BufferRef,
+    output: &BufferRef,
+    params: &BlurParams,
+    weights: &[f32],
+    threadgroup_size: MTLSize,
+    memory_length: u64,
+    non_uniform: bool,
+) {
+    let compute_encoder = command_buffer.new_compute_command_encoder();
+    compute_encoder.set_compute_pipeline_state(pipeline_state);
+    compute_encoder.set_buffer(0, Some(input), 0);
+    compute_encoder.set_buffer(1, Some(output), 0);
+    compute_encoder.set_bytes(
+        2,
+        size_of::<BlurParams>() as u64,
+        params as *const BlurParams as *const c_void,
+    );
+    compute_encoder.set_bytes(
+        3,
+        size_of_val(weights) as u64,
+        weights.as_ptr() as *const c_void,
+    );
+    compute_encoder.set_threadgroup_memory_length(0, memory_length);
+
+    let grid_size = MTLSize {
+        width: params.width as u64,
+        height: params.height as u64,
+        depth: 1,
+    };
+    if non_uniform {
+        compute_encoder.dispatch_threads(grid_size, threadgroup_size);
+    } else {
+        // the edge groups run past the image, the kernel skips their writes
+        let group_count = MTLSize {
+            width: grid_size.width.div_ceil(threadgroup_size.width),
+            height: grid_size.height.div_ceil(threadgroup_size.height),
+            depth: 1,
+        };
+        compute_encoder.dispatch_thread_groups(group_count, threadgroup_size);
+    }
+    compute_encoder.end_encoding();
+}
+
+fn random_image(length: usize) -> Vec<f32> {
+    (0..length).map(|_| rand::random::<f32>()).collect()
+}
+
+// normalized 1d kernel; the same weights are used for both passes
+fn gaussian_weights(radius: usize) -> Vec<f32> {
+    let sigma = (radius as f32 / 2.0).max(0.5);
+    let weights = (0..=2 * radius)
+        .map(|tap| {
+            let offset = tap as f32 - radius as f32;
+            (-offset * offset / (2.0 * sigma * sigma)).exp()
+        })
+        .collect::<Vec<_>>();
+    let total: f32 = weights.iter().sum();
+    weights.iter().map(|weight| weight / total).collect()
+}
+
+fn blur_on_cpu(
+    image: &[f32],
+    width: usize,
+    height: usize,
+    weights: &[f32],
+) -> Vec<f32> {
+    let horizontal = blur_axis(image, width, height, weights, true);
+    blur_axis(&horizontal, width, height, weights, false)
+}
+
+fn blur_axis(
+    input: &[f32],
+    width: usize,
+    height: usize,
+    weights: &[f32],
+    horizontal: bool,
+) -> Vec<f32> {
+    let radius = (weights.len() / 2) as isize;
+    let mut output = vec![0.0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            output[y * width + x] = weights
+                .iter()
+                .enumerate()
+                .map(|(tap, weight)| {
+                    let offset = tap as isize - radius;
+                    let (sx, sy) = if horizontal {
+                        (x as isize + offset, y as isize)
+                    } else {
+                        (x as isize, y as isize + offset)
+                    };
+                    let sx = sx.clamp(0, width as isize - 1) as usize;
+                    let sy = sy.clamp(0, height as isize - 1) as usize;
+                    weight * input[sy * width + sx]
+                })
+                .sum();
+        }
+    }
+    output
+}
+
+// both sides clamp at the border, but only interior pixels are compared so
+// the check doesn't depend on how the edges are handled
+fn verify_results(
+    result: &[f32],
+    expected: &[f32],
+    width: usize,
+    height: usize,
+    radius: usize,
+) -> Result<(), MetalError> {
+    for y in radius..height.saturating_sub(radius) {
+        for x in radius..width.saturating_sub(radius) {
+            let (result_val, expected_val) =
+                (result[y * width + x], expected[y * width + x]);
+            if (result_val - expected_val).abs()
+                > TOLERANCE * expected_val.abs().max(1.0)
+            {
+                return Err(MetalError::VerificationFailed(format!(
+                    "x={} y={} result={} vs {}",
+                    x, y, result_val, expected_val
+                )));
+            }
+        }
+    }
+
+    println!("Compute results as expected");
+    Ok(())
+}
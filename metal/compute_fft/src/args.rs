@@ -0,0 +1,46 @@
+use metal_common::{DevicePreference, next_value};
+
+pub const USAGE: &str = "usage: compute_fft \
+    [--device default|high|low|<name>] [--len N]";
+
+const DEFAULT_LENGTH: usize = 1024;
+
+pub struct Args {
+    pub device: DevicePreference,
+    pub length: usize,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            device: DevicePreference::default(),
+            length: DEFAULT_LENGTH,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut args = Args::default();
+        let mut iter = std::env::args().skip(1);
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--device" => {
+                    let Ok(device) = next_value(&mut iter, &arg)?.parse();
+                    args.device = device;
+                }
+                "--len" => {
+                    args.length = next_value(&mut iter, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|&len: &usize| len > 1 && len.is_power_of_two())
+                        .ok_or("--len must be a power of two above 1")?;
+                }
+                other => return Err(format!("unknown argument: {}", other)),
+            }
+        }
+
+        Ok(args)
+    }
+}
@@ -0,0 +1,75 @@
+use std::f64::consts::TAU;
+
+use metal_common::Complex;
+
+// e^(-2 pi i k / n) for k below n / 2, the only twiddles a radix-2 fft of
+// length n needs. built in f64 so the table adds no error of its own
+pub fn twiddles(n: usize) -> Vec<Complex> {
+    (0..n / 2)
+        .map(|k| {
+            let (sin, cos) = (-TAU * k as f64 / n as f64).sin_cos();
+            Complex::new(cos as f32, sin as f32)
+        })
+        .collect()
+}
+
+// the O(n^2) definition, accumulated in f64 so it can serve as the exact
+// reference for the gpu fft
+pub fn dft(input: &[Complex]) -> Vec<Complex> {
+    let n = input.len();
+    (0..n)
+        .map(|k| {
+            let (mut re, mut im) = (0.0f64, 0.0f64);
+            for (j, x) in input.iter().enumerate() {
+                // reduce first so the angle stays small for large k * j
+                let angle = -TAU * ((k * j) % n) as f64 / n as f64;
+                let (sin, cos) = angle.sin_cos();
+                re += x.re as f64 * cos - x.im as f64 * sin;
+                im += x.re as f64 * sin + x.im as f64 * cos;
+            }
+            Complex::new(re as f32, im as f32)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Complex, b: Complex) {
+        assert!((a - b).abs() < 1e-5, "{:?} vs {:?}", a, b);
+    }
+
+    #[test]
+    fn impulse_transforms_to_ones() {
+        let mut input = vec![Complex::default(); 8];
+        input[0] = Complex::new(1.0, 0.0);
+        for value in dft(&input) {
+            assert_close(value, Complex::new(1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn single_frequency_lands_in_one_bin() {
+        let n = 16;
+        let input = (0..n)
+            .map(|j| {
+                let (sin, cos) = (TAU * 3.0 * j as f64 / n as f64).sin_cos();
+                Complex::new(cos as f32, sin as f32)
+            })
+            .collect::<Vec<_>>();
+        for (k, value) in dft(&input).into_iter().enumerate() {
+            let expected = if k == 3 { n as f32 } else { 0.0 };
+            assert_close(value, Complex::new(expected, 0.0));
+        }
+    }
+
+    #[test]
+    fn twiddles_walk_the_lower_half_circle() {
+        let table = twiddles(8);
+        assert_eq!(table.len(), 4);
+        assert_close(table[0], Complex::new(1.0, 0.0));
+        assert_close(table[2], Complex::new(0.0, -1.0));
+        assert_close(table[3] * table[1], Complex::new(-1.0, 0.0));
+    }
+}
@@ -0,0 +1,46 @@
+#include <metal_stdlib>
+using namespace metal;
+
+// input is (re, im) pairs, output[reverse(i)] = input[i] puts the samples in
+// the order the in place butterflies expect
+kernel void bit_reverse(device const float2* input,
+                        device float2* output,
+                        constant uint& log2n,
+                        constant uint& count,
+                        uint gid [[thread_position_in_grid]])
+{
+    if (gid >= count)
+    {
+        return;
+    }
+
+    output[reverse_bits(gid) >> (32 - log2n)] = input[gid];
+}
+
+// one stage of an iterative cooley-tukey fft, each thread combines one pair
+// half_span apart. the twiddle table holds e^(-2 pi i k / n) for k < n / 2,
+// so a stage with span m steps through it n / m entries at a time
+kernel void butterfly(device float2* data,
+                      device const float2* twiddles,
+                      constant uint& half_span,
+                      constant uint& count,
+                      uint gid [[thread_position_in_grid]])
+{
+    uint n = count;
+    if (gid >= n / 2)
+    {
+        return;
+    }
+
+    uint k = gid % half_span;
+    uint i = (gid / half_span) * half_span * 2 + k;
+    uint j = i + half_span;
+
+    float2 w = twiddles[k * (n / (half_span * 2))];
+    float2 b = data[j];
+    float2 t = float2(w.x * b.x - w.y * b.y, w.x * b.y + w.y * b.x);
+    float2 a = data[i];
+
+    data[i] = a + t;
+    data[j] = a - t;
+}
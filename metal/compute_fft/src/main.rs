@@ -0,0 +1,218 @@
+mod args;
+mod dft;
+
+use std::mem::size_of_val;
+use std::time::Instant;
+
+use args::{Args, USAGE};
+use dft::{dft, twiddles};
+use log::{error, info};
+use metal::*;
+use metal_common::{
+    Complex, MetalContext, MetalError, check_completion, set_u32,
+};
+use objc::rc::autoreleasepool;
+
+// the cpu reference is O(n^2), past this it takes longer than it's worth
+const MAX_VERIFY_LENGTH: usize = 1 << 12;
+
+struct Fft {
+    context: MetalContext,
+    bit_reverse_pipeline: ComputePipelineState,
+    butterfly_pipeline: ComputePipelineState,
+}
+
+impl Fft {
+    fn new(context: MetalContext) -> Result<Self, MetalError> {
+        let library = context.compile_library(include_str!("fft.metal"))?;
+        let bit_reverse_pipeline =
+            context.make_pipeline(&library, "bit_reverse")?;
+        let butterfly_pipeline =
+            context.make_pipeline(&library, "butterfly")?;
+
+        Ok(Fft {
+            context,
+            bit_reverse_pipeline,
+            butterfly_pipeline,
+        })
+    }
+
+    // forward transform of a power of two length, unnormalized like the dft
+    fn fft(&self, input: &[Complex]) -> Result<Vec<Complex>, MetalError> {
+        let n = input.len();
+        assert!(n.is_power_of_two(), "fft length must be a power of two");
+        if n == 1 {
+            return Ok(input.to_vec());
+        }
+
+        autoreleasepool(|| {
+            let command_buffer =
+                self.context.command_queue.new_command_buffer();
+            let input_buffer = self.context.make_shared_buffer(input)?;
+            let data = self.context.new_buffer(
+                size_of_val(input) as u64,
+                MTLResourceOptions::StorageModeShared,
+            )?;
+            let twiddle_buffer =
+                self.context.make_shared_buffer(&twiddles(n))?;
+
+            self.encode_bit_reverse(command_buffer, &input_buffer, &data, n);
+            let mut half_span = 1;
+            while half_span < n {
+                self.encode_butterflies(
+                    command_buffer,
+                    &data,
+                    &twiddle_buffer,
+                    half_span,
+                    n,
+                );
+                half_span *= 2;
+            }
+
+            command_buffer.commit();
+            command_buffer.wait_until_completed();
+            check_completion(command_buffer)?;
+
+            Ok(unsafe {
+                std::slice::from_raw_parts(data.contents() as *const Complex, n)
+            }
+            .to_vec())
+        })
+    }
+
+    fn encode_bit_reverse(
+        &self,
+        command_buffer: &CommandBufferRef,
+        input: &BufferRef,
+        output: &BufferRef,
+        n: usize,
+    ) {
+        let compute_encoder = command_buffer.new_compute_command_encoder();
+        compute_encoder.set_compute_pipeline_state(&self.bit_reverse_pipeline);
+        compute_encoder.set_buffer(0, Some(input), 0);
+        compute_encoder.set_buffer(1, Some(output), 0);
+        set_u32(compute_encoder, 2, n.ilog2());
+        set_u32(compute_encoder, 3, n as u32);
+        dispatch_thread_groups(compute_encoder, &self.bit_reverse_pipeline, n);
+        compute_encoder.end_encoding();
+    }
+
+    // every stage reads what the previous one wrote, the encoder boundary
+    // orders them
+    fn encode_butterflies(
+        &self,
+        command_buffer: &CommandBufferRef,
+        data: &BufferRef,
+        twiddles: &BufferRef,
+        half_span: usize,
+        n: usize,
+    ) {
+        let compute_encoder = command_buffer.new_compute_command_encoder();
+        compute_encoder.set_compute_pipeline_state(&self.butterfly_pipeline);
+        compute_encoder.set_buffer(0, Some(data), 0);
+        compute_encoder.set_buffer(1, Some(twiddles), 0);
+        set_u32(compute_encoder, 2, half_span as u32);
+        set_u32(compute_encoder, 3, n as u32);
+        dispatch_thread_groups(
+            compute_encoder,
+            &self.butterfly_pipeline,
+            n / 2,
+        );
+        compute_encoder.end_encoding();
+    }
+}
+
+// whole threadgroups, the kernels skip the threads past count
+fn dispatch_thread_groups(
+    compute_encoder: &ComputeCommandEncoderRef,
+    pipeline_state: &ComputePipelineStateRef,
+    count: usize,
+) {
+    let count = count as u64;
+    let width = pipeline_state
+        .max_total_threads_per_threadgroup()
+        .min(count);
+    let group_count = MTLSize {
+        width: count.div_ceil(width),
+        height: 1,
+        depth: 1,
+    };
+    let threadgroup_size = MTLSize {
+        width,
+        height: 1,
+        depth: 1,
+    };
+    compute_encoder.dispatch_thread_groups(group_count, threadgroup_size);
+}
+
+fn main() {
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("info"),
+    )
+    .init();
+
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(&args) {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), MetalError> {
+    let context = MetalContext::new(&args.device)?;
+    info!("Using device: {}", context.device.name());
+    info!("FFT length: {}", args.length);
+
+    let fft = Fft::new(context)?;
+    let input: Vec<Complex> = (0..args.length)
+        .map(|_| Complex::new(rand::random(), rand::random()))
+        .collect();
+
+    let start = Instant::now();
+    let gpu_result = fft.fft(&input)?;
+    println!("GPU time: {:?}", start.elapsed());
+
+    if args.length > MAX_VERIFY_LENGTH {
+        info!(
+            "Skipping the cpu dft check above length {}",
+            MAX_VERIFY_LENGTH
+        );
+        return Ok(());
+    }
+
+    let start = Instant::now();
+    let cpu_result = dft(&input);
+    println!("CPU dft time: {:?}", start.elapsed());
+
+    verify_results(&gpu_result, &cpu_result)
+}
+
+fn verify_results(
+    gpu_result: &[Complex],
+    cpu_result: &[Complex],
+) -> Result<(), MetalError> {
+    // each output goes through log2(n) butterflies, and the rounding in the
+    // sums is bounded by the largest value they reach, the dc bin for
+    // random positive input, rather than by the output itself
+    let n = cpu_result.len();
+    let largest = cpu_result.iter().map(|x| x.abs()).fold(1.0, f32::max);
+    let tolerance = 4.0 * f32::EPSILON * n.ilog2() as f32 * largest;
+    for (i, (&gpu, &cpu)) in gpu_result.iter().zip(cpu_result).enumerate() {
+        let error = (gpu - cpu).abs();
+        if error.is_nan() || error > tolerance {
+            return Err(MetalError::VerificationFailed(format!(
+                "index={} gpu={:?} vs {:?}=cpu (tolerance {})",
+                i, gpu, cpu, tolerance
+            )));
+        }
+    }
+    println!("Compute results as expected: {} bins", n);
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use metal_common::{DevicePreference, next_value};
+
+pub const USAGE: &str =
+    "usage: compute_matmul [--device default|high|low|<name>] [--n N]";
+
+const DEFAULT_N: usize = 256;
+
+pub struct Args {
+    pub device: DevicePreference,
+    pub n: usize,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            device: DevicePreference::default(),
+            n: DEFAULT_N,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut args = Args::default();
+        let mut iter = std::env::args().skip(1);
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--device" => {
+                    let Ok(device) = next_value(&mut iter, &arg)?.parse();
+                    args.device = device;
+                }
+                "--n" => {
+                    args.n = next_value(&mut iter, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or("--n must be a positive integer")?;
+                }
+                other => return Err(format!("unknown argument: {}", other)),
+            }
+        }
+
+        Ok(args)
+    }
+}
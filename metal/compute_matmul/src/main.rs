@@ -0,0 +1,151 @@
+mod args;
+
+use std::mem::size_of;
+use std::time::Instant;
+
+use args::{Args, USAGE};
+use log::{error, info};
+use metal::*;
+use metal_common::{MetalContext, MetalError, check_completion, set_u32};
+use objc::rc::autoreleasepool;
+
+const TOLERANCE: f32 = 1e-4;
+
+fn main() {
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("info"),
+    )
+    .init();
+
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(&args) {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), MetalError> {
+    let n = args.n;
+
+    autoreleasepool(|| {
+        let context = MetalContext::new(&args.device)?;
+        let device = &context.device;
+        info!("Using device: {}", device.name());
+        info!("Matrix size: {}x{}", n, n);
+
+        let a = random_matrix(n);
+        let b = random_matrix(n);
+
+        let buffer_a = context.make_shared_buffer(&a)?;
+        let buffer_b = context.make_shared_buffer(&b)?;
+        let result_buffer = context.new_buffer(
+            (n * n * size_of::<f32>()) as u64,
+            MTLResourceOptions::StorageModeShared,
+        )?;
+
+        let library = context.compile_library(include_str!("matmul.metal"))?;
+        let pipeline_state = context.make_pipeline(&library, "matmul")?;
+
+        let command_buffer = context.command_queue.new_command_buffer();
+        let compute_encoder = command_buffer.new_compute_command_encoder();
+
+        compute_encoder.set_compute_pipeline_state(&pipeline_state);
+        compute_encoder.set_buffer(0, Some(&buffer_a), 0);
+        compute_encoder.set_buffer(1, Some(&buffer_b), 0);
+        compute_encoder.set_buffer(2, Some(&result_buffer), 0);
+        set_u32(compute_encoder, 3, n as u32);
+
+        let grid_size = MTLSize {
+            width: n as u64,
+            height: n as u64,
+            depth: 1,
+        };
+        let threadgroup_size = threadgroup_tile(&pipeline_state);
+        info!(
+            "Threadgroup tile: {}x{}",
+            threadgroup_size.width, threadgroup_size.height
+        );
+
+        compute_encoder.dispatch_threads(grid_size, threadgroup_size);
+        compute_encoder.end_encoding();
+
+        let start = Instant::now();
+        command_buffer.commit();
+        command_buffer.wait_until_completed();
+        check_completion(command_buffer)?;
+        println!("GPU time: {:?}", start.elapsed());
+
+        let result = unsafe {
+            std::slice::from_raw_parts(
+                result_buffer.contents() as *const f32,
+                n * n,
+            )
+        };
+
+        let start = Instant::now();
+        let expected = matmul_on_cpu(&a, &b, n);
+        println!("CPU time: {:?}", start.elapsed());
+
+        verify_results(result, &expected, n)
+    })
+}
+
+fn threadgroup_tile(pipeline_state: &ComputePipelineStateRef) -> MTLSize {
+    let width = pipeline_state.thread_execution_width();
+    let height = pipeline_state.max_total_threads_per_threadgroup() / width;
+
+    MTLSize {
+        width,
+        height,
+        depth: 1,
+    }
+}
+
+fn random_matrix(n: usize) -> Vec<f32> {
+    (0..n * n).map(|_| rand::random::<f32>()).collect()
+}
+
+fn matmul_on_cpu(a: &[f32], b: &[f32], n: usize) -> Vec<f32> {
+    let mut result = vec![0.0; n * n];
+    for row in 0..n {
+        for k in 0..n {
+            let a_val = a[row * n + k];
+            for col in 0..n {
+                result[row * n + col] += a_val * b[k * n + col];
+            }
+        }
+    }
+    result
+}
+
+fn verify_results(
+    result: &[f32],
+    expected: &[f32],
+    n: usize,
+) -> Result<(), MetalError> {
+    for (i, (&result_val, &expected_val)) in
+        result.iter().zip(expected).enumerate()
+    {
+        if (result_val - expected_val).abs()
+            > TOLERANCE * expected_val.abs().max(1.0)
+        {
+            return Err(MetalError::VerificationFailed(format!(
+                "row={} col={} result={} vs {}",
+                i / n,
+                i % n,
+                result_val,
+                expected_val
+            )));
+        }
+    }
+
+    println!("Compute results as expected");
+    Ok(())
+}
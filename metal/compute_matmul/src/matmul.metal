@@ -0,0 +1,23 @@
+#include <metal_stdlib>
+using namespace metal;
+
+kernel void matmul(device const float* inA,
+                   device const float* inB,
+                   device float* result,
+                   constant uint& n,
+                   uint2 gid [[thread_position_in_grid]])
+{
+    if (gid.x >= n || gid.y >= n)
+    {
+        return;
+    }
+
+    uint row = gid.y;
+    uint col = gid.x;
+    float sum = 0.0;
+    for (uint k = 0; k < n; k++)
+    {
+        sum += inA[row * n + k] * inB[k * n + col];
+    }
+    result[row * n + col] = sum;
+}
@@ -0,0 +1,64 @@
+use metal_common::{DevicePreference, next_value};
+
+pub const USAGE: &str = "usage: compute_reduce \
+    [--device default|high|low|<name>] [--len N] [--bins N]";
+
+const DEFAULT_ARRAY_LENGTH: usize = 1 << 20;
+const DEFAULT_BINS: usize = 256;
+// every threadgroup keeps its own copy of the bins in threadgroup memory,
+// which is 32KB on apple gpus
+const MAX_BINS: usize = 8192;
+
+pub struct Args {
+    pub device: DevicePreference,
+    pub array_length: usize,
+    pub bins: usize,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            device: DevicePreference::default(),
+            array_length: DEFAULT_ARRAY_LENGTH,
+            bins: DEFAULT_BINS,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut args = Args::default();
+        let mut iter = std::env::args().skip(1);
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--device" => {
+                    let Ok(device) = next_value(&mut iter, &arg)?.parse();
+                    args.device = device;
+                }
+                "--len" => {
+                    args.array_length = next_value(&mut iter, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|&len| len > 0)
+                        .ok_or("--len must be a positive integer")?;
+                }
+                "--bins" => {
+                    args.bins = next_value(&mut iter, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|&bins| bins > 0 && bins <= MAX_BINS)
+                        .ok_or_else(|| {
+                            format!(
+                                "--bins must be an integer from 1 to {}",
+                                MAX_BINS
+                            )
+                        })?;
+                }
+                other => return Err(format!("unknown argument: {}", other)),
+            }
+        }
+
+        Ok(args)
+    }
+}
@@ -0,0 +1,73 @@
+// compensated summation: the low order bits each add rounds away are carried
+// into the next add, so the error stays around one ulp of the total instead
+// of growing with the number of terms
+pub fn kahan_sum(data: &[f32]) -> f32 {
+    let mut sum = KahanSum::default();
+    for &value in data {
+        sum.add(value);
+    }
+    sum.total
+}
+
+pub fn kahan_scan(data: &[f32]) -> Vec<f32> {
+    let mut sum = KahanSum::default();
+    data.iter().map(|&value| sum.add(value)).collect()
+}
+
+#[derive(Default)]
+struct KahanSum {
+    total: f32,
+    // what the last add rounded away, taken back out of the next term
+    compensation: f32,
+}
+
+impl KahanSum {
+    // returns the running total
+    fn add(&mut self, value: f32) -> f32 {
+        let corrected = value - self.compensation;
+        let next = self.total + corrected;
+        self.compensation = (next - self.total) - corrected;
+        self.total = next;
+        self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_sum_is_zero() {
+        assert_eq!(kahan_sum(&[]), 0.0);
+    }
+
+    #[test]
+    fn small_terms_survive_a_large_total() {
+        // naively every 1.0 after the first rounds away against 2^24
+        let mut data = vec![16_777_216.0];
+        data.extend(std::iter::repeat_n(1.0, 1000));
+        let naive: f32 = data.iter().sum();
+        assert_eq!(naive, 16_777_216.0);
+        assert_eq!(kahan_sum(&data), 16_778_216.0);
+    }
+
+    #[test]
+    fn sum_is_the_last_prefix() {
+        let data = (0..1000).map(|i| 1.0 / (i + 1) as f32).collect::<Vec<_>>();
+        assert_eq!(kahan_sum(&data), *kahan_scan(&data).last().unwrap());
+    }
+
+    #[test]
+    fn scan_matches_double_precision() {
+        let data = (0..100_000)
+            .map(|i| ((i * 7919) % 1000) as f32 / 1000.0)
+            .collect::<Vec<_>>();
+        let mut exact = 0.0f64;
+        for (&value, &prefix) in data.iter().zip(&kahan_scan(&data)) {
+            exact += value as f64;
+            assert!(
+                (prefix as f64 - exact).abs() <= exact * f32::EPSILON as f64
+            );
+        }
+    }
+}
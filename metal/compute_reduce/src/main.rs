@@ -0,0 +1,404 @@
+mod args;
+mod kahan;
+
+use std::mem::size_of;
+use std::time::Instant;
+
+use args::{Args, USAGE};
+use kahan::{kahan_scan, kahan_sum};
+use log::{error, info};
+use metal::*;
+use metal_common::{
+    MetalContext, MetalError, check_completion, check_threadgroup_memory,
+    set_u32,
+};
+use objc::rc::autoreleasepool;
+
+struct Reducer {
+    context: MetalContext,
+    pipeline_state: ComputePipelineState,
+    scan_pipeline: ComputePipelineState,
+    offsets_pipeline: ComputePipelineState,
+    histogram_pipeline: ComputePipelineState,
+    threadgroup_width: u64,
+    // one float per thread, for the reduction and the scan blocks
+    reduce_memory_length: u64,
+    scan_memory_length: u64,
+}
+
+impl Reducer {
+    fn new(context: MetalContext) -> Result<Self, MetalError> {
+        let library = context.compile_library(include_str!("reduce.metal"))?;
+        let pipeline_state = context.make_pipeline(&library, "reduce_sum")?;
+        let scan_pipeline = context.make_pipeline(&library, "scan_blocks")?;
+        let offsets_pipeline =
+            context.make_pipeline(&library, "add_block_offsets")?;
+        let histogram_pipeline =
+            context.make_pipeline(&library, "histogram")?;
+
+        // the tree reduction and the scan both halve or double the stride
+        // each step, so the group width has to be a power of two that every
+        // pipeline can run
+        let max_threads = [
+            &pipeline_state,
+            &scan_pipeline,
+            &offsets_pipeline,
+            &histogram_pipeline,
+        ]
+        .iter()
+        .map(|pipeline| pipeline.max_total_threads_per_threadgroup())
+        .min()
+        .unwrap_or(1);
+        let threadgroup_width = 1 << max_threads.ilog2();
+        let block_length = threadgroup_width * size_of::<f32>() as u64;
+        let reduce_memory_length = check_threadgroup_memory(
+            &context.device,
+            &pipeline_state,
+            block_length,
+        )?;
+        let scan_memory_length = check_threadgroup_memory(
+            &context.device,
+            &scan_pipeline,
+            block_length,
+        )?;
+
+        Ok(Reducer {
+            context,
+            pipeline_state,
+            scan_pipeline,
+            offsets_pipeline,
+            histogram_pipeline,
+            threadgroup_width,
+            reduce_memory_length,
+            scan_memory_length,
+        })
+    }
+
+    fn reduce_sum(&self, data: &[f32]) -> Result<f32, MetalError> {
+        if data.is_empty() {
+            return Ok(0.0);
+        }
+
+        autoreleasepool(|| {
+            let command_buffer =
+                self.context.command_queue.new_command_buffer();
+            let mut input = self.context.make_shared_buffer(data)?;
+            let mut count = data.len() as u64;
+
+            while count > 1 {
+                let group_count = count.div_ceil(self.threadgroup_width);
+                let partials = self.new_float_buffer(group_count)?;
+                self.encode_pass(command_buffer, &input, &partials, count);
+                input = partials;
+                count = group_count;
+            }
+
+            command_buffer.commit();
+            command_buffer.wait_until_completed();
+            check_completion(command_buffer)?;
+
+            Ok(unsafe { *(input.contents() as *const f32) })
+        })
+    }
+
+    fn encode_pass(
+        &self,
+        command_buffer: &CommandBufferRef,
+        input: &BufferRef,
+        partials: &BufferRef,
+        count: u64,
+    ) {
+        let compute_encoder = command_buffer.new_compute_command_encoder();
+        compute_encoder.set_compute_pipeline_state(&self.pipeline_state);
+        compute_encoder.set_buffer(0, Some(input), 0);
+        compute_encoder.set_buffer(1, Some(partials), 0);
+        set_u32(compute_encoder, 2, count as u32);
+        compute_encoder
+            .set_threadgroup_memory_length(0, self.reduce_memory_length);
+
+        self.dispatch_blocks(compute_encoder, count);
+        compute_encoder.end_encoding();
+    }
+
+    fn scan_inclusive(&self, data: &[f32]) -> Result<Vec<f32>, MetalError> {
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        autoreleasepool(|| {
+            let command_buffer =
+                self.context.command_queue.new_command_buffer();
+            let input = self.context.make_shared_buffer(data)?;
+            let count = data.len() as u64;
+            let output = self.new_float_buffer(count)?;
+
+            self.encode_scan(command_buffer, &input, &output, count)?;
+
+            command_buffer.commit();
+            command_buffer.wait_until_completed();
+            check_completion(command_buffer)?;
+
+            Ok(unsafe {
+                std::slice::from_raw_parts(
+                    output.contents() as *const f32,
+                    data.len(),
+                )
+            }
+            .to_vec())
+        })
+    }
+
+    fn encode_scan(
+        &self,
+        command_buffer: &CommandBufferRef,
+        input: &BufferRef,
+        output: &BufferRef,
+        count: u64,
+    ) -> Result<(), MetalError> {
+        let group_count = count.div_ceil(self.threadgroup_width);
+        let block_sums = self.new_float_buffer(group_count)?;
+
+        let compute_encoder = command_buffer.new_compute_command_encoder();
+        compute_encoder.set_compute_pipeline_state(&self.scan_pipeline);
+        compute_encoder.set_buffer(0, Some(input), 0);
+        compute_encoder.set_buffer(1, Some(output), 0);
+        compute_encoder.set_buffer(2, Some(&block_sums), 0);
+        set_u32(compute_encoder, 3, count as u32);
+        compute_encoder
+            .set_threadgroup_memory_length(0, self.scan_memory_length);
+        self.dispatch_blocks(compute_encoder, count);
+        compute_encoder.end_encoding();
+
+        if group_count == 1 {
+            return Ok(());
+        }
+
+        // the block totals are scanned the same way, recursing until they
+        // fit in a single group, then folded back into every later block
+        let block_offsets = self.new_float_buffer(group_count)?;
+        self.encode_scan(
+            command_buffer,
+            &block_sums,
+            &block_offsets,
+            group_count,
+        )?;
+
+        let compute_encoder = command_buffer.new_compute_command_encoder();
+        compute_encoder.set_compute_pipeline_state(&self.offsets_pipeline);
+        compute_encoder.set_buffer(0, Some(output), 0);
+        compute_encoder.set_buffer(1, Some(&block_offsets), 0);
+        set_u32(compute_encoder, 2, count as u32);
+        self.dispatch_blocks(compute_encoder, count);
+        compute_encoder.end_encoding();
+        Ok(())
+    }
+
+    fn histogram(
+        &self,
+        data: &[f32],
+        bins: usize,
+    ) -> Result<Vec<u32>, MetalError> {
+        if data.is_empty() {
+            return Ok(vec![0; bins]);
+        }
+
+        autoreleasepool(|| {
+            let command_buffer =
+                self.context.command_queue.new_command_buffer();
+            let input = self.context.make_shared_buffer(data)?;
+            let count = data.len() as u64;
+            // the kernel only ever adds to the global bins
+            let global_bins =
+                self.context.make_shared_buffer(&vec![0u32; bins])?;
+            // the bin count comes with the call, so this one can't be
+            // checked up front
+            let memory_length = check_threadgroup_memory(
+                &self.context.device,
+                &self.histogram_pipeline,
+                (bins * size_of::<u32>()).next_multiple_of(16) as u64,
+            )?;
+
+            let compute_encoder = command_buffer.new_compute_command_encoder();
+            compute_encoder
+                .set_compute_pipeline_state(&self.histogram_pipeline);
+            compute_encoder.set_buffer(0, Some(&input), 0);
+            compute_encoder.set_buffer(1, Some(&global_bins), 0);
+            set_u32(compute_encoder, 2, count as u32);
+            set_u32(compute_encoder, 3, bins as u32);
+            compute_encoder.set_threadgroup_memory_length(0, memory_length);
+            self.dispatch_blocks(compute_encoder, count);
+            compute_encoder.end_encoding();
+
+            command_buffer.commit();
+            command_buffer.wait_until_completed();
+            check_completion(command_buffer)?;
+
+            Ok(unsafe {
+                std::slice::from_raw_parts(
+                    global_bins.contents() as *const u32,
+                    bins,
+                )
+            }
+            .to_vec())
+        })
+    }
+
+    fn new_float_buffer(&self, length: u64) -> Result<Buffer, MetalError> {
+        self.context.new_buffer(
+            length * size_of::<f32>() as u64,
+            MTLResourceOptions::StorageModeShared,
+        )
+    }
+
+    fn dispatch_blocks(
+        &self,
+        compute_encoder: &ComputeCommandEncoderRef,
+        count: u64,
+    ) {
+        let group_count = MTLSize {
+            width: count.div_ceil(self.threadgroup_width),
+            height: 1,
+            depth: 1,
+        };
+        let threadgroup_size = MTLSize {
+            width: self.threadgroup_width,
+            height: 1,
+            depth: 1,
+        };
+        compute_encoder.dispatch_thread_groups(group_count, threadgroup_size);
+    }
+}
+
+fn main() {
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("info"),
+    )
+    .init();
+
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(&args) {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), MetalError> {
+    let context = MetalContext::new(&args.device)?;
+    info!("Using device: {}", context.device.name());
+    info!("Array length: {}", args.array_length);
+
+    let reducer = Reducer::new(context)?;
+    let data: Vec<f32> = (0..args.array_length)
+        .map(|_| rand::random::<f32>())
+        .collect();
+
+    let start = Instant::now();
+    let gpu_sum = reducer.reduce_sum(&data)?;
+    println!("GPU time: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let cpu_sum = kahan_sum(&data);
+    println!("CPU time: {:?}", start.elapsed());
+
+    verify_results(gpu_sum, cpu_sum, data.len())?;
+
+    let start = Instant::now();
+    let gpu_scan = reducer.scan_inclusive(&data)?;
+    println!("GPU scan time: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let cpu_scan = kahan_scan(&data);
+    println!("CPU scan time: {:?}", start.elapsed());
+
+    verify_scan(&gpu_scan, &cpu_scan)?;
+
+    let start = Instant::now();
+    let gpu_histogram = reducer.histogram(&data, args.bins)?;
+    println!("GPU histogram time: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let cpu_histogram = histogram_on_cpu(&data, args.bins);
+    println!("CPU histogram time: {:?}", start.elapsed());
+
+    verify_histogram(&gpu_histogram, &cpu_histogram)
+}
+
+// same binning as the kernel, including the f32 multiply, so a value on a
+// bin edge lands in the same bin on both sides
+fn histogram_on_cpu(data: &[f32], bins: usize) -> Vec<u32> {
+    let mut histogram = vec![0; bins];
+    for &value in data {
+        let bin = (value.clamp(0.0, 1.0) * bins as f32) as usize;
+        histogram[bin.min(bins - 1)] += 1;
+    }
+    histogram
+}
+
+fn verify_results(
+    gpu_sum: f32,
+    cpu_sum: f32,
+    length: usize,
+) -> Result<(), MetalError> {
+    // the compensated cpu sum is within about one ulp, so the budget is all
+    // for the gpu's rounding, which accumulates roughly like a random walk
+    // and so grows with sqrt(length). a naive f32 reference drifts at the
+    // same rate, and the two drifts together could flag a correct sum
+    let tolerance =
+        f32::EPSILON * (length as f32).sqrt() * cpu_sum.abs().max(1.0);
+    if (gpu_sum - cpu_sum).abs() > tolerance {
+        return Err(MetalError::VerificationFailed(format!(
+            "gpu sum={} vs {}=cpu sum (tolerance {})",
+            gpu_sum, cpu_sum, tolerance
+        )));
+    }
+    println!("Compute results as expected: sum={}", gpu_sum);
+    Ok(())
+}
+
+fn verify_scan(gpu_scan: &[f32], cpu_scan: &[f32]) -> Result<(), MetalError> {
+    // each prefix is its own sum, so it gets the same sqrt(length) error
+    // budget as the full reduction, measured at its own length
+    for (i, (&gpu, &cpu)) in gpu_scan.iter().zip(cpu_scan).enumerate() {
+        let tolerance =
+            f32::EPSILON * ((i + 1) as f32).sqrt() * cpu.abs().max(1.0);
+        if gpu.is_nan() || (gpu - cpu).abs() > tolerance {
+            return Err(MetalError::VerificationFailed(format!(
+                "scan index={} gpu={} vs {}=cpu (tolerance {})",
+                i, gpu, cpu, tolerance
+            )));
+        }
+    }
+    println!(
+        "Scan results as expected: last={}",
+        gpu_scan.last().copied().unwrap_or(0.0)
+    );
+    Ok(())
+}
+
+fn verify_histogram(
+    gpu_histogram: &[u32],
+    cpu_histogram: &[u32],
+) -> Result<(), MetalError> {
+    for (bin, (&gpu, &cpu)) in
+        gpu_histogram.iter().zip(cpu_histogram).enumerate()
+    {
+        if gpu != cpu {
+            return Err(MetalError::VerificationFailed(format!(
+                "histogram bin={} gpu={} vs {}=cpu",
+                bin, gpu, cpu
+            )));
+        }
+    }
+    println!(
+        "Histogram results as expected: {} bins",
+        gpu_histogram.len()
+    );
+    Ok(())
+}
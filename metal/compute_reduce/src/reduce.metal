@@ -0,0 +1,139 @@
+#include <metal_stdlib>
+using namespace metal;
+
+kernel void reduce_sum(device const float* input,
+                       device float* partials,
+                       constant uint& count,
+                       threadgroup float* scratch [[threadgroup(0)]],
+                       uint gid [[thread_position_in_grid]],
+                       uint lid [[thread_position_in_threadgroup]],
+                       uint group [[threadgroup_position_in_grid]],
+                       uint group_size [[threads_per_threadgroup]])
+{
+    scratch[lid] = gid < count ? input[gid] : 0.0;
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+
+    for (uint stride = group_size / 2; stride > 0; stride >>= 1)
+    {
+        if (lid < stride)
+        {
+            scratch[lid] += scratch[lid + stride];
+        }
+        threadgroup_barrier(mem_flags::mem_threadgroup);
+    }
+
+    if (lid == 0)
+    {
+        partials[group] = scratch[0];
+    }
+}
+
+// work-efficient (Blelloch) scan of one threadgroup sized block. the group
+// width is a power of two; threads past the end of the input scan zeros so
+// the last block can be partial
+kernel void scan_blocks(device const float* input,
+                        device float* output,
+                        device float* block_sums,
+                        constant uint& count,
+                        threadgroup float* scratch [[threadgroup(0)]],
+                        uint gid [[thread_position_in_grid]],
+                        uint lid [[thread_position_in_threadgroup]],
+                        uint group [[threadgroup_position_in_grid]],
+                        uint group_size [[threads_per_threadgroup]])
+{
+    float value = gid < count ? input[gid] : 0.0;
+    scratch[lid] = value;
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+
+    // up-sweep: each level adds the left subtree into its right sibling
+    for (uint stride = 1; stride < group_size; stride <<= 1)
+    {
+        uint index = (lid + 1) * stride * 2 - 1;
+        if (index < group_size)
+        {
+            scratch[index] += scratch[index - stride];
+        }
+        threadgroup_barrier(mem_flags::mem_threadgroup);
+    }
+
+    if (lid == 0)
+    {
+        block_sums[group] = scratch[group_size - 1];
+        scratch[group_size - 1] = 0.0;
+    }
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+
+    // down-sweep: pushes prefixes back down, leaving an exclusive scan
+    for (uint stride = group_size / 2; stride > 0; stride >>= 1)
+    {
+        uint index = (lid + 1) * stride * 2 - 1;
+        if (index < group_size)
+        {
+            float left = scratch[index - stride];
+            scratch[index - stride] = scratch[index];
+            scratch[index] += left;
+        }
+        threadgroup_barrier(mem_flags::mem_threadgroup);
+    }
+
+    if (gid < count)
+    {
+        output[gid] = scratch[lid] + value;
+    }
+}
+
+// block_offsets is the inclusive scan of block_sums, so every block but the
+// first adds the running total of all blocks before it
+kernel void add_block_offsets(device float* output,
+                              device const float* block_offsets,
+                              constant uint& count,
+                              uint gid [[thread_position_in_grid]],
+                              uint group [[threadgroup_position_in_grid]])
+{
+    if (group > 0 && gid < count)
+    {
+        output[gid] += block_offsets[group - 1];
+    }
+}
+
+// counts values in [0, 1) into `bins` equal buckets. every group builds a
+// private histogram with threadgroup atomics and merges it into the global
+// one with a single device atomic add per non-empty bin. metal atomics are
+// relaxed only, so the ordering between the zero, count and merge phases
+// comes from the threadgroup barriers; the device adds need no fence since
+// nothing reads the global bins until the command buffer has completed, and
+// integer addition commutes, so the result is the same for any schedule
+kernel void histogram(device const float* input,
+                      device atomic_uint* global_bins,
+                      constant uint& count,
+                      constant uint& bins,
+                      threadgroup atomic_uint* local_bins [[threadgroup(0)]],
+                      uint gid [[thread_position_in_grid]],
+                      uint lid [[thread_position_in_threadgroup]],
+                      uint group_size [[threads_per_threadgroup]])
+{
+    for (uint bin = lid; bin < bins; bin += group_size)
+    {
+        atomic_store_explicit(&local_bins[bin], 0, memory_order_relaxed);
+    }
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+
+    if (gid < count)
+    {
+        float value = clamp(input[gid], 0.0, 1.0);
+        uint bin = min(uint(value * float(bins)), bins - 1);
+        atomic_fetch_add_explicit(&local_bins[bin], 1, memory_order_relaxed);
+    }
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+
+    for (uint bin = lid; bin < bins; bin += group_size)
+    {
+        uint local_count =
+            atomic_load_explicit(&local_bins[bin], memory_order_relaxed);
+        if (local_count > 0)
+        {
+            atomic_fetch_add_explicit(&global_bins[bin], local_count,
+                                      memory_order_relaxed);
+        }
+    }
+}
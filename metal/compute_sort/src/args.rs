@@ -0,0 +1,46 @@
+use metal_common::{DevicePreference, next_value};
+
+pub const USAGE: &str = "usage: compute_sort \
+    [--device default|high|low|<name>] [--len N]";
+
+const DEFAULT_ARRAY_LENGTH: usize = 1 << 16;
+
+pub struct Args {
+    pub device: DevicePreference,
+    pub array_length: usize,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            device: DevicePreference::default(),
+            array_length: DEFAULT_ARRAY_LENGTH,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut args = Args::default();
+        let mut iter = std::env::args().skip(1);
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--device" => {
+                    let Ok(device) = next_value(&mut iter, &arg)?.parse();
+                    args.device = device;
+                }
+                "--len" => {
+                    args.array_length = next_value(&mut iter, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|&len| len > 0)
+                        .ok_or("--len must be a positive integer")?;
+                }
+                other => return Err(format!("unknown argument: {}", other)),
+            }
+        }
+
+        Ok(args)
+    }
+}
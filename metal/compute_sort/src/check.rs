@@ -0,0 +1,59 @@
+// the gpu output has to be non-decreasing and hold exactly the values that
+// went in, compared by bits so duplicates count exactly. the kernel only
+// swaps on >, so -0.0 and 0.0 are equal to it and either can come first;
+// both are folded into 0.0 before comparing
+pub fn is_sorted_permutation(input: &[f32], output: &[f32]) -> bool {
+    if input.len() != output.len() {
+        return false;
+    }
+    if !output.windows(2).all(|pair| pair[0] <= pair[1]) {
+        return false;
+    }
+
+    let mut expected = input
+        .iter()
+        .map(|&value| canonical(value))
+        .collect::<Vec<_>>();
+    expected.sort_by(f32::total_cmp);
+    expected
+        .iter()
+        .zip(output)
+        .all(|(a, &b)| a.to_bits() == canonical(b).to_bits())
+}
+
+fn canonical(value: f32) -> f32 {
+    if value == 0.0 { 0.0 } else { value }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorted_copy_passes() {
+        let input = [3.0, -1.0, 2.0, 2.0, 0.5];
+        assert!(is_sorted_permutation(&input, &[-1.0, 0.5, 2.0, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn out_of_order_fails() {
+        let input = [1.0, 2.0, 3.0];
+        assert!(!is_sorted_permutation(&input, &[1.0, 3.0, 2.0]));
+    }
+
+    #[test]
+    fn signed_zeros_sort_in_either_order() {
+        let input = [0.0, 1.0, -0.0, -1.0];
+        assert!(is_sorted_permutation(&input, &[-1.0, 0.0, -0.0, 1.0]));
+        assert!(is_sorted_permutation(&input, &[-1.0, -0.0, 0.0, 1.0]));
+        assert!(!is_sorted_permutation(&input, &[-1.0, 0.0, 1.0, 1.0]));
+    }
+
+    #[test]
+    fn lost_duplicate_fails() {
+        // sorted, but one 2.0 was overwritten by a padding sentinel
+        let input = [2.0, 1.0, 2.0];
+        assert!(!is_sorted_permutation(&input, &[1.0, 2.0, f32::INFINITY]));
+        assert!(!is_sorted_permutation(&input, &[1.0, 2.0]));
+    }
+}
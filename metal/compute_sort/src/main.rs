@@ -0,0 +1,165 @@
+mod args;
+mod check;
+
+use std::time::Instant;
+
+use args::{Args, USAGE};
+use check::is_sorted_permutation;
+use log::{error, info};
+use metal::*;
+use metal_common::{MetalContext, MetalError, check_completion, set_u32};
+use objc::rc::autoreleasepool;
+
+struct Sorter {
+    context: MetalContext,
+    pipeline_state: ComputePipelineState,
+}
+
+impl Sorter {
+    fn new(context: MetalContext) -> Result<Self, MetalError> {
+        let library = context.compile_library(include_str!("sort.metal"))?;
+        let pipeline_state = context.make_pipeline(&library, "bitonic_step")?;
+
+        Ok(Sorter {
+            context,
+            pipeline_state,
+        })
+    }
+
+    // ascending, in place. the network only works on powers of two, so the
+    // rest is padded with +inf, which sorts to the end and is cut off again
+    fn sort(&self, data: &mut [f32]) -> Result<(), MetalError> {
+        if data.len() < 2 {
+            return Ok(());
+        }
+        let n = data.len().next_power_of_two();
+        let mut padded = data.to_vec();
+        padded.resize(n, f32::INFINITY);
+
+        autoreleasepool(|| {
+            let command_buffer =
+                self.context.command_queue.new_command_buffer();
+            let buffer = self.context.make_shared_buffer(&padded)?;
+
+            let mut block_size = 2;
+            while block_size <= n {
+                let mut pass_size = block_size / 2;
+                while pass_size > 0 {
+                    self.encode_step(
+                        command_buffer,
+                        &buffer,
+                        block_size,
+                        pass_size,
+                        n,
+                    );
+                    pass_size /= 2;
+                }
+                block_size *= 2;
+            }
+
+            command_buffer.commit();
+            command_buffer.wait_until_completed();
+            check_completion(command_buffer)?;
+
+            data.copy_from_slice(unsafe {
+                std::slice::from_raw_parts(
+                    buffer.contents() as *const f32,
+                    data.len(),
+                )
+            });
+            Ok(())
+        })
+    }
+
+    // every pass reads what the previous one swapped, the encoder boundary
+    // orders them
+    fn encode_step(
+        &self,
+        command_buffer: &CommandBufferRef,
+        data: &BufferRef,
+        block_size: usize,
+        pass_size: usize,
+        n: usize,
+    ) {
+        let compute_encoder = command_buffer.new_compute_command_encoder();
+        compute_encoder.set_compute_pipeline_state(&self.pipeline_state);
+        compute_encoder.set_buffer(0, Some(data), 0);
+        set_u32(compute_encoder, 1, block_size as u32);
+        set_u32(compute_encoder, 2, pass_size as u32);
+        set_u32(compute_encoder, 3, n as u32);
+        dispatch_thread_groups(compute_encoder, &self.pipeline_state, n / 2);
+        compute_encoder.end_encoding();
+    }
+}
+
+// whole threadgroups, the kernel skips the threads past count
+fn dispatch_thread_groups(
+    compute_encoder: &ComputeCommandEncoderRef,
+    pipeline_state: &ComputePipelineStateRef,
+    count: usize,
+) {
+    let count = count as u64;
+    let width = pipeline_state
+        .max_total_threads_per_threadgroup()
+        .min(count);
+    let group_count = MTLSize {
+        width: count.div_ceil(width),
+        height: 1,
+        depth: 1,
+    };
+    let threadgroup_size = MTLSize {
+        width,
+        height: 1,
+        depth: 1,
+    };
+    compute_encoder.dispatch_thread_groups(group_count, threadgroup_size);
+}
+
+fn main() {
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("info"),
+    )
+    .init();
+
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(&args) {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), MetalError> {
+    let context = MetalContext::new(&args.device)?;
+    info!("Using device: {}", context.device.name());
+    info!("Array length: {}", args.array_length);
+
+    let sorter = Sorter::new(context)?;
+    let input: Vec<f32> = (0..args.array_length)
+        .map(|_| rand::random::<f32>())
+        .collect();
+
+    let mut gpu_result = input.clone();
+    let start = Instant::now();
+    sorter.sort(&mut gpu_result)?;
+    println!("GPU time: {:?}", start.elapsed());
+
+    let mut cpu_result = input.clone();
+    let start = Instant::now();
+    cpu_result.sort_by(f32::total_cmp);
+    println!("CPU time: {:?}", start.elapsed());
+
+    if !is_sorted_permutation(&input, &gpu_result) {
+        return Err(MetalError::VerificationFailed(
+            "output isn't a sorted copy of the input".to_string(),
+        ));
+    }
+    println!("Compute results as expected: {} values sorted", input.len());
+    Ok(())
+}
@@ -0,0 +1,30 @@
+#include <metal_stdlib>
+using namespace metal;
+
+// one compare-exchange pass of a bitonic sort, each thread owns the pair
+// pass_size apart that starts at i. the block_size bit of i picks whether
+// the pair is ordered ascending or descending, so every block of that size
+// ends up a bitonic half of the next bigger one
+kernel void bitonic_step(device float* data,
+                         constant uint& block_size,
+                         constant uint& pass_size,
+                         constant uint& count,
+                         uint gid [[thread_position_in_grid]])
+{
+    if (gid >= count / 2)
+    {
+        return;
+    }
+
+    uint i = (gid / pass_size) * pass_size * 2 + gid % pass_size;
+    uint j = i + pass_size;
+    bool ascending = (i & block_size) == 0;
+
+    float a = data[i];
+    float b = data[j];
+    if ((a > b) == ascending)
+    {
+        data[i] = b;
+        data[j] = a;
+    }
+}
@@ -0,0 +1,85 @@
+use log::error;
+use metal_common::{DevicePreference, MetalContext, MetalError};
+
+const USAGE: &str = "usage: gpu_misc --mode raster|compute [example args...]";
+
+enum Mode {
+    Raster(raster_triangle::Args),
+    Compute(compute_add::Args),
+}
+
+impl Mode {
+    fn device(&self) -> &DevicePreference {
+        match self {
+            Mode::Raster(args) => &args.device,
+            Mode::Compute(args) => &args.device,
+        }
+    }
+}
+
+// --mode picks the example, everything else is handed to its own parser
+fn parse_mode() -> Result<Mode, String> {
+    let mut mode = None;
+    let mut rest = Vec::new();
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        if arg == "--mode" {
+            mode = Some(iter.next().ok_or("missing value for --mode")?);
+        } else {
+            rest.push(arg);
+        }
+    }
+
+    match mode.as_deref() {
+        Some("raster") => raster_triangle::Args::parse_from(rest)
+            .map(Mode::Raster)
+            .map_err(|err| format!("{}\n{}", err, raster_triangle::USAGE)),
+        Some("compute") => compute_add::Args::parse_from(rest)
+            .map(Mode::Compute)
+            .map_err(|err| format!("{}\n{}", err, compute_add::USAGE)),
+        Some(other) => Err(format!("unknown mode: {}", other)),
+        None => Err("missing --mode".to_string()),
+    }
+}
+
+fn main() {
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("info"),
+    )
+    .init();
+
+    let mode = match parse_mode() {
+        Ok(mode) => mode,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(mode) {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+// one context for whichever example runs, so adding another mode doesn't
+// mean another copy of the device selection
+fn run(mode: Mode) -> Result<(), MetalError> {
+    let context = match (MetalContext::new(mode.device()), &mode) {
+        // compute_add::run finds no device either and falls back to the cpu
+        (Err(MetalError::NoDevice), Mode::Compute(args))
+            if args.headless_ok =>
+        {
+            return compute_add::run(args);
+        }
+        (context, _) => context?,
+    };
+    match mode {
+        Mode::Raster(args) => {
+            let config = raster_triangle::RenderConfig::from_args(&args);
+            raster_triangle::run_with_context(args, config, context);
+            Ok(())
+        }
+        Mode::Compute(args) => compute_add::run_with_context(&args, &context),
+    }
+}
@@ -0,0 +1,315 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use metal_common::{DevicePreference, next_value};
+
+use crate::geometry::{MAX_GRID_VERTICES, grid_fits};
+
+pub const USAGE: &str = "usage: raster_triangle \
+    [--device default|high|low|<name>] [--shape triangle|quad] \
+    [--grid COLSxROWS] [--texture image.png] [--mipmaps] [--pattern] \
+    [--skybox image.png] \
+    [--blend opaque|alpha|additive] \
+    [--present vsync|immediate|scheduled:MS] [--fps N] \
+    [--primitive triangle|line|line-strip|point] [--instances N] [--srgb] \
+    [--hdr] [--indirect] [--verify-indirect] [--wave] [--accumulate ALPHA] \
+    [--rotation-speed RAD_PER_S] [--info] \
+    [--metallib shaders.metallib] [model.obj|model.ply...]";
+
+#[derive(Debug, Default, Clone, Copy)]
+pub enum Shape {
+    #[default]
+    Triangle,
+    Quad,
+    Grid {
+        cols: usize,
+        rows: usize,
+    },
+}
+
+#[derive(Debug, Default, Clone)]
+pub enum TextureSource {
+    #[default]
+    White,
+    Image(PathBuf),
+    // mandelbrot computed on the gpu at startup
+    Pattern,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub enum BlendMode {
+    #[default]
+    Opaque,
+    AlphaBlend,
+    Additive,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub enum PresentMode {
+    #[default]
+    Vsync,
+    Immediate,
+    // each drawable is shown at least this long after the previous one
+    Scheduled(Duration),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Linear,
+    Srgb,
+    ExtendedRange,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub enum PrimitiveMode {
+    #[default]
+    Triangle,
+    Line,
+    LineStrip,
+    Point,
+}
+
+impl PrimitiveMode {
+    pub fn next(self) -> Self {
+        match self {
+            PrimitiveMode::Triangle => PrimitiveMode::Line,
+            PrimitiveMode::Line => PrimitiveMode::LineStrip,
+            PrimitiveMode::LineStrip => PrimitiveMode::Point,
+            PrimitiveMode::Point => PrimitiveMode::Triangle,
+        }
+    }
+}
+
+pub struct Args {
+    pub device: DevicePreference,
+    pub shape: Shape,
+    pub models: Vec<PathBuf>,
+    pub texture: TextureSource,
+    pub mipmaps: bool,
+    // equirectangular background drawn behind the scene
+    pub skybox: Option<PathBuf>,
+    pub metallib: Option<PathBuf>,
+    pub blend_mode: BlendMode,
+    pub present_mode: PresentMode,
+    pub fps: Option<u32>,
+    pub primitive_mode: PrimitiveMode,
+    pub instance_count: usize,
+    pub output_format: OutputFormat,
+    pub indirect: bool,
+    // renders the first frame both ways and compares them at startup
+    pub verify_indirect: bool,
+    pub wave: bool,
+    pub accumulate: Option<f32>,
+    // radians per second around z, zero for a still image
+    pub rotation_speed: Option<f32>,
+    pub info: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            device: DevicePreference::default(),
+            shape: Shape::default(),
+            models: Vec::new(),
+            texture: TextureSource::default(),
+            mipmaps: false,
+            skybox: None,
+            metallib: None,
+            blend_mode: BlendMode::default(),
+            present_mode: PresentMode::default(),
+            fps: None,
+            primitive_mode: PrimitiveMode::default(),
+            instance_count: 1,
+            output_format: OutputFormat::default(),
+            indirect: false,
+            verify_indirect: false,
+            wave: false,
+            accumulate: None,
+            rotation_speed: None,
+            info: false,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from(
+        iter: impl IntoIterator<Item = String>,
+    ) -> Result<Self, String> {
+        let mut args = Args::default();
+        let mut iter = iter.into_iter();
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--device" => {
+                    let Ok(device) = next_value(&mut iter, &arg)?.parse();
+                    args.device = device;
+                }
+                "--shape" => {
+                    args.shape = match next_value(&mut iter, &arg)?.as_str() {
+                        "triangle" => Shape::Triangle,
+                        "quad" => Shape::Quad,
+                        other => {
+                            return Err(format!("unknown shape: {}", other));
+                        }
+                    };
+                }
+                "--grid" => {
+                    args.shape = parse_grid(&next_value(&mut iter, &arg)?)?;
+                }
+                "--metallib" => {
+                    args.metallib =
+                        Some(PathBuf::from(next_value(&mut iter, &arg)?));
+                }
+                "--texture" => {
+                    args.texture = TextureSource::Image(PathBuf::from(
+                        next_value(&mut iter, &arg)?,
+                    ));
+                }
+                "--skybox" => {
+                    args.skybox =
+                        Some(PathBuf::from(next_value(&mut iter, &arg)?));
+                }
+                "--pattern" => args.texture = TextureSource::Pattern,
+                "--mipmaps" => args.mipmaps = true,
+                "--blend" => {
+                    args.blend_mode =
+                        match next_value(&mut iter, &arg)?.as_str() {
+                            "opaque" => BlendMode::Opaque,
+                            "alpha" => BlendMode::AlphaBlend,
+                            "additive" => BlendMode::Additive,
+                            other => {
+                                return Err(format!(
+                                    "unknown blend mode: {}",
+                                    other
+                                ));
+                            }
+                        };
+                }
+                "--present" => {
+                    args.present_mode =
+                        parse_present(&next_value(&mut iter, &arg)?)?;
+                }
+                "--primitive" => {
+                    args.primitive_mode =
+                        match next_value(&mut iter, &arg)?.as_str() {
+                            "triangle" => PrimitiveMode::Triangle,
+                            "line" => PrimitiveMode::Line,
+                            "line-strip" => PrimitiveMode::LineStrip,
+                            "point" => PrimitiveMode::Point,
+                            other => {
+                                return Err(format!(
+                                    "unknown primitive mode: {}",
+                                    other
+                                ));
+                            }
+                        };
+                }
+                "--srgb" => args.output_format = OutputFormat::Srgb,
+                "--indirect" => args.indirect = true,
+                "--verify-indirect" => args.verify_indirect = true,
+                "--wave" => args.wave = true,
+                "--info" => args.info = true,
+                "--hdr" => args.output_format = OutputFormat::ExtendedRange,
+                "--accumulate" => {
+                    args.accumulate = Some(
+                        next_value(&mut iter, &arg)?
+                            .parse()
+                            .ok()
+                            .filter(|&alpha: &f32| alpha > 0.0 && alpha <= 1.0)
+                            .ok_or("--accumulate must be an alpha in (0, 1]")?,
+                    );
+                }
+                "--rotation-speed" => {
+                    args.rotation_speed = Some(
+                        next_value(&mut iter, &arg)?
+                            .parse()
+                            .ok()
+                            .filter(|speed: &f32| speed.is_finite())
+                            .ok_or("--rotation-speed must be a number")?,
+                    );
+                }
+                "--fps" => {
+                    args.fps = Some(
+                        next_value(&mut iter, &arg)?
+                            .parse()
+                            .ok()
+                            .filter(|&fps| fps > 0)
+                            .ok_or("--fps must be a positive integer")?,
+                    );
+                }
+                "--instances" => {
+                    args.instance_count = next_value(&mut iter, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|&count| count > 0)
+                        .ok_or("--instances must be a positive integer")?;
+                }
+                other if other.starts_with("--") => {
+                    return Err(format!("unknown argument: {}", other));
+                }
+                path => args.models.push(PathBuf::from(path)),
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+// COLSxROWS, small enough that every vertex has a u16 index
+fn parse_grid(value: &str) -> Result<Shape, String> {
+    let invalid = || format!("--grid must be COLSxROWS: {}", value);
+    let (cols, rows) = value.split_once('x').ok_or_else(invalid)?;
+    let cols: usize = cols.parse().map_err(|_| invalid())?;
+    let rows: usize = rows.parse().map_err(|_| invalid())?;
+    if cols == 0 || rows == 0 {
+        return Err(invalid());
+    }
+    if !grid_fits(cols, rows) {
+        return Err(format!(
+            "--grid {} needs more than {} vertices",
+            value, MAX_GRID_VERTICES
+        ));
+    }
+    Ok(Shape::Grid { cols, rows })
+}
+
+// vsync, immediate or scheduled:MS with the frame interval in milliseconds
+fn parse_present(value: &str) -> Result<PresentMode, String> {
+    match value {
+        "vsync" => Ok(PresentMode::Vsync),
+        "immediate" => Ok(PresentMode::Immediate),
+        other => {
+            let interval = other
+                .strip_prefix("scheduled:")
+                .ok_or_else(|| format!("unknown present mode: {}", other))?;
+            interval
+                .parse::<f64>()
+                .ok()
+                .filter(|ms| ms.is_finite() && *ms > 0.0)
+                .map(|ms| {
+                    PresentMode::Scheduled(Duration::from_secs_f64(ms / 1000.0))
+                })
+                .ok_or_else(|| {
+                    format!(
+                        "--present scheduled:MS needs a positive interval: {}",
+                        value
+                    )
+                })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_has_single_spaces_between_flags() {
+        assert!(!USAGE.contains("  "), "{}", USAGE);
+    }
+}
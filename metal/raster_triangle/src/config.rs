@@ -0,0 +1,41 @@
+use metal::MTLClearColor;
+
+use crate::args::{Args, OutputFormat, PresentMode};
+
+const DEFAULT_TITLE: &str = "Metal Triangle with Buffers";
+const DEFAULT_SAMPLE_COUNT: u32 = 4;
+
+// what the renderer is set up with before the first frame, the key toggles
+// change most of it afterwards
+#[derive(Debug, Clone)]
+pub struct RenderConfig {
+    // the fps and gpu time get appended to it
+    pub title: String,
+    pub output_format: OutputFormat,
+    pub clear_color: MTLClearColor,
+    // falls back to 1 on devices that can't multisample at this count
+    pub sample_count: u32,
+    pub present_mode: PresentMode,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            title: DEFAULT_TITLE.to_string(),
+            output_format: OutputFormat::default(),
+            clear_color: crate::clear_color_preset(0),
+            sample_count: DEFAULT_SAMPLE_COUNT,
+            present_mode: PresentMode::default(),
+        }
+    }
+}
+
+impl RenderConfig {
+    pub fn from_args(args: &Args) -> Self {
+        RenderConfig {
+            output_format: args.output_format,
+            present_mode: args.present_mode,
+            ..RenderConfig::default()
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use metal::{MTLCullMode, MTLWinding, RenderCommandEncoderRef};
+
+// which faces the rasterizer drops and which winding counts as front facing,
+// defaulting to metal's own of culling nothing with clockwise fronts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CullConfig {
+    pub mode: MTLCullMode,
+    pub winding: MTLWinding,
+}
+
+impl Default for CullConfig {
+    fn default() -> Self {
+        CullConfig {
+            mode: MTLCullMode::None,
+            winding: MTLWinding::Clockwise,
+        }
+    }
+}
+
+impl CullConfig {
+    pub fn next_mode(self) -> Self {
+        let mode = match self.mode {
+            MTLCullMode::None => MTLCullMode::Back,
+            MTLCullMode::Back => MTLCullMode::Front,
+            MTLCullMode::Front => MTLCullMode::None,
+        };
+        CullConfig { mode, ..self }
+    }
+
+    pub fn flip_winding(self) -> Self {
+        let winding = match self.winding {
+            MTLWinding::Clockwise => MTLWinding::CounterClockwise,
+            MTLWinding::CounterClockwise => MTLWinding::Clockwise,
+        };
+        CullConfig { winding, ..self }
+    }
+
+    pub fn apply(self, render_encoder: &RenderCommandEncoderRef) {
+        render_encoder.set_front_facing_winding(self.winding);
+        render_encoder.set_cull_mode(self.mode);
+    }
+}
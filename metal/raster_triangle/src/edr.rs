@@ -0,0 +1,56 @@
+use std::ffi::c_void;
+
+use cocoa::base::id as cocoa_id;
+use metal::MetalLayerRef;
+use metal::objc::{msg_send, sel, sel_impl};
+use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use winit::window::Window;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+unsafe extern "C" {
+    static kCGColorSpaceExtendedLinearSRGB: *const c_void;
+    fn CGColorSpaceCreateWithName(name: *const c_void) -> *mut c_void;
+    fn CGColorSpaceRelease(space: *mut c_void);
+}
+
+// largest color component the window's screen can show; 1.0 means the
+// display has no headroom above sdr white
+pub fn max_edr_value(window: &Window) -> f64 {
+    let Ok(RawWindowHandle::AppKit(handle)) =
+        window.window_handle().map(|wh| wh.as_raw())
+    else {
+        return 1.0;
+    };
+    unsafe {
+        let view = handle.ns_view.as_ptr() as cocoa_id;
+        let ns_window: cocoa_id = msg_send![view, window];
+        if ns_window.is_null() {
+            return 1.0;
+        }
+        let screen: cocoa_id = msg_send![ns_window, screen];
+        if screen.is_null() {
+            return 1.0;
+        }
+        msg_send![
+            screen,
+            maximumPotentialExtendedDynamicRangeColorComponentValue
+        ]
+    }
+}
+
+// with an extended linear colorspace the compositor reads the float surface
+// as linear light, letting values above 1.0 go brighter than sdr white
+pub fn set_extended_range(layer: &MetalLayerRef, enabled: bool) {
+    layer.set_wants_extended_dynamic_range_content(enabled);
+    unsafe {
+        let colorspace = if enabled {
+            CGColorSpaceCreateWithName(kCGColorSpaceExtendedLinearSRGB)
+        } else {
+            std::ptr::null_mut()
+        };
+        let () = msg_send![layer, setColorspace: colorspace];
+        if !colorspace.is_null() {
+            CGColorSpaceRelease(colorspace);
+        }
+    }
+}
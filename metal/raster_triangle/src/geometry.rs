@@ -0,0 +1,214 @@
+use std::mem::offset_of;
+
+use metal::{MTLVertexFormat, VertexDescriptor};
+
+use crate::vertex_layout::VertexLayoutBuilder;
+
+// the built in shapes lie flat in the xy plane
+pub const FACING_CAMERA: [f32; 3] = [0.0, 0.0, 1.0];
+// the most vertices a u16 index buffer can address
+pub const MAX_INDEXED_VERTICES: usize = u16::MAX as usize + 1;
+// grids are always drawn through the index buffer
+pub const MAX_GRID_VERTICES: usize = MAX_INDEXED_VERTICES;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct AAPLVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+    pub tex_coord: [f32; 2],
+    pub normal: [f32; 3],
+}
+
+impl AAPLVertex {
+    // matches VertexIn in shaders.metal
+    pub fn vertex_descriptor(buffer_index: u64) -> VertexDescriptor {
+        VertexLayoutBuilder::new(buffer_index)
+            .attribute(
+                MTLVertexFormat::Float3,
+                offset_of!(AAPLVertex, position),
+            )
+            .attribute(MTLVertexFormat::Float4, offset_of!(AAPLVertex, color))
+            .attribute(
+                MTLVertexFormat::Float2,
+                offset_of!(AAPLVertex, tex_coord),
+            )
+            .attribute(MTLVertexFormat::Float3, offset_of!(AAPLVertex, normal))
+            .build::<AAPLVertex>()
+    }
+}
+
+pub fn triangle() -> (Vec<AAPLVertex>, Vec<u16>) {
+    let vertices = vec![
+        AAPLVertex {
+            position: [0.5, -0.5, 0.0],
+            color: [1.0, 0.0, 0.0, 1.0],
+            tex_coord: [1.0, 1.0],
+            normal: FACING_CAMERA,
+        },
+        AAPLVertex {
+            position: [-0.5, -0.5, 0.0],
+            color: [0.0, 1.0, 0.0, 1.0],
+            tex_coord: [0.0, 1.0],
+            normal: FACING_CAMERA,
+        },
+        AAPLVertex {
+            position: [0.0, 0.5, 0.0],
+            color: [0.0, 0.0, 1.0, 1.0],
+            tex_coord: [0.5, 0.0],
+            normal: FACING_CAMERA,
+        },
+    ];
+    (vertices, Vec::new())
+}
+
+pub fn quad() -> (Vec<AAPLVertex>, Vec<u16>) {
+    let vertices = vec![
+        AAPLVertex {
+            position: [-0.5, -0.5, 0.0],
+            color: [1.0, 0.0, 0.0, 1.0],
+            tex_coord: [0.0, 1.0],
+            normal: FACING_CAMERA,
+        },
+        AAPLVertex {
+            position: [0.5, -0.5, 0.0],
+            color: [0.0, 1.0, 0.0, 1.0],
+            tex_coord: [1.0, 1.0],
+            normal: FACING_CAMERA,
+        },
+        AAPLVertex {
+            position: [0.5, 0.5, 0.0],
+            color: [0.0, 0.0, 1.0, 1.0],
+            tex_coord: [1.0, 0.0],
+            normal: FACING_CAMERA,
+        },
+        AAPLVertex {
+            position: [-0.5, 0.5, 0.0],
+            color: [1.0, 1.0, 0.0, 1.0],
+            tex_coord: [0.0, 0.0],
+            normal: FACING_CAMERA,
+        },
+    ];
+    (vertices, vec![0, 1, 2, 2, 3, 0])
+}
+
+// one vertex per index, in the same order, so any list or strip draws the
+// same primitives without an index buffer
+pub fn unindex(vertices: &[AAPLVertex], indices: &[u16]) -> Vec<AAPLVertex> {
+    indices
+        .iter()
+        .map(|&index| vertices[index as usize])
+        .collect()
+}
+
+// a vertex on every cell corner, None once that overflows usize
+pub fn grid_vertex_count(cols: usize, rows: usize) -> Option<usize> {
+    cols.checked_add(1)?.checked_mul(rows.checked_add(1)?)
+}
+
+// whether every vertex of the grid has a u16 index
+pub fn grid_fits(cols: usize, rows: usize) -> bool {
+    grid_vertex_count(cols, rows)
+        .is_some_and(|count| count <= MAX_GRID_VERTICES)
+}
+
+// cols x rows quads over [-1, 1], red across and green up, to load the
+// rasterizer with a chosen vertex and triangle count through the index path
+pub fn make_grid(cols: usize, rows: usize) -> (Vec<AAPLVertex>, Vec<u16>) {
+    assert!(cols > 0 && rows > 0, "grid needs at least one cell");
+    assert!(
+        grid_fits(cols, rows),
+        "grid has too many vertices for u16 indices"
+    );
+
+    let mut vertices = Vec::with_capacity((cols + 1) * (rows + 1));
+    for row in 0..=rows {
+        let v = row as f32 / rows as f32;
+        for col in 0..=cols {
+            let u = col as f32 / cols as f32;
+            vertices.push(AAPLVertex {
+                position: [u * 2.0 - 1.0, v * 2.0 - 1.0, 0.0],
+                color: [u, v, 1.0 - u, 1.0],
+                tex_coord: [u, 1.0 - v],
+                normal: FACING_CAMERA,
+            });
+        }
+    }
+
+    let mut indices = Vec::with_capacity(cols * rows * 6);
+    let stride = cols + 1;
+    for row in 0..rows {
+        for col in 0..cols {
+            let bottom_left = (row * stride + col) as u16;
+            let bottom_right = bottom_left + 1;
+            let top_left = bottom_left + stride as u16;
+            let top_right = top_left + 1;
+            // same winding as quad()
+            indices.extend_from_slice(&[
+                bottom_left,
+                bottom_right,
+                top_right,
+                top_right,
+                top_left,
+                bottom_left,
+            ]);
+        }
+    }
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unindexed_quad_has_three_vertices_per_triangle() {
+        let (vertices, indices) = quad();
+        let unindexed = unindex(&vertices, &indices);
+        assert_eq!(unindexed.len(), 6);
+        for (vertex, &index) in unindexed.iter().zip(&indices) {
+            assert_eq!(vertex.position, vertices[index as usize].position);
+        }
+    }
+
+    #[test]
+    fn unindexed_grid_keeps_every_triangle() {
+        let (vertices, indices) = make_grid(3, 2);
+        let unindexed = unindex(&vertices, &indices);
+        assert_eq!(unindexed.len(), 3 * 2 * 6);
+        // the first triangle of the first cell, corner by corner
+        assert_eq!(unindexed[0].position, vertices[0].position);
+        assert_eq!(unindexed[1].position, vertices[1].position);
+        assert_eq!(unindexed[2].position, vertices[5].position);
+    }
+
+    #[test]
+    fn grid_has_a_vertex_per_corner_and_two_triangles_per_cell() {
+        let (vertices, indices) = make_grid(4, 3);
+        assert_eq!(vertices.len(), 5 * 4);
+        assert_eq!(indices.len(), 4 * 3 * 6);
+        assert_eq!(Some(vertices.len()), grid_vertex_count(4, 3));
+        assert!(
+            indices
+                .iter()
+                .all(|&index| (index as usize) < vertices.len())
+        );
+    }
+
+    #[test]
+    fn largest_grid_still_has_u16_indices() {
+        assert!(grid_fits(255, 255));
+        assert!(!grid_fits(256, 255));
+        let (vertices, indices) = make_grid(255, 255);
+        assert_eq!(vertices.len(), MAX_GRID_VERTICES);
+        assert_eq!(indices.iter().max(), Some(&u16::MAX));
+    }
+
+    #[test]
+    fn overflowing_grid_sizes_dont_fit() {
+        assert_eq!(grid_vertex_count(usize::MAX, 1), None);
+        assert_eq!(grid_vertex_count(1, usize::MAX), None);
+        assert_eq!(grid_vertex_count(usize::MAX / 2, 2), None);
+        assert!(!grid_fits(usize::MAX, usize::MAX));
+    }
+}
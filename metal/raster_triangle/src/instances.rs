@@ -0,0 +1,64 @@
+// extent of the disc the scattered instances are centered in and the scale
+// they are drawn at
+const SCATTER_RADIUS: f32 = 0.6;
+const SCATTER_SCALE: f32 = 0.5;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InstanceData {
+    // xyz is the world offset, w scales the mesh so denser grids still fit
+    pub offset_scale: [f32; 4],
+    pub color: [f32; 4],
+}
+
+// lays the instances out on a square grid spanning [-1, 1] in x and y, a
+// single instance sits at the origin untinted so it matches a plain draw
+pub fn grid(count: usize) -> Vec<InstanceData> {
+    let side = (count as f32).sqrt().ceil() as usize;
+    let spacing = 2.0 / side as f32;
+    let max_cell = (side - 1).max(1) as f32;
+
+    (0..count)
+        .map(|i| {
+            let (column, row) = (i % side, i / side);
+            let x = (column as f32 + 0.5) * spacing - 1.0;
+            let y = (row as f32 + 0.5) * spacing - 1.0;
+            let (u, v) = (column as f32 / max_cell, row as f32 / max_cell);
+            InstanceData {
+                offset_scale: [x, y, 0.0, 1.0 / side as f32],
+                color: [1.0, 1.0 - 0.5 * u, 1.0 - 0.5 * v, 1.0],
+            }
+        })
+        .collect()
+}
+
+// the golden angle spreads the instances evenly over a disc while keeping
+// them large enough that most of them overlap, each one tinted around the
+// color wheel with the same alpha
+pub fn scattered(count: usize, alpha: f32) -> Vec<InstanceData> {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0_f32.sqrt());
+
+    (0..count)
+        .map(|i| {
+            let radius =
+                SCATTER_RADIUS * ((i as f32 + 0.5) / count as f32).sqrt();
+            let angle = i as f32 * golden_angle;
+            let hue = (i as f32 / count as f32) * std::f32::consts::TAU;
+            let tint = |phase: f32| 0.5 + 0.5 * (hue + phase).cos();
+            InstanceData {
+                offset_scale: [
+                    radius * angle.cos(),
+                    radius * angle.sin(),
+                    0.0,
+                    SCATTER_SCALE,
+                ],
+                color: [
+                    tint(0.0),
+                    tint(std::f32::consts::TAU / 3.0),
+                    tint(2.0 * std::f32::consts::TAU / 3.0),
+                    alpha,
+                ],
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,2501 @@
+pub mod args;
+mod config;
+mod cull;
+mod edr;
+mod geometry;
+mod instances;
+mod math;
+mod mesh;
+mod obj;
+mod ply;
+mod present;
+mod skybox;
+mod software;
+mod stencil;
+mod texture;
+mod uniform_ring;
+mod vertex_layout;
+mod vertex_ring;
+
+pub use args::{Args, USAGE};
+use args::{
+    BlendMode, OutputFormat, PresentMode, PrimitiveMode, Shape, TextureSource,
+};
+use cocoa::appkit::NSView;
+use cocoa::base::id as cocoa_id;
+pub use config::RenderConfig;
+use core_graphics_types::geometry::CGSize;
+use cull::CullConfig;
+pub use geometry::AAPLVertex;
+use geometry::{MAX_GRID_VERTICES, grid_fits};
+use half::f16;
+use instances::InstanceData;
+use log::{debug, error, info, trace, warn};
+use math::Mat4;
+use mesh::Mesh;
+use metal::*;
+use metal_common::{
+    MetalContext, MetalError, ShaderSource, check_completion,
+    command_buffer_gpu_time_ms, compute_pattern, print_device_info,
+};
+use objc::rc::autoreleasepool;
+use skybox::Skybox;
+pub use software::cpu_rasterize;
+use std::collections::{HashSet, VecDeque};
+use std::ffi::c_void;
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use texture::SamplerConfig;
+use uniform_ring::UniformRing;
+use winit::{
+    application::ApplicationHandler,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{
+        ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent,
+    },
+    event_loop::{ActiveEventLoop, EventLoop},
+    keyboard::{KeyCode, PhysicalKey},
+    raw_window_handle::{HasWindowHandle, RawWindowHandle},
+    window::{Fullscreen, Window, WindowId},
+};
+
+const AAPL_VERTEX_INPUT_INDEX_VERTICES: u64 = 0;
+const AAPL_VERTEX_INPUT_INDEX_INSTANCES: u64 = 1;
+const AAPL_VERTEX_INPUT_INDEX_UNIFORMS: u64 = 2;
+const AAPL_FRAGMENT_TEXTURE_INDEX: u64 = 0;
+const AAPL_FRAGMENT_SAMPLER_INDEX: u64 = 0;
+const AAPL_FRAGMENT_UNIFORMS_INDEX: u64 = 0;
+const AAPL_POST_TEXTURE_INDEX: u64 = 0;
+const AAPL_POST_TONEMAP_INDEX: u64 = 0;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Uniforms {
+    model: Mat4,
+    view_projection: Mat4,
+    // xyz towards the light, w unused
+    light_dir: [f32; 4],
+    point_size: f32,
+    linearize_colors: u32,
+    show_barycentric: u32,
+    // the metal struct is padded to float4x4's 16 byte alignment
+    _padding: f32,
+}
+
+#[derive(Clone, Copy)]
+struct VertexPick {
+    mesh: usize,
+    vertex: usize,
+    instance: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Drag {
+    Orbit,
+    Vertex(VertexPick),
+}
+
+// the accumulation demo adds up past 1.0 in the scene and only tonemaps it
+// back into range in the post pass
+const ACCUMULATION_PIXEL_FORMAT: MTLPixelFormat = MTLPixelFormat::RGBA16Float;
+// the scene's second color attachment, normals packed from [-1, 1] to [0, 1]
+const NORMAL_PIXEL_FORMAT: MTLPixelFormat = MTLPixelFormat::RGBA8Unorm;
+const DEPTH_STENCIL_PIXEL_FORMAT: MTLPixelFormat =
+    MTLPixelFormat::Depth32Float_Stencil8;
+
+// bytes of constants each frame can allocate from the uniform ring
+const UNIFORM_REGION_SIZE: u64 = 64 * 1024;
+const DEFAULT_ROTATION_SPEED: f32 = 1.0;
+// up and to the right of the camera
+const DEFAULT_LIGHT_DIR: [f32; 3] = [0.4, 0.5, 0.77];
+// radians the arrow keys turn the light per press
+const LIGHT_STEP: f32 = std::f32::consts::PI / 16.0;
+// z displacement of the --wave animation, in mesh units
+const WAVE_AMPLITUDE: f32 = 0.1;
+const WAVE_FREQUENCY: f32 = 8.0;
+const WAVE_SPEED: f32 = 3.0;
+const DEFAULT_POINT_SIZE: f32 = 8.0;
+// animation advances in steps of this many seconds whatever the frame rate,
+// and a long stall only catches up this far so it can't snowball
+const SIM_STEP: f64 = 1.0 / 120.0;
+const MAX_SIM_CATCH_UP: f64 = 0.25;
+
+const ORBIT_SENSITIVITY: f32 = 0.01;
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+// in logical pixels, scaled by the window's scale factor when picking
+const VERTEX_PICK_RADIUS: f64 = 12.0;
+// magnification per wheel notch, trackpads report pixels that are converted
+// to notches first
+const ZOOM_STEP: f32 = 1.1;
+const PIXELS_PER_WHEEL_LINE: f32 = 20.0;
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 64.0;
+
+const SHADER_PATH: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders.metal");
+
+const FOV_Y: f32 = std::f32::consts::FRAC_PI_3;
+const Z_NEAR: f32 = 0.1;
+const Z_FAR: f32 = 100.0;
+
+const CAMERA_SPEED: f32 = 1.0;
+const CAMERA_START: [f32; 3] = [0.0, 0.0, 2.0];
+
+// digits 1 to 9 render only the matching mesh, 0 goes back to all of them
+const MESH_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+const CAMERA_KEYS: [(KeyCode, usize, f32); 6] = [
+    (KeyCode::KeyA, 0, -1.0),
+    (KeyCode::KeyD, 0, 1.0),
+    (KeyCode::KeyS, 1, -1.0),
+    (KeyCode::KeyW, 1, 1.0),
+    (KeyCode::KeyQ, 2, -1.0),
+    (KeyCode::KeyE, 2, 1.0),
+];
+
+const PATTERN_SIZE: u32 = 512;
+
+const CAPTURE_PATH: &str = "capture.png";
+
+const CLEAR_COLOR_PRESETS: [[f64; 4]; 4] = [
+    [0.0, 0.5, 0.7, 1.0],
+    [0.1, 0.1, 0.1, 1.0],
+    [0.9, 0.9, 0.9, 1.0],
+    [0.3, 0.1, 0.3, 1.0],
+];
+// red, green and blue, nudged while alt is held so the keys keep their
+// usual toggles otherwise
+const CLEAR_COLOR_KEYS: [KeyCode; 3] =
+    [KeyCode::KeyR, KeyCode::KeyG, KeyCode::KeyB];
+const CLEAR_COLOR_STEP: f64 = 0.05;
+
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+const TITLE_UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+// frames the gpu time average in the title is taken over
+const GPU_TIME_WINDOW: usize = 60;
+const DROPPED_FRAME_WARNING: u32 = 60;
+
+struct MetalState {
+    window: Arc<Window>,
+    title: String,
+    context: MetalContext,
+    layer: MetalLayer,
+    pipelines: Pipelines,
+    blend_mode: BlendMode,
+    texture: Texture,
+    srgb_texture: Texture,
+    skybox: Option<Skybox>,
+    sampler_state: SamplerState,
+    sampler_config: SamplerConfig,
+    depth_stencil_state: DepthStencilState,
+    mask_depth_stencil_state: DepthStencilState,
+    stencil_mask: bool,
+    depth_texture: Texture,
+    offscreen_texture: Texture,
+    normal_texture: Texture,
+    sample_count: u32,
+    msaa_texture: Option<Texture>,
+    msaa_normal_texture: Option<Texture>,
+    // the post pass shows normal_texture instead of the lit scene
+    show_normals: bool,
+    meshes: Vec<Mesh>,
+    selected_mesh: Option<usize>,
+    instance_count: usize,
+    instance_buffer: Buffer,
+    indirect: bool,
+    wave: bool,
+    // shades each triangle with its interpolated corner weights instead of
+    // the lit color
+    barycentric: bool,
+    // per instance alpha of the additive accumulation demo when it's on
+    accumulate: Option<f32>,
+    // cols and rows of the --grid mesh, resized with +/-
+    grid: Option<(usize, usize)>,
+    uniform_ring: UniformRing,
+    projection: Mat4,
+    // off stretches the scene with the window, for comparing against the
+    // aspect corrected projection
+    preserve_aspect: bool,
+    // seconds of animation simulated so far, and real time not yet covered
+    // by a whole step
+    sim_time: f64,
+    accumulator: f64,
+    rotation_speed: f32,
+    light_dir: [f32; 3],
+    yaw: f32,
+    pitch: f32,
+    camera_pos: [f32; 3],
+    // screen space magnification applied after the projection, and the ndc
+    // offset that keeps the point under the cursor in place while zooming
+    zoom: f32,
+    zoom_offset: [f32; 2],
+    held_keys: HashSet<KeyCode>,
+    last_frame: Instant,
+    // --fps caps rendering by sleeping until next_frame_due after each frame
+    frame_budget: Option<Duration>,
+    next_frame_due: Instant,
+    paused_since: Option<Instant>,
+    frame_times: VecDeque<Instant>,
+    // committed frames whose gpu time hasn't been read yet, oldest first
+    frames_in_flight: VecDeque<CommandBuffer>,
+    gpu_frame_times: VecDeque<f64>,
+    last_title_update: Instant,
+    clear_color: MTLClearColor,
+    clear_color_preset: usize,
+    triangle_fill_mode: MTLTriangleFillMode,
+    cull_config: CullConfig,
+    output_format: OutputFormat,
+    primitive_mode: PrimitiveMode,
+    present_mode: PresentMode,
+    // what Shift+V goes back to after trying immediate
+    configured_present_mode: PresentMode,
+    point_size: f32,
+    shader_modified: Option<SystemTime>,
+    metallib: Option<PathBuf>,
+    frames_dropped: u64,
+    consecutive_frames_dropped: u32,
+}
+
+impl MetalState {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        window: Arc<Window>,
+        context: MetalContext,
+        config: &RenderConfig,
+        geometry: &[(Vec<AAPLVertex>, Vec<u16>)],
+        texture_source: &TextureSource,
+        mipmaps: bool,
+        metallib: Option<&Path>,
+        blend_mode: BlendMode,
+    ) -> Result<Self, MetalError> {
+        let device = &context.device;
+        info!("Using device: {}", device.name());
+
+        let sample_count = if device
+            .supports_texture_sample_count(config.sample_count as u64)
+        {
+            config.sample_count
+        } else {
+            warn!(
+                "Sample count {} unsupported, falling back to 1",
+                config.sample_count
+            );
+            1
+        };
+        debug!("Sample count: {}", sample_count);
+
+        let mut layer = MetalLayer::new();
+        layer.set_device(device);
+        layer.set_pixel_format(color_pixel_format(OutputFormat::Linear));
+        layer.set_presents_with_transaction(false);
+        // present_drawable queues the drawable for the next vblank, so with
+        // display sync on the frame rate is capped at the refresh rate.
+        // disabling it hands the drawable to the compositor as soon as the
+        // gpu finishes, which can tear but shows the uncapped frame rate.
+        // scheduled presents still land on a vblank, just not before their
+        // interval is up
+        layer.set_display_sync_enabled(!matches!(
+            config.present_mode,
+            PresentMode::Immediate
+        ));
+        if let PresentMode::Scheduled(interval) = config.present_mode {
+            info!("Presenting at most every {:?}", interval);
+        }
+        unsafe {
+            if let Ok(RawWindowHandle::AppKit(rw)) =
+                window.window_handle().map(|wh| wh.as_raw())
+            {
+                let view = rw.ns_view.as_ptr() as cocoa_id;
+                view.setWantsLayer(true);
+                view.setLayer(<*mut _>::cast(layer.as_mut()));
+                debug!("Attached metal layer to the window's view");
+            } else {
+                warn!("Window has no AppKit view, nothing will be presented");
+            }
+        }
+
+        let shader_modified = shader_modified_time();
+        let source;
+        let shader_source = match metallib {
+            Some(path) => ShaderSource::Library(path.to_path_buf()),
+            None => {
+                source = load_shader_source();
+                ShaderSource::Source(&source)
+            }
+        };
+        let pipelines = Pipelines::build(
+            &context,
+            &shader_source,
+            sample_count,
+            blend_mode,
+            color_pixel_format(OutputFormat::Linear),
+            color_pixel_format(OutputFormat::Linear),
+        )?;
+
+        let texture = match texture_source {
+            TextureSource::White => texture::white_texture(device),
+            TextureSource::Image(path) => {
+                texture::load_texture(&context, path, mipmaps)?
+            }
+            TextureSource::Pattern => {
+                compute_pattern(&context, PATTERN_SIZE, PATTERN_SIZE)?
+            }
+        };
+        let srgb_texture = texture::srgb_view(&texture);
+        let sampler_config = if mipmaps {
+            SamplerConfig::TRILINEAR
+        } else {
+            SamplerConfig::LINEAR
+        };
+        let sampler_state = sampler_config.create(device);
+
+        let depth_stencil_state =
+            stencil::scene_depth_stencil_state(device, true);
+        let mask_depth_stencil_state =
+            stencil::mask_depth_stencil_state(device);
+
+        let inner_size = window.inner_size();
+        let (width, height) =
+            (inner_size.width.max(1), inner_size.height.max(1));
+        let depth_texture = create_render_target(
+            device,
+            DEPTH_STENCIL_PIXEL_FORMAT,
+            width,
+            height,
+            sample_count,
+        );
+        let offscreen_texture = create_offscreen_texture(
+            device,
+            color_pixel_format(OutputFormat::Linear),
+            width,
+            height,
+        );
+        let msaa_texture = (sample_count > 1).then(|| {
+            create_render_target(
+                device,
+                color_pixel_format(OutputFormat::Linear),
+                width,
+                height,
+                sample_count,
+            )
+        });
+        let normal_texture = create_offscreen_texture(
+            device,
+            NORMAL_PIXEL_FORMAT,
+            width,
+            height,
+        );
+        let msaa_normal_texture = (sample_count > 1).then(|| {
+            create_render_target(
+                device,
+                NORMAL_PIXEL_FORMAT,
+                width,
+                height,
+                sample_count,
+            )
+        });
+
+        let meshes = geometry
+            .iter()
+            .map(|(vertices, indices)| Mesh::new(&context, vertices, indices))
+            .collect::<Result<_, _>>()?;
+
+        let instance_buffer =
+            context.make_shared_buffer(&instances::grid(1))?;
+        let uniform_ring = UniformRing::new(device, UNIFORM_REGION_SIZE);
+
+        let mut state = MetalState {
+            window,
+            title: config.title.clone(),
+            context,
+            layer,
+            pipelines,
+            blend_mode,
+            texture,
+            srgb_texture,
+            skybox: None,
+            sampler_state,
+            sampler_config,
+            depth_stencil_state,
+            mask_depth_stencil_state,
+            stencil_mask: false,
+            depth_texture,
+            offscreen_texture,
+            normal_texture,
+            sample_count,
+            msaa_texture,
+            msaa_normal_texture,
+            show_normals: false,
+            meshes,
+            selected_mesh: None,
+            instance_count: 1,
+            instance_buffer,
+            indirect: false,
+            wave: false,
+            barycentric: false,
+            accumulate: None,
+            grid: None,
+            uniform_ring,
+            projection: math::perspective(
+                FOV_Y,
+                width as f32 / height as f32,
+                Z_NEAR,
+                Z_FAR,
+            ),
+            preserve_aspect: true,
+            sim_time: 0.0,
+            accumulator: 0.0,
+            rotation_speed: DEFAULT_ROTATION_SPEED,
+            light_dir: math::normalize(DEFAULT_LIGHT_DIR),
+            yaw: 0.0,
+            pitch: 0.0,
+            camera_pos: CAMERA_START,
+            zoom: 1.0,
+            zoom_offset: [0.0, 0.0],
+            held_keys: HashSet::new(),
+            last_frame: Instant::now(),
+            frame_budget: None,
+            next_frame_due: Instant::now(),
+            paused_since: None,
+            frame_times: VecDeque::new(),
+            frames_in_flight: VecDeque::new(),
+            gpu_frame_times: VecDeque::new(),
+            last_title_update: Instant::now(),
+            clear_color: config.clear_color,
+            clear_color_preset: 0,
+            triangle_fill_mode: MTLTriangleFillMode::Fill,
+            cull_config: CullConfig::default(),
+            output_format: OutputFormat::Linear,
+            primitive_mode: PrimitiveMode::default(),
+            present_mode: config.present_mode,
+            configured_present_mode: config.present_mode,
+            point_size: DEFAULT_POINT_SIZE,
+            shader_modified,
+            metallib: metallib.map(Path::to_path_buf),
+            frames_dropped: 0,
+            consecutive_frames_dropped: 0,
+        };
+        state.set_scale_factor(state.window.scale_factor());
+        state.resize(state.window.inner_size());
+        // everything above starts out linear, other formats rebuild it
+        if config.output_format != OutputFormat::Linear {
+            state.set_output_format(config.output_format);
+        }
+        Ok(state)
+    }
+
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+        self.layer.set_drawable_size(CGSize::new(
+            new_size.width as f64,
+            new_size.height as f64,
+        ));
+        self.depth_texture = create_render_target(
+            &self.context.device,
+            DEPTH_STENCIL_PIXEL_FORMAT,
+            new_size.width,
+            new_size.height,
+            self.sample_count,
+        );
+        let scene_pixel_format = self.scene_pixel_format(self.output_format);
+        self.offscreen_texture = create_offscreen_texture(
+            &self.context.device,
+            scene_pixel_format,
+            new_size.width,
+            new_size.height,
+        );
+        if self.sample_count > 1 {
+            self.msaa_texture = Some(create_render_target(
+                &self.context.device,
+                scene_pixel_format,
+                new_size.width,
+                new_size.height,
+                self.sample_count,
+            ));
+            self.msaa_normal_texture = Some(create_render_target(
+                &self.context.device,
+                NORMAL_PIXEL_FORMAT,
+                new_size.width,
+                new_size.height,
+                self.sample_count,
+            ));
+        }
+        self.normal_texture = create_offscreen_texture(
+            &self.context.device,
+            NORMAL_PIXEL_FORMAT,
+            new_size.width,
+            new_size.height,
+        );
+        self.update_projection(new_size);
+        self.window.request_redraw();
+    }
+
+    fn update_projection(&mut self, size: PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        let aspect = if self.preserve_aspect {
+            size.width as f32 / size.height as f32
+        } else {
+            1.0
+        };
+        self.projection = math::perspective(FOV_Y, aspect, Z_NEAR, Z_FAR);
+    }
+
+    fn set_preserve_aspect(&mut self, preserve_aspect: bool) {
+        self.preserve_aspect = preserve_aspect;
+        info!("Preserve aspect ratio: {}", preserve_aspect);
+        self.update_projection(self.window.inner_size());
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let fullscreen = match self.window.fullscreen() {
+            Some(_) => None,
+            None => Some(Fullscreen::Borderless(None)),
+        };
+        info!("Fullscreen: {}", fullscreen.is_some());
+        self.window.set_fullscreen(fullscreen);
+        // macos animates the transition and reports the final size through
+        // Resized, this covers platforms that apply the new size right away
+        self.resize(self.window.inner_size());
+    }
+
+    fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.layer.set_contents_scale(scale_factor);
+    }
+
+    fn orbit_matrix(&self) -> Mat4 {
+        math::mul(&math::rotation_x(self.pitch), &math::rotation_y(self.yaw))
+    }
+
+    fn model_matrix(&self) -> Mat4 {
+        let elapsed = self.animation_time();
+        let spin = math::rotation_z(elapsed * self.rotation_speed);
+        math::mul(&self.orbit_matrix(), &spin)
+    }
+
+    // the zoom is folded in here so picking and dragging see the same
+    // transform the vertex shader does
+    fn view_projection_matrix(&self) -> Mat4 {
+        let view = math::translation(self.camera_pos.map(|value| -value));
+        let zoom = [
+            [self.zoom, 0.0, 0.0, 0.0],
+            [0.0, self.zoom, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [self.zoom_offset[0], self.zoom_offset[1], 0.0, 1.0],
+        ];
+        math::mul(&zoom, &math::mul(&self.projection, &view))
+    }
+
+    // scales the screen around the cursor, so whatever is under it stays put
+    fn zoom_at(&mut self, factor: f32, cursor: PhysicalPosition<f64>) {
+        let zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let factor = zoom / self.zoom;
+        let center = self.cursor_ndc(cursor);
+        self.zoom_offset = [0, 1].map(|axis| {
+            factor * self.zoom_offset[axis] + (1.0 - factor) * center[axis]
+        });
+        self.zoom = zoom;
+        debug!("Zoom: {:.2}x", self.zoom);
+    }
+
+    fn cursor_ndc(&self, cursor: PhysicalPosition<f64>) -> [f32; 2] {
+        let size = self.window.inner_size();
+        [
+            (2.0 * cursor.x / size.width as f64 - 1.0) as f32,
+            (1.0 - 2.0 * cursor.y / size.height as f64) as f32,
+        ]
+    }
+
+    fn uniforms(&self) -> Uniforms {
+        Uniforms {
+            model: self.model_matrix(),
+            view_projection: self.view_projection_matrix(),
+            light_dir: [
+                self.light_dir[0],
+                self.light_dir[1],
+                self.light_dir[2],
+                0.0,
+            ],
+            point_size: self.point_size,
+            linearize_colors: self.linearizes_colors() as u32,
+            show_barycentric: self.barycentric as u32,
+            _padding: 0.0,
+        }
+    }
+
+    fn handle_key(&mut self, key_code: KeyCode) {
+        match key_code {
+            key_code
+                if CLEAR_COLOR_KEYS.contains(&key_code)
+                    && self.held(&[KeyCode::AltLeft, KeyCode::AltRight]) =>
+            {
+                self.nudge_clear_color(key_code)
+            }
+            KeyCode::KeyI
+                if self.held(&[KeyCode::ShiftLeft, KeyCode::ShiftRight]) =>
+            {
+                self.log_state()
+            }
+            KeyCode::KeyV
+                if self.held(&[KeyCode::ShiftLeft, KeyCode::ShiftRight]) =>
+            {
+                self.set_present_mode(
+                    match (self.present_mode, self.configured_present_mode) {
+                        (PresentMode::Immediate, PresentMode::Immediate) => {
+                            PresentMode::Vsync
+                        }
+                        (PresentMode::Immediate, configured) => configured,
+                        (PresentMode::Vsync | PresentMode::Scheduled(_), _) => {
+                            PresentMode::Immediate
+                        }
+                    },
+                )
+            }
+            KeyCode::KeyP => {
+                match self.capture_frame(Path::new(CAPTURE_PATH)) {
+                    Ok(()) => info!("Saved frame to {}", CAPTURE_PATH),
+                    Err(err) => error!("{}", err),
+                }
+            }
+            KeyCode::Space => self.cycle_clear_color(),
+            KeyCode::KeyF => self.toggle_wireframe(),
+            KeyCode::KeyC => self.set_cull_config(self.cull_config.next_mode()),
+            KeyCode::KeyO => {
+                self.set_cull_config(self.cull_config.flip_winding())
+            }
+            KeyCode::KeyG => self.set_output_format(match self.output_format {
+                OutputFormat::Srgb => OutputFormat::Linear,
+                _ => OutputFormat::Srgb,
+            }),
+            KeyCode::KeyI => self.set_indirect(!self.indirect),
+            KeyCode::KeyV => self.set_wave(!self.wave),
+            KeyCode::KeyB => self.set_barycentric(!self.barycentric),
+            KeyCode::KeyT => self.set_show_normals(!self.show_normals),
+            KeyCode::Equal | KeyCode::NumpadAdd => {
+                if let Some((cols, rows)) = self.grid {
+                    self.resize_grid(cols * 2, rows * 2);
+                }
+            }
+            KeyCode::Minus | KeyCode::NumpadSubtract => {
+                if let Some((cols, rows)) = self.grid {
+                    self.resize_grid(cols / 2, rows / 2);
+                }
+            }
+            KeyCode::KeyL => {
+                self.set_primitive_mode(self.primitive_mode.next())
+            }
+            KeyCode::BracketRight => {
+                self.set_instance_count(self.instance_count.saturating_mul(2))
+            }
+            KeyCode::BracketLeft => {
+                self.set_instance_count(self.instance_count / 2)
+            }
+            KeyCode::KeyM => self.stencil_mask = !self.stencil_mask,
+            KeyCode::KeyR => self.set_preserve_aspect(!self.preserve_aspect),
+            KeyCode::KeyN => {
+                self.set_sampler_config(self.sampler_config.next_preset())
+            }
+            KeyCode::Digit0 => self.select_mesh(None),
+            KeyCode::ArrowLeft => {
+                self.rotate_light(&math::rotation_y(-LIGHT_STEP))
+            }
+            KeyCode::ArrowRight => {
+                self.rotate_light(&math::rotation_y(LIGHT_STEP))
+            }
+            KeyCode::ArrowUp => {
+                self.rotate_light(&math::rotation_x(-LIGHT_STEP))
+            }
+            KeyCode::ArrowDown => {
+                self.rotate_light(&math::rotation_x(LIGHT_STEP))
+            }
+            _ => {
+                if let Some(index) =
+                    MESH_KEYS.iter().position(|&key| key == key_code)
+                {
+                    self.select_mesh(Some(index));
+                }
+            }
+        }
+    }
+
+    fn set_sampler_config(&mut self, sampler_config: SamplerConfig) {
+        self.sampler_config = sampler_config;
+        self.sampler_state = sampler_config.create(&self.context.device);
+        info!("Sampler: {:?}", sampler_config);
+    }
+
+    fn rotate_light(&mut self, rotation: &Mat4) {
+        let [x, y, z, _] = math::transform(rotation, self.light_dir);
+        // renormalized so rounding doesn't build up over many presses
+        self.light_dir = math::normalize([x, y, z]);
+        debug!("Light direction: {:?}", self.light_dir);
+    }
+
+    fn set_clear_color(&mut self, clear_color: MTLClearColor) {
+        self.clear_color = clear_color;
+    }
+
+    // shift lowers the channel instead of raising it
+    fn nudge_clear_color(&mut self, key_code: KeyCode) {
+        let step = if self.held(&[KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            -CLEAR_COLOR_STEP
+        } else {
+            CLEAR_COLOR_STEP
+        };
+        let mut clear_color = self.clear_color;
+        let value = match key_code {
+            KeyCode::KeyR => &mut clear_color.red,
+            KeyCode::KeyG => &mut clear_color.green,
+            _ => &mut clear_color.blue,
+        };
+        *value = (*value + step).clamp(0.0, 1.0);
+        self.set_clear_color(clear_color);
+        info!(
+            "Clear color: {:.2}, {:.2}, {:.2}",
+            clear_color.red, clear_color.green, clear_color.blue
+        );
+    }
+
+    fn cycle_clear_color(&mut self) {
+        self.clear_color_preset =
+            (self.clear_color_preset + 1) % CLEAR_COLOR_PRESETS.len();
+        self.set_clear_color(clear_color_preset(self.clear_color_preset));
+    }
+
+    fn set_cull_config(&mut self, cull_config: CullConfig) {
+        self.cull_config = cull_config;
+        info!("Culling: {:?}", cull_config);
+    }
+
+    fn toggle_wireframe(&mut self) {
+        self.triangle_fill_mode = match self.triangle_fill_mode {
+            MTLTriangleFillMode::Fill => MTLTriangleFillMode::Lines,
+            MTLTriangleFillMode::Lines => MTLTriangleFillMode::Fill,
+        };
+    }
+
+    fn linearizes_colors(&self) -> bool {
+        self.output_format != OutputFormat::Linear
+    }
+
+    fn set_output_format(&mut self, output_format: OutputFormat) {
+        let output_format = if output_format == OutputFormat::ExtendedRange
+            && edr::max_edr_value(&self.window) <= 1.0
+        {
+            warn!("Display has no EDR headroom, falling back to SDR");
+            OutputFormat::Linear
+        } else {
+            output_format
+        };
+        let pixel_format = color_pixel_format(output_format);
+        match self.build_pipelines(output_format) {
+            Ok(pipelines) => self.pipelines = pipelines,
+            Err(err) => {
+                error!("Switching output format failed: {}", err);
+                return;
+            }
+        }
+        self.output_format = output_format;
+        self.layer.set_pixel_format(pixel_format);
+        edr::set_extended_range(
+            &self.layer,
+            output_format == OutputFormat::ExtendedRange,
+        );
+        // the msaa and offscreen targets have to match the new format
+        self.resize(self.window.inner_size());
+        info!("Output format: {:?}", pixel_format);
+    }
+
+    fn set_primitive_mode(&mut self, primitive_mode: PrimitiveMode) {
+        self.primitive_mode = primitive_mode;
+    }
+
+    // capped at what one buffer can hold, every instance lives in it
+    fn set_instance_count(&mut self, instance_count: usize) {
+        let max_count = self.context.device.max_buffer_length() as usize
+            / size_of::<InstanceData>();
+        if instance_count > max_count {
+            warn!("At most {} instances fit in one buffer", max_count);
+        }
+        let instance_count = instance_count.min(max_count).max(1);
+        let instances = match self.accumulate {
+            Some(alpha) => instances::scattered(instance_count, alpha),
+            None => instances::grid(instance_count),
+        };
+        // the current instances stay when the new ones can't be allocated
+        match self.context.make_shared_buffer(&instances) {
+            Ok(buffer) => self.instance_buffer = buffer,
+            Err(err) => {
+                warn!("Keeping {} instances: {}", self.instance_count, err);
+                return;
+            }
+        }
+        self.instance_count = instance_count;
+        for mesh in &self.meshes {
+            mesh.set_indirect_instance_count(self.instance_count as u32);
+        }
+        info!("Drawing {} instances", self.instance_count);
+    }
+
+    fn instances(&self) -> &[InstanceData] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self.instance_buffer.contents() as *const InstanceData,
+                self.instance_count,
+            )
+        }
+    }
+
+    // mesh space to clip space for one instance, the same transform the
+    // vertex shader applies
+    fn instance_clip_matrix(&self, instance: &InstanceData) -> Mat4 {
+        let [x, y, z, scale] = instance.offset_scale;
+        let world = math::mul(
+            &math::translation([x, y, z]),
+            &math::mul(&self.model_matrix(), &math::scaling(scale)),
+        );
+        math::mul(&self.view_projection_matrix(), &world)
+    }
+
+    fn pick_vertex(&self, cursor: PhysicalPosition<f64>) -> Option<VertexPick> {
+        let size = self.window.inner_size();
+        let mut nearest = None;
+        let mut nearest_distance =
+            VERTEX_PICK_RADIUS * self.window.scale_factor();
+        for (instance_index, instance) in self.instances().iter().enumerate() {
+            let clip_matrix = self.instance_clip_matrix(instance);
+            for (mesh_index, mesh) in self.visible_meshes() {
+                for (vertex_index, vertex) in mesh.vertices().iter().enumerate()
+                {
+                    let clip = math::transform(&clip_matrix, vertex.position);
+                    // behind the camera
+                    if clip[3] <= 0.0 {
+                        continue;
+                    }
+                    let x = (clip[0] / clip[3] + 1.0) as f64
+                        * 0.5
+                        * size.width as f64;
+                    let y = (1.0 - clip[1] / clip[3]) as f64
+                        * 0.5
+                        * size.height as f64;
+                    let distance = (x - cursor.x).hypot(y - cursor.y);
+                    if distance <= nearest_distance {
+                        nearest_distance = distance;
+                        nearest = Some(VertexPick {
+                            mesh: mesh_index,
+                            vertex: vertex_index,
+                            instance: instance_index,
+                        });
+                    }
+                }
+            }
+        }
+        nearest
+    }
+
+    // keeps the vertex on its mesh space z plane and moves it to wherever
+    // that plane is under the cursor
+    fn drag_vertex(&self, pick: VertexPick, cursor: PhysicalPosition<f64>) {
+        // the instance grid and the vertex grid can both shrink mid drag
+        let Some(instance) = self.instances().get(pick.instance) else {
+            return;
+        };
+        let mesh = &self.meshes[pick.mesh];
+        let Some(vertex) = mesh.vertices().get(pick.vertex) else {
+            return;
+        };
+        let [_, _, z] = vertex.position;
+        let ndc = self.cursor_ndc(cursor);
+        if let Some([x, y]) = math::unproject_on_plane(
+            &self.instance_clip_matrix(instance),
+            ndc,
+            z,
+        ) {
+            mesh.set_vertex_position(pick.vertex, [x, y, z]);
+        }
+    }
+
+    fn select_mesh(&mut self, selected_mesh: Option<usize>) {
+        match selected_mesh {
+            Some(index) if index >= self.meshes.len() => {
+                debug!(
+                    "No mesh {} loaded, {} meshes",
+                    index + 1,
+                    self.meshes.len()
+                );
+                return;
+            }
+            Some(index) => info!("Rendering mesh {} only", index + 1),
+            None => info!("Rendering all {} meshes", self.meshes.len()),
+        }
+        self.selected_mesh = selected_mesh;
+    }
+
+    // the dropped model replaces everything loaded so far, one that can't be
+    // loaded leaves the current meshes in place
+    fn load_model(&mut self, path: &Path) {
+        let (vertices, indices) = match load_model_file(path) {
+            Ok(geometry) => geometry,
+            Err(err) => {
+                error!("{}", err);
+                return;
+            }
+        };
+
+        let mesh = match Mesh::new(&self.context, &vertices, &indices) {
+            Ok(mesh) => mesh,
+            Err(err) => {
+                error!("{}: {}", path.display(), err);
+                return;
+            }
+        };
+        mesh.set_indirect_instance_count(self.instance_count as u32);
+        self.meshes = vec![mesh];
+        self.selected_mesh = None;
+        self.grid = None;
+        info!("Loaded {}, {} vertices", path.display(), vertices.len());
+    }
+
+    // a bad image leaves the flat clear color behind the scene
+    fn load_skybox(&mut self, path: &Path) {
+        match Skybox::load(&self.context, path) {
+            Ok(skybox) => {
+                self.skybox = Some(skybox);
+                info!("Loaded skybox {}", path.display());
+            }
+            Err(err) => error!("{}", err),
+        }
+    }
+
+    // the selected mesh alone, or every mesh when none is selected
+    fn visible_meshes(&self) -> impl Iterator<Item = (usize, &Mesh)> {
+        self.meshes.iter().enumerate().filter(|(index, _)| {
+            self.selected_mesh.is_none_or(|selected| selected == *index)
+        })
+    }
+
+    // everything a bug report asks for in one place. it only reads, so it's
+    // fine to call in the middle of a frame
+    fn log_state(&self) {
+        let config = RenderConfig {
+            title: self.title.clone(),
+            output_format: self.output_format,
+            clear_color: self.clear_color,
+            sample_count: self.sample_count,
+            present_mode: self.present_mode,
+        };
+        let drawable_size = self.layer.drawable_size();
+        info!("Device: {}", self.context.device.name());
+        info!("{:#?}", config);
+        info!(
+            "Pixel format: {:?}, scene {:?}",
+            self.layer.pixel_format(),
+            self.scene_pixel_format(self.output_format)
+        );
+        info!(
+            "Viewport: {}x{}, preserve aspect: {}",
+            drawable_size.width, drawable_size.height, self.preserve_aspect
+        );
+        info!(
+            "{:?} {:?}, blend {:?}, fill {:?}",
+            self.primitive_mode,
+            self.cull_config,
+            self.blend_mode,
+            self.triangle_fill_mode
+        );
+        info!("{:?}", self.sampler_config);
+        for (index, mesh) in self.meshes.iter().enumerate() {
+            info!(
+                "Mesh {}: {} vertices, {} indices",
+                index,
+                mesh.vertices().len(),
+                mesh.index_count()
+            );
+        }
+        info!(
+            "Draws: {} {} with {} instances each",
+            self.visible_meshes().count(),
+            if self.indirect { "indirect" } else { "direct" },
+            self.instance_count
+        );
+        info!(
+            "Camera: position {:?}, yaw {:.3}, pitch {:.3}, zoom {:.3} at \
+             {:?}",
+            self.camera_pos, self.yaw, self.pitch, self.zoom, self.zoom_offset
+        );
+    }
+
+    fn set_wave(&mut self, wave: bool) {
+        self.wave = wave;
+        if !wave {
+            self.meshes.iter_mut().for_each(Mesh::stop_streaming);
+        }
+        info!("Wave animation: {}", wave);
+    }
+
+    // recomputed from the static positions every frame and streamed, so
+    // dragged vertices keep moving with the wave
+    fn animate_wave(&mut self) {
+        let phase = self.animation_time() * WAVE_SPEED;
+        let device = &self.context.device;
+        let streamed = self.meshes.iter_mut().try_for_each(|mesh| {
+            let vertices = mesh
+                .vertices()
+                .iter()
+                .map(|vertex| {
+                    let [x, y, z] = vertex.position;
+                    let offset = WAVE_AMPLITUDE
+                        * (WAVE_FREQUENCY * (x + y) + phase).sin();
+                    AAPLVertex {
+                        position: [x, y, z + offset],
+                        ..*vertex
+                    }
+                })
+                .collect::<Vec<_>>();
+            mesh.stream_vertices(device, &vertices)
+        });
+        if let Err(err) = streamed {
+            warn!("Stopping the wave animation: {}", err);
+            self.set_wave(false);
+        }
+    }
+
+    // regenerates the grid into the existing mesh, which only reallocates
+    // its buffers when the grid grows past them
+    fn resize_grid(&mut self, cols: usize, rows: usize) {
+        let (cols, rows) = (cols.max(1), rows.max(1));
+        if !grid_fits(cols, rows) {
+            warn!(
+                "Grid {}x{} needs more than {} vertices",
+                cols, rows, MAX_GRID_VERTICES
+            );
+            return;
+        }
+        if self.grid == Some((cols, rows)) {
+            return;
+        }
+        let (vertices, indices) = geometry::make_grid(cols, rows);
+        if let Err(err) = self.meshes[0].set_geometry(
+            &self.context.device,
+            &vertices,
+            &indices,
+        ) {
+            warn!("Grid {}x{} not applied: {}", cols, rows, err);
+            return;
+        }
+        self.grid = Some((cols, rows));
+        info!("Grid: {}x{}, {} triangles", cols, rows, indices.len() / 3);
+    }
+
+    // overlapping translucent instances added into a float scene, which the
+    // post pass tonemaps so the dense center doesn't clip to white
+    fn set_accumulate(&mut self, alpha: f32) {
+        let previous_blend_mode =
+            std::mem::replace(&mut self.blend_mode, BlendMode::Additive);
+        self.accumulate = Some(alpha);
+        match self.build_pipelines(self.output_format) {
+            Ok(pipelines) => self.pipelines = pipelines,
+            Err(err) => {
+                error!("Switching to accumulation failed: {}", err);
+                self.blend_mode = previous_blend_mode;
+                self.accumulate = None;
+                return;
+            }
+        }
+        self.depth_stencil_state =
+            stencil::scene_depth_stencil_state(&self.context.device, false);
+        self.set_instance_count(self.instance_count);
+        // the offscreen and msaa targets switch to the float format
+        self.resize(self.window.inner_size());
+        info!("Accumulating instances with alpha {}", alpha);
+    }
+
+    fn set_show_normals(&mut self, show_normals: bool) {
+        self.show_normals = show_normals;
+        info!(
+            "Showing the {} target",
+            if show_normals { "normal" } else { "color" }
+        );
+    }
+
+    fn set_barycentric(&mut self, barycentric: bool) {
+        self.barycentric = barycentric;
+        info!("Barycentric debug colors: {}", barycentric);
+    }
+
+    fn set_indirect(&mut self, indirect: bool) {
+        self.indirect = indirect;
+        info!(
+            "Drawing with {} draw calls",
+            if indirect { "indirect" } else { "direct" }
+        );
+    }
+
+    fn set_key_held(&mut self, key_code: KeyCode, held: bool) {
+        if held {
+            self.held_keys.insert(key_code);
+        } else {
+            self.held_keys.remove(&key_code);
+        }
+    }
+
+    fn held(&self, key_codes: &[KeyCode]) -> bool {
+        key_codes
+            .iter()
+            .any(|key_code| self.held_keys.contains(key_code))
+    }
+
+    fn update_camera(&mut self, delta_time: f32) {
+        for (key_code, axis, direction) in CAMERA_KEYS {
+            if self.held_keys.contains(&key_code) {
+                self.camera_pos[axis] += direction * CAMERA_SPEED * delta_time;
+            }
+        }
+    }
+
+    fn orbit(&mut self, delta_x: f32, delta_y: f32) {
+        self.yaw += delta_x * ORBIT_SENSITIVITY;
+        self.pitch = (self.pitch + delta_y * ORBIT_SENSITIVITY)
+            .clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    fn update_fps(&mut self) {
+        let now = Instant::now();
+        self.frame_times.push_back(now);
+        while let Some(&oldest) = self.frame_times.front() {
+            if now.duration_since(oldest) <= FPS_WINDOW {
+                break;
+            }
+            self.frame_times.pop_front();
+        }
+
+        if now.duration_since(self.last_title_update) >= TITLE_UPDATE_INTERVAL {
+            self.last_title_update = now;
+            debug!(
+                "{} FPS, last frame {:.3} ms on the gpu",
+                self.frame_times.len(),
+                self.last_frame_gpu_ms().unwrap_or(0.0)
+            );
+            self.set_fps_title(self.frame_times.len());
+        }
+    }
+
+    // frames already committed were encoded for the old mode, so they're
+    // waited out before the layer switches rather than changing how a
+    // drawable in flight gets presented
+    fn set_present_mode(&mut self, present_mode: PresentMode) {
+        if let Some(newest) = self.frames_in_flight.back() {
+            newest.wait_until_completed();
+        }
+        self.layer.set_display_sync_enabled(!matches!(
+            present_mode,
+            PresentMode::Immediate
+        ));
+        self.present_mode = present_mode;
+        info!("Present mode: {:?}", present_mode);
+        // the counter would otherwise average across both modes
+        self.reset_fps();
+    }
+
+    fn reset_fps(&mut self) {
+        self.frame_times.clear();
+        self.gpu_frame_times.clear();
+        self.set_fps_title(0);
+    }
+
+    // polls instead of waiting so the cpu never stalls on the gpu, a frame's
+    // time shows up a frame or two after it was submitted
+    fn collect_gpu_times(&mut self) {
+        while let Some(command_buffer) = self.frames_in_flight.front() {
+            match command_buffer.status() {
+                MTLCommandBufferStatus::Completed => {
+                    let gpu_ms = command_buffer_gpu_time_ms(command_buffer);
+                    trace!("Frame gpu time: {:.3} ms", gpu_ms);
+                    if self.gpu_frame_times.len() == GPU_TIME_WINDOW {
+                        self.gpu_frame_times.pop_front();
+                    }
+                    self.gpu_frame_times.push_back(gpu_ms);
+                }
+                MTLCommandBufferStatus::Error => (),
+                _ => break,
+            }
+            self.frames_in_flight.pop_front();
+        }
+    }
+
+    fn last_frame_gpu_ms(&self) -> Option<f64> {
+        self.gpu_frame_times.back().copied()
+    }
+
+    fn average_frame_gpu_ms(&self) -> Option<f64> {
+        (!self.gpu_frame_times.is_empty()).then(|| {
+            self.gpu_frame_times.iter().sum::<f64>()
+                / self.gpu_frame_times.len() as f64
+        })
+    }
+
+    // stops the redraw loop while the window can't be seen
+    fn pause(&mut self) {
+        if self.paused_since.is_none() {
+            debug!("Pausing rendering");
+            self.paused_since = Some(Instant::now());
+            self.reset_fps();
+        }
+    }
+
+    fn resume(&mut self) {
+        let Some(paused_since) = self.paused_since.take() else {
+            return;
+        };
+        let paused = paused_since.elapsed();
+        debug!("Resuming rendering after {:?}", paused);
+        // restart the frame clock so the pause isn't simulated and the
+        // animation continues where it stopped instead of jumping ahead
+        self.last_frame = Instant::now();
+        self.next_frame_due = self.last_frame;
+        self.window.request_redraw();
+    }
+
+    fn set_fps_title(&self, fps: usize) {
+        let title = match self.average_frame_gpu_ms() {
+            Some(gpu_ms) => {
+                format!("{} - {} FPS - {:.2} ms gpu", self.title, fps, gpu_ms)
+            }
+            None => format!("{} - {} FPS", self.title, fps),
+        };
+        self.window.set_title(&title);
+    }
+
+    fn render(&mut self) {
+        let now = Instant::now();
+        let delta_time = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+        trace!("Frame time: {:.3} ms", delta_time * 1000.0);
+
+        self.reload_shaders_if_changed();
+        self.update_camera(delta_time);
+        self.advance_simulation(delta_time as f64);
+        self.collect_gpu_times();
+        self.update_fps();
+        // before taking a drawable, so none is held while the vertex ring
+        // waits for a free buffer. a dropped frame keeps the written slot
+        // for the next one
+        if self.wave {
+            self.animate_wave();
+        }
+        // owned, so encoding the frame can borrow self mutably
+        let Some(drawable) = self.layer.next_drawable().map(ToOwned::to_owned)
+        else {
+            self.drop_frame();
+            return;
+        };
+        self.consecutive_frames_dropped = 0;
+        self.uniform_ring.begin_frame();
+        autoreleasepool(|| {
+            let command_buffer =
+                self.context.command_queue.new_command_buffer();
+            self.encode_frame(command_buffer, drawable.texture());
+            present::present(command_buffer, &drawable, self.present_mode);
+            for mesh in &mut self.meshes {
+                mesh.signal_on_completion(command_buffer);
+            }
+            self.uniform_ring.signal_on_completion(command_buffer);
+            command_buffer.commit();
+            self.frames_in_flight.push_back(command_buffer.to_owned());
+        });
+        self.limit_frame_rate();
+    }
+
+    // runs as many fixed steps as the real time covers and keeps the rest
+    // for the next frame, so the animation is the same at any frame rate
+    fn advance_simulation(&mut self, delta_time: f64) {
+        self.accumulator += delta_time.min(MAX_SIM_CATCH_UP);
+        let mut steps = 0;
+        while self.accumulator >= SIM_STEP {
+            self.sim_time += SIM_STEP;
+            self.accumulator -= SIM_STEP;
+            steps += 1;
+        }
+        trace!("Simulation steps: {}", steps);
+    }
+
+    // where the animation is between the last two steps. the animated state
+    // is a function of the simulated time alone, so blending the time is the
+    // same as blending the states and keeps motion smooth when frames don't
+    // line up with steps
+    fn animation_time(&self) -> f32 {
+        let previous = (self.sim_time - SIM_STEP).max(0.0);
+        let alpha = self.accumulator / SIM_STEP;
+        (previous + (self.sim_time - previous) * alpha) as f32
+    }
+
+    // the angle is the speed times the animation time, so this is meant for
+    // setup, changing it later jumps the mesh to the new angle
+    fn set_rotation_speed(&mut self, speed: f32) {
+        self.rotation_speed = speed;
+        info!("Rotation speed: {} rad/s", speed);
+    }
+
+    fn set_fps_cap(&mut self, fps: u32) {
+        self.frame_budget = Some(Duration::from_secs(1) / fps);
+        self.next_frame_due = Instant::now();
+        info!("Capping the frame rate at {} fps", fps);
+    }
+
+    // sleeps out the rest of the frame's budget. deadlines advance by whole
+    // budgets rather than from when the sleep ended, so oversleeping one
+    // frame shortens the next; falling a full frame behind resyncs instead
+    // of rendering a burst to catch up. animation steps by the real time
+    // that passed, so it moves at the same speed under any cap
+    fn limit_frame_rate(&mut self) {
+        let Some(budget) = self.frame_budget else {
+            return;
+        };
+        self.next_frame_due += budget;
+        let now = Instant::now();
+        if self.next_frame_due > now {
+            std::thread::sleep(self.next_frame_due - now);
+        } else if now - self.next_frame_due > budget {
+            self.next_frame_due = now;
+        }
+    }
+
+    fn drop_frame(&mut self) {
+        self.frames_dropped += 1;
+        self.consecutive_frames_dropped += 1;
+        if self.consecutive_frames_dropped == DROPPED_FRAME_WARNING {
+            warn!(
+                "No drawable for {} frames ({} dropped total), \
+                 is the layer attached to a visible window?",
+                self.consecutive_frames_dropped, self.frames_dropped
+            );
+        }
+    }
+
+    // the scene only differs from the output when accumulating
+    fn scene_pixel_format(
+        &self,
+        output_format: OutputFormat,
+    ) -> MTLPixelFormat {
+        match self.accumulate {
+            Some(_) => ACCUMULATION_PIXEL_FORMAT,
+            None => color_pixel_format(output_format),
+        }
+    }
+
+    fn build_pipelines(
+        &self,
+        output_format: OutputFormat,
+    ) -> Result<Pipelines, MetalError> {
+        let source;
+        let shader_source = match &self.metallib {
+            Some(path) => ShaderSource::Library(path.clone()),
+            None => {
+                source = load_shader_source();
+                ShaderSource::Source(&source)
+            }
+        };
+        Pipelines::build(
+            &self.context,
+            &shader_source,
+            self.sample_count,
+            self.blend_mode,
+            self.scene_pixel_format(output_format),
+            color_pixel_format(output_format),
+        )
+    }
+
+    fn reload_shaders_if_changed(&mut self) {
+        // a precompiled library has no source to watch
+        if self.metallib.is_some() {
+            return;
+        }
+        let modified = shader_modified_time();
+        if modified.is_none() || modified == self.shader_modified {
+            return;
+        }
+        self.shader_modified = modified;
+
+        match self.build_pipelines(self.output_format) {
+            Ok(pipelines) => {
+                self.pipelines = pipelines;
+                info!("Reloaded {}", SHADER_PATH);
+            }
+            Err(err) => error!("Shader reload failed: {}", err),
+        }
+    }
+
+    fn capture_frame(&mut self, path: &Path) -> Result<(), MetalError> {
+        let (pixels, width, height) = self.read_frame()?;
+        image::save_buffer(
+            path,
+            &pixels,
+            width,
+            height,
+            image::ExtendedColorType::Rgba8,
+        )
+        .map_err(|err| MetalError::ImageWrite(err.to_string()))
+    }
+
+    // renders the current state once with direct and once with indirect
+    // draws, the arguments buffer shouldn't change a single pixel
+    fn verify_indirect(&mut self) -> Result<(), MetalError> {
+        let indirect = self.indirect;
+        self.indirect = false;
+        let direct_frame = self.read_frame();
+        self.indirect = true;
+        let indirect_frame = self.read_frame();
+        self.indirect = indirect;
+
+        let (direct_pixels, _, _) = direct_frame?;
+        let (indirect_pixels, _, _) = indirect_frame?;
+        let differing = direct_pixels
+            .chunks_exact(4)
+            .zip(indirect_pixels.chunks_exact(4))
+            .filter(|(direct, indirect)| direct != indirect)
+            .count();
+        if differing > 0 {
+            return Err(MetalError::VerificationFailed(format!(
+                "{} of {} pixels differ between direct and indirect draws",
+                differing,
+                direct_pixels.len() / 4
+            )));
+        }
+        info!(
+            "Direct and indirect draws match over {} pixels",
+            direct_pixels.len() / 4
+        );
+        Ok(())
+    }
+
+    // draws a frame at the drawable size into a texture of its own and reads
+    // it back as rgba8. the constants come out of the uniform ring like any
+    // other frame's, so this waits its turn for a region
+    fn read_frame(&mut self) -> Result<(Vec<u8>, u32, u32), MetalError> {
+        if self.output_format == OutputFormat::ExtendedRange {
+            return Err(MetalError::ImageWrite(
+                "capture only supports 8 bit output formats".to_string(),
+            ));
+        }
+        let drawable_size = self.layer.drawable_size();
+        let (width, height) =
+            (drawable_size.width as u32, drawable_size.height as u32);
+        if width == 0 || height == 0 {
+            return Err(MetalError::ImageWrite(format!(
+                "nothing to capture from a {}x{} drawable, is the window \
+                 minimized?",
+                width, height
+            )));
+        }
+        let target = create_render_target(
+            &self.context.device,
+            color_pixel_format(self.output_format),
+            width,
+            height,
+            1,
+        );
+        let bytes_per_row = width as u64 * 4;
+        let readback_buffer = self.context.new_buffer(
+            bytes_per_row * height as u64,
+            MTLResourceOptions::StorageModeShared,
+        )?;
+
+        self.uniform_ring.begin_frame();
+        autoreleasepool(|| {
+            let command_buffer =
+                self.context.command_queue.new_command_buffer();
+            self.encode_frame(command_buffer, &target);
+
+            let blit_encoder = command_buffer.new_blit_command_encoder();
+            blit_encoder.copy_from_texture_to_buffer(
+                &target,
+                0,
+                0,
+                MTLOrigin { x: 0, y: 0, z: 0 },
+                MTLSize::new(width as u64, height as u64, 1),
+                &readback_buffer,
+                0,
+                bytes_per_row,
+                bytes_per_row * height as u64,
+                MTLBlitOption::empty(),
+            );
+            blit_encoder.end_encoding();
+
+            for mesh in &mut self.meshes {
+                mesh.signal_on_completion(command_buffer);
+            }
+            self.uniform_ring.signal_on_completion(command_buffer);
+            command_buffer.commit();
+            command_buffer.wait_until_completed();
+            check_completion(command_buffer)
+        })?;
+
+        let mut pixels = unsafe {
+            std::slice::from_raw_parts(
+                readback_buffer.contents() as *const u8,
+                readback_buffer.length() as usize,
+            )
+        }
+        .to_vec();
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        Ok((pixels, width, height))
+    }
+
+    // reads the pixel under the cursor back from the last scene drawn into
+    // offscreen_texture, which holds the stored values before any post pass
+    fn pick_color(
+        &self,
+        cursor: PhysicalPosition<f64>,
+    ) -> Result<Option<[f32; 4]>, MetalError> {
+        let texture = &self.offscreen_texture;
+        if cursor.x < 0.0
+            || cursor.y < 0.0
+            || cursor.x >= texture.width() as f64
+            || cursor.y >= texture.height() as f64
+        {
+            return Ok(None);
+        }
+        let float_pixels =
+            texture.pixel_format() == MTLPixelFormat::RGBA16Float;
+        let bytes_per_pixel = if float_pixels { 8 } else { 4 };
+        let readback_buffer = self.context.new_buffer(
+            bytes_per_pixel,
+            MTLResourceOptions::StorageModeShared,
+        )?;
+
+        autoreleasepool(|| {
+            let command_buffer =
+                self.context.command_queue.new_command_buffer();
+            let blit_encoder = command_buffer.new_blit_command_encoder();
+            blit_encoder.copy_from_texture_to_buffer(
+                texture,
+                0,
+                0,
+                MTLOrigin {
+                    x: cursor.x as u64,
+                    y: cursor.y as u64,
+                    z: 0,
+                },
+                MTLSize::new(1, 1, 1),
+                &readback_buffer,
+                0,
+                bytes_per_pixel,
+                bytes_per_pixel,
+                MTLBlitOption::empty(),
+            );
+            blit_encoder.end_encoding();
+
+            command_buffer.commit();
+            command_buffer.wait_until_completed();
+            check_completion(command_buffer)
+        })?;
+
+        let contents = readback_buffer.contents();
+        Ok(Some(if float_pixels {
+            let bits = unsafe { *(contents as *const [u16; 4]) };
+            bits.map(|bits| f16::from_bits(bits).to_f32())
+        } else {
+            // srgb targets store the encoded bytes, which for vertex colors
+            // are the original values again
+            let [b, g, r, a] = unsafe { *(contents as *const [u8; 4]) };
+            [r, g, b, a].map(|channel| channel as f32 / 255.0)
+        }))
+    }
+
+    fn log_color_at(&self, cursor: PhysicalPosition<f64>) {
+        match self.pick_color(cursor) {
+            Ok(Some([r, g, b, a])) => info!(
+                "Color at ({}, {}): rgba({:.3}, {:.3}, {:.3}, {:.3})",
+                cursor.x as u32, cursor.y as u32, r, g, b, a
+            ),
+            Ok(None) => (),
+            Err(err) => warn!("Color under the cursor not read: {}", err),
+        }
+    }
+
+    // the scene is drawn into offscreen_texture, then a full-screen pass
+    // samples it into the target so post effects can read the whole frame
+    fn encode_frame(
+        &self,
+        command_buffer: &CommandBufferRef,
+        target: &TextureRef,
+    ) {
+        self.encode_scene(command_buffer, &self.offscreen_texture);
+        self.encode_post(command_buffer, target);
+    }
+
+    fn encode_post(
+        &self,
+        command_buffer: &CommandBufferRef,
+        target: &TextureRef,
+    ) {
+        let render_pass_descriptor = RenderPassDescriptor::new();
+        let color_attachment = render_pass_descriptor
+            .color_attachments()
+            .object_at(0)
+            .unwrap();
+        color_attachment.set_texture(Some(target));
+        color_attachment.set_load_action(MTLLoadAction::DontCare);
+        color_attachment.set_store_action(MTLStoreAction::Store);
+
+        let render_encoder =
+            command_buffer.new_render_command_encoder(render_pass_descriptor);
+        render_encoder.set_render_pipeline_state(&self.pipelines.post);
+        render_encoder.set_fragment_texture(
+            AAPL_POST_TEXTURE_INDEX,
+            Some(if self.show_normals {
+                &self.normal_texture
+            } else {
+                &self.offscreen_texture
+            }),
+        );
+        let tonemap = (self.accumulate.is_some() && !self.show_normals) as u32;
+        render_encoder.set_fragment_bytes(
+            AAPL_POST_TONEMAP_INDEX,
+            size_of::<u32>() as u64,
+            &tonemap as *const u32 as *const c_void,
+        );
+        render_encoder.draw_primitives(MTLPrimitiveType::Triangle, 0, 3);
+        render_encoder.end_encoding();
+    }
+
+    fn encode_scene(
+        &self,
+        command_buffer: &CommandBufferRef,
+        target: &TextureRef,
+    ) {
+        let view_size = [target.width() as f32, target.height() as f32];
+
+        let (uniform_buffer, uniform_offset) =
+            self.uniform_ring.alloc(&self.uniforms());
+
+        let render_pass_descriptor = RenderPassDescriptor::new();
+        let color_attachment = render_pass_descriptor
+            .color_attachments()
+            .object_at(0)
+            .unwrap();
+        color_attachment.set_load_action(MTLLoadAction::Clear);
+        color_attachment.set_clear_color(if self.linearizes_colors() {
+            // clear colors are written as is, so linearize them like the
+            // vertex colors to land on the same srgb value on screen
+            linear_clear_color(self.clear_color)
+        } else {
+            self.clear_color
+        });
+        set_color_target(
+            color_attachment,
+            target,
+            self.msaa_texture.as_deref(),
+        );
+
+        // cleared to zero, which no packed normal can be, so the background
+        // stays distinguishable from geometry
+        let normal_attachment = render_pass_descriptor
+            .color_attachments()
+            .object_at(1)
+            .unwrap();
+        normal_attachment.set_load_action(MTLLoadAction::Clear);
+        normal_attachment
+            .set_clear_color(MTLClearColor::new(0.0, 0.0, 0.0, 0.0));
+        set_color_target(
+            normal_attachment,
+            &self.normal_texture,
+            self.msaa_normal_texture.as_deref(),
+        );
+
+        let depth_attachment =
+            render_pass_descriptor.depth_attachment().unwrap();
+        depth_attachment.set_texture(Some(&self.depth_texture));
+        depth_attachment.set_load_action(MTLLoadAction::Clear);
+        depth_attachment.set_clear_depth(1.0);
+        depth_attachment.set_store_action(MTLStoreAction::DontCare);
+
+        let stencil_attachment =
+            render_pass_descriptor.stencil_attachment().unwrap();
+        stencil_attachment.set_texture(Some(&self.depth_texture));
+        stencil_attachment.set_load_action(MTLLoadAction::Clear);
+        stencil_attachment.set_clear_stencil(0);
+        stencil_attachment.set_store_action(MTLStoreAction::DontCare);
+
+        let render_encoder =
+            command_buffer.new_render_command_encoder(render_pass_descriptor);
+
+        let viewport = MTLViewport {
+            originX: 0.0,
+            originY: 0.0,
+            width: view_size[0] as f64,
+            height: view_size[1] as f64,
+            znear: 0.0,
+            zfar: 1.0,
+        };
+        render_encoder.set_viewport(viewport);
+
+        if let Some(skybox) = &self.skybox {
+            skybox.encode(
+                render_encoder,
+                &self.pipelines.skybox,
+                &skybox::ndc_to_direction(
+                    &self.projection,
+                    self.zoom,
+                    self.zoom_offset,
+                    &self.orbit_matrix(),
+                ),
+                self.linearizes_colors(),
+            );
+        }
+
+        let stencil_reference = if self.stencil_mask {
+            render_encoder.set_render_pipeline_state(&self.pipelines.mask);
+            render_encoder
+                .set_depth_stencil_state(&self.mask_depth_stencil_state);
+            render_encoder.set_stencil_reference_value(stencil::MASK_REFERENCE);
+            render_encoder.draw_primitives(
+                MTLPrimitiveType::TriangleStrip,
+                0,
+                4,
+            );
+            stencil::MASK_REFERENCE
+        } else {
+            0
+        };
+
+        render_encoder.set_render_pipeline_state(&self.pipelines.scene);
+        render_encoder.set_depth_stencil_state(&self.depth_stencil_state);
+        render_encoder.set_stencil_reference_value(stencil_reference);
+        render_encoder.set_triangle_fill_mode(self.triangle_fill_mode);
+        self.cull_config.apply(render_encoder);
+
+        render_encoder.set_vertex_buffer(
+            AAPL_VERTEX_INPUT_INDEX_INSTANCES,
+            Some(&self.instance_buffer),
+            0,
+        );
+        render_encoder.set_vertex_buffer(
+            AAPL_VERTEX_INPUT_INDEX_UNIFORMS,
+            Some(&uniform_buffer),
+            uniform_offset,
+        );
+        render_encoder.set_fragment_buffer(
+            AAPL_FRAGMENT_UNIFORMS_INDEX,
+            Some(&uniform_buffer),
+            uniform_offset,
+        );
+
+        render_encoder.set_fragment_texture(
+            AAPL_FRAGMENT_TEXTURE_INDEX,
+            Some(if self.linearizes_colors() {
+                &self.srgb_texture
+            } else {
+                &self.texture
+            }),
+        );
+        render_encoder.set_fragment_sampler_state(
+            AAPL_FRAGMENT_SAMPLER_INDEX,
+            Some(&self.sampler_state),
+        );
+
+        for (_, mesh) in self.visible_meshes() {
+            if self.barycentric {
+                // indirect arguments count indices, so the de-indexed
+                // copy is always drawn directly
+                let drawn = mesh.draw_unindexed(
+                    &self.context.device,
+                    render_encoder,
+                    AAPL_VERTEX_INPUT_INDEX_VERTICES,
+                    primitive_type(self.primitive_mode),
+                    self.instance_count as u64,
+                );
+                if let Err(err) = drawn {
+                    warn!("Skipping a barycentric draw: {}", err);
+                }
+            } else if self.indirect {
+                mesh.draw_indirect(
+                    render_encoder,
+                    AAPL_VERTEX_INPUT_INDEX_VERTICES,
+                    primitive_type(self.primitive_mode),
+                );
+            } else {
+                mesh.draw(
+                    render_encoder,
+                    AAPL_VERTEX_INPUT_INDEX_VERTICES,
+                    primitive_type(self.primitive_mode),
+                    self.instance_count as u64,
+                );
+            }
+        }
+        render_encoder.end_encoding();
+    }
+}
+
+fn set_blend_mode(
+    attachment: &RenderPipelineColorAttachmentDescriptorRef,
+    blend_mode: BlendMode,
+) {
+    let (source_factor, destination_factor) = match blend_mode {
+        BlendMode::Opaque => return,
+        BlendMode::AlphaBlend => (
+            MTLBlendFactor::SourceAlpha,
+            MTLBlendFactor::OneMinusSourceAlpha,
+        ),
+        BlendMode::Additive => {
+            (MTLBlendFactor::SourceAlpha, MTLBlendFactor::One)
+        }
+    };
+    attachment.set_blending_enabled(true);
+    attachment.set_rgb_blend_operation(MTLBlendOperation::Add);
+    attachment.set_alpha_blend_operation(MTLBlendOperation::Add);
+    attachment.set_source_rgb_blend_factor(source_factor);
+    attachment.set_destination_rgb_blend_factor(destination_factor);
+    attachment.set_source_alpha_blend_factor(source_factor);
+    attachment.set_destination_alpha_blend_factor(destination_factor);
+}
+
+fn primitive_type(primitive_mode: PrimitiveMode) -> MTLPrimitiveType {
+    match primitive_mode {
+        PrimitiveMode::Triangle => MTLPrimitiveType::Triangle,
+        PrimitiveMode::Line => MTLPrimitiveType::Line,
+        PrimitiveMode::LineStrip => MTLPrimitiveType::LineStrip,
+        PrimitiveMode::Point => MTLPrimitiveType::Point,
+    }
+}
+
+fn load_shader_source() -> String {
+    std::fs::read_to_string(SHADER_PATH)
+        .unwrap_or_else(|_| include_str!("shaders.metal").to_string())
+}
+
+fn shader_modified_time() -> Option<SystemTime> {
+    std::fs::metadata(SHADER_PATH)
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+struct Pipelines {
+    scene: RenderPipelineState,
+    mask: RenderPipelineState,
+    skybox: RenderPipelineState,
+    post: RenderPipelineState,
+}
+
+impl Pipelines {
+    fn build(
+        context: &MetalContext,
+        shader_source: &ShaderSource,
+        sample_count: u32,
+        blend_mode: BlendMode,
+        scene_pixel_format: MTLPixelFormat,
+        output_pixel_format: MTLPixelFormat,
+    ) -> Result<Self, MetalError> {
+        let library = context.load_library(shader_source)?;
+        debug!(
+            "Building pipelines for {:?} (scene {:?}) with {}x msaa",
+            output_pixel_format, scene_pixel_format, sample_count
+        );
+        Ok(Pipelines {
+            scene: build_pipeline_state(
+                context,
+                &library,
+                sample_count,
+                blend_mode,
+                scene_pixel_format,
+            )?,
+            mask: build_mask_pipeline_state(
+                context,
+                &library,
+                sample_count,
+                scene_pixel_format,
+            )?,
+            skybox: build_skybox_pipeline_state(
+                context,
+                &library,
+                sample_count,
+                scene_pixel_format,
+            )?,
+            post: build_post_pipeline_state(
+                context,
+                &library,
+                output_pixel_format,
+            )?,
+        })
+    }
+}
+
+fn build_pipeline_state(
+    context: &MetalContext,
+    library: &LibraryRef,
+    sample_count: u32,
+    blend_mode: BlendMode,
+    color_pixel_format: MTLPixelFormat,
+) -> Result<RenderPipelineState, MetalError> {
+    let vertex_function = library
+        .get_function("vertexShader", None)
+        .map_err(MetalError::FunctionNotFound)?;
+    let fragment_function = library
+        .get_function("fragmentShader", None)
+        .map_err(MetalError::FunctionNotFound)?;
+
+    let pipeline_state_descriptor = RenderPipelineDescriptor::new();
+    pipeline_state_descriptor.set_label("Simple Pipeline");
+    pipeline_state_descriptor.set_vertex_function(Some(&vertex_function));
+    pipeline_state_descriptor.set_fragment_function(Some(&fragment_function));
+    let color_attachment = pipeline_state_descriptor
+        .color_attachments()
+        .object_at(0)
+        .unwrap();
+    color_attachment.set_pixel_format(color_pixel_format);
+    set_blend_mode(color_attachment, blend_mode);
+    pipeline_state_descriptor
+        .color_attachments()
+        .object_at(1)
+        .unwrap()
+        .set_pixel_format(NORMAL_PIXEL_FORMAT);
+    pipeline_state_descriptor
+        .set_depth_attachment_pixel_format(DEPTH_STENCIL_PIXEL_FORMAT);
+    pipeline_state_descriptor
+        .set_stencil_attachment_pixel_format(DEPTH_STENCIL_PIXEL_FORMAT);
+    pipeline_state_descriptor.set_sample_count(sample_count as u64);
+
+    let vertex_descriptor =
+        AAPLVertex::vertex_descriptor(AAPL_VERTEX_INPUT_INDEX_VERTICES);
+    pipeline_state_descriptor.set_vertex_descriptor(Some(&vertex_descriptor));
+
+    let (pipeline_state, reflection) = context
+        .device
+        .new_render_pipeline_state_with_reflection(
+            &pipeline_state_descriptor,
+            MTLPipelineOption::ArgumentInfo,
+        )
+        .map_err(MetalError::PipelineCreation)?;
+    check_fragment_bindings(reflection.fragment_arguments())?;
+    Ok(pipeline_state)
+}
+
+fn build_mask_pipeline_state(
+    context: &MetalContext,
+    library: &LibraryRef,
+    sample_count: u32,
+    color_pixel_format: MTLPixelFormat,
+) -> Result<RenderPipelineState, MetalError> {
+    let vertex_function = library
+        .get_function("maskVertexShader", None)
+        .map_err(MetalError::FunctionNotFound)?;
+    let fragment_function = library
+        .get_function("maskFragmentShader", None)
+        .map_err(MetalError::FunctionNotFound)?;
+
+    let pipeline_state_descriptor = RenderPipelineDescriptor::new();
+    pipeline_state_descriptor.set_label("Stencil Mask Pipeline");
+    pipeline_state_descriptor.set_vertex_function(Some(&vertex_function));
+    pipeline_state_descriptor.set_fragment_function(Some(&fragment_function));
+    let color_attachment = pipeline_state_descriptor
+        .color_attachments()
+        .object_at(0)
+        .unwrap();
+    color_attachment.set_pixel_format(color_pixel_format);
+    color_attachment.set_write_mask(MTLColorWriteMask::empty());
+    // every pipeline in the scene pass has to match both of its attachments
+    let normal_attachment = pipeline_state_descriptor
+        .color_attachments()
+        .object_at(1)
+        .unwrap();
+    normal_attachment.set_pixel_format(NORMAL_PIXEL_FORMAT);
+    normal_attachment.set_write_mask(MTLColorWriteMask::empty());
+    pipeline_state_descriptor
+        .set_depth_attachment_pixel_format(DEPTH_STENCIL_PIXEL_FORMAT);
+    pipeline_state_descriptor
+        .set_stencil_attachment_pixel_format(DEPTH_STENCIL_PIXEL_FORMAT);
+    pipeline_state_descriptor.set_sample_count(sample_count as u64);
+
+    context
+        .device
+        .new_render_pipeline_state(&pipeline_state_descriptor)
+        .map_err(MetalError::PipelineCreation)
+}
+
+fn build_skybox_pipeline_state(
+    context: &MetalContext,
+    library: &LibraryRef,
+    sample_count: u32,
+    color_pixel_format: MTLPixelFormat,
+) -> Result<RenderPipelineState, MetalError> {
+    let vertex_function = library
+        .get_function("skyboxVertexShader", None)
+        .map_err(MetalError::FunctionNotFound)?;
+    let fragment_function = library
+        .get_function("skyboxFragmentShader", None)
+        .map_err(MetalError::FunctionNotFound)?;
+
+    let pipeline_state_descriptor = RenderPipelineDescriptor::new();
+    pipeline_state_descriptor.set_label("Skybox Pipeline");
+    pipeline_state_descriptor.set_vertex_function(Some(&vertex_function));
+    pipeline_state_descriptor.set_fragment_function(Some(&fragment_function));
+    pipeline_state_descriptor
+        .color_attachments()
+        .object_at(0)
+        .unwrap()
+        .set_pixel_format(color_pixel_format);
+    // the normals stay cleared so the background still reads as empty
+    let normal_attachment = pipeline_state_descriptor
+        .color_attachments()
+        .object_at(1)
+        .unwrap();
+    normal_attachment.set_pixel_format(NORMAL_PIXEL_FORMAT);
+    normal_attachment.set_write_mask(MTLColorWriteMask::empty());
+    pipeline_state_descriptor
+        .set_depth_attachment_pixel_format(DEPTH_STENCIL_PIXEL_FORMAT);
+    pipeline_state_descriptor
+        .set_stencil_attachment_pixel_format(DEPTH_STENCIL_PIXEL_FORMAT);
+    pipeline_state_descriptor.set_sample_count(sample_count as u64);
+
+    context
+        .device
+        .new_render_pipeline_state(&pipeline_state_descriptor)
+        .map_err(MetalError::PipelineCreation)
+}
+
+fn build_post_pipeline_state(
+    context: &MetalContext,
+    library: &LibraryRef,
+    color_pixel_format: MTLPixelFormat,
+) -> Result<RenderPipelineState, MetalError> {
+    let vertex_function = library
+        .get_function("postVertexShader", None)
+        .map_err(MetalError::FunctionNotFound)?;
+    let fragment_function = library
+        .get_function("postFragmentShader", None)
+        .map_err(MetalError::FunctionNotFound)?;
+
+    let pipeline_state_descriptor = RenderPipelineDescriptor::new();
+    pipeline_state_descriptor.set_label("Post Process Pipeline");
+    pipeline_state_descriptor.set_vertex_function(Some(&vertex_function));
+    pipeline_state_descriptor.set_fragment_function(Some(&fragment_function));
+    pipeline_state_descriptor
+        .color_attachments()
+        .object_at(0)
+        .unwrap()
+        .set_pixel_format(color_pixel_format);
+
+    context
+        .device
+        .new_render_pipeline_state(&pipeline_state_descriptor)
+        .map_err(MetalError::PipelineCreation)
+}
+
+// encode_scene only binds one texture and one sampler, so reject shaders
+// that read from any other slot instead of letting them sample garbage.
+// MTLArgument is deprecated but metal-rs doesn't expose MTLBinding yet
+#[allow(deprecated)]
+fn check_fragment_bindings(
+    arguments: &ArgumentArrayRef,
+) -> Result<(), MetalError> {
+    for argument in
+        (0..arguments.count()).filter_map(|i| arguments.object_at(i))
+    {
+        let bound = match argument.type_() {
+            MTLArgumentType::Texture => {
+                argument.index() == AAPL_FRAGMENT_TEXTURE_INDEX
+            }
+            MTLArgumentType::Sampler => {
+                argument.index() == AAPL_FRAGMENT_SAMPLER_INDEX
+            }
+            _ => true,
+        };
+        if argument.is_active() && !bound {
+            return Err(MetalError::PipelineCreation(format!(
+                "fragment argument '{}' at index {} is never bound",
+                argument.name(),
+                argument.index()
+            )));
+        }
+    }
+    Ok(())
+}
+
+// picked by extension, obj models are always unindexed
+fn load_model_file(
+    path: &Path,
+) -> Result<(Vec<AAPLVertex>, Vec<u16>), MetalError> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("obj") => {
+            obj::load_obj(path).map(|vertices| (vertices, Vec::new()))
+        }
+        Some("ply") => ply::load_ply(path),
+        _ => Err(MetalError::MeshLoad(format!(
+            "{}: expected an .obj or .ply model",
+            path.display()
+        ))),
+    }
+}
+
+fn color_pixel_format(output_format: OutputFormat) -> MTLPixelFormat {
+    match output_format {
+        OutputFormat::Linear => MTLPixelFormat::BGRA8Unorm,
+        OutputFormat::Srgb => MTLPixelFormat::BGRA8Unorm_sRGB,
+        OutputFormat::ExtendedRange => MTLPixelFormat::RGBA16Float,
+    }
+}
+
+fn linear_clear_color(clear_color: MTLClearColor) -> MTLClearColor {
+    let linearize = |value: f64| {
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    MTLClearColor::new(
+        linearize(clear_color.red),
+        linearize(clear_color.green),
+        linearize(clear_color.blue),
+        clear_color.alpha,
+    )
+}
+
+fn clear_color_preset(index: usize) -> MTLClearColor {
+    let [red, green, blue, alpha] = CLEAR_COLOR_PRESETS[index];
+    MTLClearColor::new(red, green, blue, alpha)
+}
+
+// with msaa the samples land in the multisampled texture and get resolved
+// into target at the end of the pass
+fn set_color_target(
+    attachment: &RenderPassColorAttachmentDescriptorRef,
+    target: &TextureRef,
+    msaa_texture: Option<&TextureRef>,
+) {
+    if let Some(msaa_texture) = msaa_texture {
+        attachment.set_texture(Some(msaa_texture));
+        attachment.set_resolve_texture(Some(target));
+        attachment.set_store_action(MTLStoreAction::MultisampleResolve);
+    } else {
+        attachment.set_texture(Some(target));
+        attachment.set_store_action(MTLStoreAction::Store);
+    }
+}
+
+fn create_render_target(
+    device: &Device,
+    pixel_format: MTLPixelFormat,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+) -> Texture {
+    let descriptor = TextureDescriptor::new();
+    if sample_count > 1 {
+        descriptor.set_texture_type(MTLTextureType::D2Multisample);
+        descriptor.set_sample_count(sample_count as u64);
+    } else {
+        descriptor.set_texture_type(MTLTextureType::D2);
+    }
+    descriptor.set_pixel_format(pixel_format);
+    descriptor.set_width(width as u64);
+    descriptor.set_height(height as u64);
+    descriptor.set_storage_mode(MTLStorageMode::Private);
+    descriptor.set_usage(MTLTextureUsage::RenderTarget);
+    device.new_texture(&descriptor)
+}
+
+// single sampled target the scene resolves into, sampled by the post pass
+fn create_offscreen_texture(
+    device: &Device,
+    pixel_format: MTLPixelFormat,
+    width: u32,
+    height: u32,
+) -> Texture {
+    let descriptor = TextureDescriptor::new();
+    descriptor.set_texture_type(MTLTextureType::D2);
+    descriptor.set_pixel_format(pixel_format);
+    descriptor.set_width(width as u64);
+    descriptor.set_height(height as u64);
+    descriptor.set_storage_mode(MTLStorageMode::Private);
+    descriptor
+        .set_usage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
+    device.new_texture(&descriptor)
+}
+
+struct App {
+    args: Args,
+    config: RenderConfig,
+    // handed in by the unified gpu_misc binary, otherwise created from
+    // --device when the window is
+    context: Option<MetalContext>,
+    window: Option<Arc<Window>>,
+    metal_state: Option<MetalState>,
+    drag: Option<Drag>,
+    last_cursor: Option<PhysicalPosition<f64>>,
+}
+
+impl App {
+    fn new(
+        args: Args,
+        config: RenderConfig,
+        context: Option<MetalContext>,
+    ) -> Self {
+        App {
+            args,
+            config,
+            context,
+            window: None,
+            metal_state: None,
+            drag: None,
+            last_cursor: None,
+        }
+    }
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let window = Arc::new(
+            event_loop
+                .create_window(
+                    Window::default_attributes()
+                        .with_title(&self.config.title)
+                        .with_inner_size(winit::dpi::LogicalSize::new(
+                            800.0, 600.0,
+                        )),
+                )
+                .unwrap(),
+        );
+
+        let geometry = if self.args.models.is_empty() {
+            vec![match self.args.shape {
+                Shape::Triangle => geometry::triangle(),
+                Shape::Quad => geometry::quad(),
+                Shape::Grid { cols, rows } => geometry::make_grid(cols, rows),
+            }]
+        } else {
+            let loaded = self
+                .args
+                .models
+                .iter()
+                .map(|path| load_model_file(path))
+                .collect::<Result<Vec<_>, _>>();
+            match loaded {
+                Ok(geometry) => geometry,
+                Err(err) => {
+                    error!("{}", err);
+                    event_loop.exit();
+                    return;
+                }
+            }
+        };
+
+        let context = match self.context.take() {
+            Some(context) => context,
+            None => match MetalContext::new(&self.args.device) {
+                Ok(context) => context,
+                Err(err) => {
+                    error!("{}", err);
+                    event_loop.exit();
+                    return;
+                }
+            },
+        };
+
+        match MetalState::new(
+            window.clone(),
+            context,
+            &self.config,
+            &geometry,
+            &self.args.texture,
+            self.args.mipmaps,
+            self.args.metallib.as_deref(),
+            self.args.blend_mode,
+        ) {
+            Ok(mut metal_state) => {
+                if self.args.info {
+                    print_device_info(&metal_state.context.device);
+                }
+                metal_state.set_primitive_mode(self.args.primitive_mode);
+                metal_state.set_instance_count(self.args.instance_count);
+                if self.args.indirect {
+                    metal_state.set_indirect(true);
+                }
+                if self.args.wave {
+                    metal_state.set_wave(true);
+                }
+                if let Some(path) = &self.args.skybox {
+                    metal_state.load_skybox(path);
+                }
+                if let Some(alpha) = self.args.accumulate {
+                    metal_state.set_accumulate(alpha);
+                }
+                if let Some(fps) = self.args.fps {
+                    metal_state.set_fps_cap(fps);
+                }
+                if let Some(speed) = self.args.rotation_speed {
+                    metal_state.set_rotation_speed(speed);
+                }
+                metal_state.grid = match self.args.shape {
+                    Shape::Grid { cols, rows }
+                        if self.args.models.is_empty() =>
+                    {
+                        Some((cols, rows))
+                    }
+                    _ => None,
+                };
+                let verified = if self.args.verify_indirect {
+                    metal_state.verify_indirect()
+                } else {
+                    Ok(())
+                };
+                if let Err(err) = verified {
+                    error!("{}", err);
+                    event_loop.exit();
+                }
+                metal_state.window.request_redraw();
+                self.metal_state = Some(metal_state);
+            }
+            Err(err) => {
+                error!("Failed to initialize Metal: {}", err);
+                event_loop.exit();
+            }
+        }
+        self.window = Some(window);
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        _id: WindowId,
+        event: WindowEvent,
+    ) {
+        if let Some(metal_state) = &mut self.metal_state {
+            match event {
+                WindowEvent::CloseRequested => event_loop.exit(),
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            physical_key: PhysicalKey::Code(key_code),
+                            state,
+                            repeat,
+                            ..
+                        },
+                    ..
+                } => {
+                    metal_state.set_key_held(key_code, state.is_pressed());
+                    if state.is_pressed() && !repeat {
+                        match key_code {
+                            KeyCode::Escape => event_loop.exit(),
+                            KeyCode::F11 => metal_state.toggle_fullscreen(),
+                            key_code => metal_state.handle_key(key_code),
+                        }
+                    }
+                }
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Left,
+                    ..
+                } => {
+                    // grabbing a vertex takes priority, anywhere else orbits
+                    let last_cursor = self.last_cursor;
+                    self.drag = (state == ElementState::Pressed).then(|| {
+                        last_cursor
+                            .and_then(|cursor| metal_state.pick_vertex(cursor))
+                            .map_or(Drag::Orbit, Drag::Vertex)
+                    });
+                }
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button: MouseButton::Right,
+                    ..
+                } => {
+                    if let Some(cursor) = self.last_cursor {
+                        metal_state.log_color_at(cursor);
+                    }
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    match (self.drag, self.last_cursor) {
+                        (Some(Drag::Orbit), Some(last)) => metal_state.orbit(
+                            (position.x - last.x) as f32,
+                            (position.y - last.y) as f32,
+                        ),
+                        (Some(Drag::Vertex(pick)), _) => {
+                            metal_state.drag_vertex(pick, position)
+                        }
+                        _ => (),
+                    }
+                    self.last_cursor = Some(position);
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let lines = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y,
+                        MouseScrollDelta::PixelDelta(position) => {
+                            position.y as f32 / PIXELS_PER_WHEEL_LINE
+                        }
+                    };
+                    if let Some(cursor) = self.last_cursor {
+                        metal_state.zoom_at(ZOOM_STEP.powf(lines), cursor);
+                    }
+                }
+                // some platforms report minimizing as a resize to zero
+                WindowEvent::Resized(new_size) => {
+                    if new_size.width == 0 || new_size.height == 0 {
+                        metal_state.pause();
+                    } else {
+                        metal_state.resize(new_size);
+                        metal_state.resume();
+                    }
+                }
+                WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                    metal_state.set_scale_factor(scale_factor);
+                    let new_size = metal_state.window.inner_size();
+                    metal_state.resize(new_size);
+                }
+                WindowEvent::DroppedFile(path) => {
+                    // a vertex being dragged may belong to the old meshes
+                    self.drag = None;
+                    metal_state.load_model(&path);
+                }
+                WindowEvent::Focused(false) => metal_state.held_keys.clear(),
+                WindowEvent::Occluded(true) => metal_state.pause(),
+                WindowEvent::Occluded(false) => metal_state.resume(),
+                WindowEvent::RedrawRequested
+                    if metal_state.paused_since.is_none() =>
+                {
+                    metal_state.render();
+                    metal_state.window.request_redraw();
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+pub fn run(args: Args, config: RenderConfig) {
+    run_app(App::new(args, config, None));
+}
+
+// for callers that already picked a device, e.g. the unified gpu_misc binary
+pub fn run_with_context(
+    args: Args,
+    config: RenderConfig,
+    context: MetalContext,
+) {
+    run_app(App::new(args, config, Some(context)));
+}
+
+fn run_app(mut app: App) {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.run_app(&mut app).expect("Failed to run app");
+}
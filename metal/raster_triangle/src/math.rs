@@ -0,0 +1,126 @@
+pub type Mat4 = [[f32; 4]; 4];
+
+pub fn rotation_x(angle: f32) -> Mat4 {
+    let (s, c) = angle.sin_cos();
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, c, s, 0.0],
+        [0.0, -s, c, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+pub fn rotation_y(angle: f32) -> Mat4 {
+    let (s, c) = angle.sin_cos();
+    [
+        [c, 0.0, -s, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [s, 0.0, c, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+pub fn rotation_z(angle: f32) -> Mat4 {
+    let (s, c) = angle.sin_cos();
+    [
+        [c, s, 0.0, 0.0],
+        [-s, c, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
+    let f = 1.0 / (fov_y / 2.0).tan();
+    let range = near - far;
+    [
+        [f / aspect, 0.0, 0.0, 0.0],
+        [0.0, f, 0.0, 0.0],
+        [0.0, 0.0, far / range, -1.0],
+        [0.0, 0.0, near * far / range, 0.0],
+    ]
+}
+
+pub fn mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut out = [[0.0; 4]; 4];
+    for (col, out_col) in out.iter_mut().enumerate() {
+        for (row, value) in out_col.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[col][k]).sum();
+        }
+    }
+    out
+}
+
+pub fn translation(offset: [f32; 3]) -> Mat4 {
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [offset[0], offset[1], offset[2], 1.0],
+    ]
+}
+
+pub fn scaling(factor: f32) -> Mat4 {
+    [
+        [factor, 0.0, 0.0, 0.0],
+        [0.0, factor, 0.0, 0.0],
+        [0.0, 0.0, factor, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+// the inverse for pure rotations
+pub fn transpose(m: &Mat4) -> Mat4 {
+    std::array::from_fn(|col| std::array::from_fn(|row| m[row][col]))
+}
+
+pub fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+// zero length vectors come back unchanged rather than as nan
+pub fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = v.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if length <= f32::EPSILON {
+        return v;
+    }
+    v.map(|value| value / length)
+}
+
+pub fn transform(m: &Mat4, point: [f32; 3]) -> [f32; 4] {
+    let mut out = m[3];
+    for (col, value) in point.iter().enumerate() {
+        for (row, out_value) in out.iter_mut().enumerate() {
+            *out_value += m[col][row] * value;
+        }
+    }
+    out
+}
+
+// finds the point on the plane z = plane_z whose projection through m lands
+// on the given ndc position, none when the plane is seen edge on
+pub fn unproject_on_plane(
+    m: &Mat4,
+    ndc: [f32; 2],
+    plane_z: f32,
+) -> Option<[f32; 2]> {
+    // clip.x - ndc.x * clip.w = 0 and the same for y are linear in x and y
+    let row = |r: usize, target: f32| {
+        [
+            m[0][r] - target * m[0][3],
+            m[1][r] - target * m[1][3],
+            -(m[2][r] - target * m[2][3]) * plane_z
+                - (m[3][r] - target * m[3][3]),
+        ]
+    };
+    let ([a, b, e], [c, d, f]) = (row(0, ndc[0]), row(1, ndc[1]));
+    let det = a * d - b * c;
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    Some([(e * d - b * f) / det, (a * f - e * c) / det])
+}
@@ -0,0 +1,304 @@
+use std::mem::size_of_val;
+
+use metal::*;
+use metal_common::{MetalContext, MetalError, allocate_buffer};
+
+use crate::geometry::{self, AAPLVertex};
+use crate::vertex_ring::VertexRing;
+
+pub struct Mesh {
+    vertex_buffer: Buffer,
+    vertex_count: u64,
+    index_buffer: Option<Buffer>,
+    index_count: u64,
+    // MTLDrawPrimitivesIndirectArguments, or the indexed variant when the
+    // mesh has an index buffer
+    indirect_buffer: Buffer,
+    // per frame vertices drawn instead of vertex_buffer while animating
+    stream: Option<VertexRing>,
+}
+
+impl Mesh {
+    pub fn new(
+        context: &MetalContext,
+        vertices: &[AAPLVertex],
+        indices: &[u16],
+    ) -> Result<Self, MetalError> {
+        let indirect_buffer = if indices.is_empty() {
+            context.make_shared_buffer(&[
+                MTLDrawPrimitivesIndirectArguments {
+                    vertexCount: vertices.len() as u32,
+                    instanceCount: 1,
+                    vertexStart: 0,
+                    baseInstance: 0,
+                },
+            ])?
+        } else {
+            context.make_shared_buffer(&[
+                MTLDrawIndexedPrimitivesIndirectArguments {
+                    indexCount: indices.len() as u32,
+                    instanceCount: 1,
+                    indexStart: 0,
+                    baseVertex: 0,
+                    baseInstance: 0,
+                },
+            ])?
+        };
+
+        Ok(Mesh {
+            vertex_buffer: context.make_shared_buffer(vertices)?,
+            vertex_count: vertices.len() as u64,
+            index_buffer: (!indices.is_empty())
+                .then(|| context.make_shared_buffer(indices))
+                .transpose()?,
+            index_count: indices.len() as u64,
+            indirect_buffer,
+            stream: None,
+        })
+    }
+
+    // rewrites the buffers in place while the new geometry fits and only
+    // allocates when it grows, keeping the instance count already set in
+    // the indirect arguments
+    pub fn set_geometry(
+        &mut self,
+        device: &DeviceRef,
+        vertices: &[AAPLVertex],
+        indices: &[u16],
+    ) -> Result<(), MetalError> {
+        assert_eq!(
+            indices.is_empty(),
+            self.index_buffer.is_none(),
+            "mesh can't switch between indexed and non-indexed draws"
+        );
+        // the counts only move once both buffers hold the new geometry
+        write_or_grow(device, &mut self.vertex_buffer, vertices)?;
+        if let Some(index_buffer) = &mut self.index_buffer {
+            write_or_grow(device, index_buffer, indices)?;
+        }
+        self.vertex_count = vertices.len() as u64;
+        self.index_count = indices.len() as u64;
+
+        let arguments = self.indirect_buffer.contents();
+        unsafe {
+            if self.index_buffer.is_some() {
+                (*(arguments
+                    as *mut MTLDrawIndexedPrimitivesIndirectArguments))
+                    .indexCount = indices.len() as u32;
+            } else {
+                (*(arguments as *mut MTLDrawPrimitivesIndirectArguments))
+                    .vertexCount = vertices.len() as u32;
+            }
+        }
+        Ok(())
+    }
+
+    // zero when the mesh draws its vertices in order
+    pub fn index_count(&self) -> u64 {
+        self.index_count
+    }
+
+    pub fn vertices(&self) -> &[AAPLVertex] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self.vertex_buffer.contents() as *const AAPLVertex,
+                self.vertex_count as usize,
+            )
+        }
+    }
+
+    // the buffer is shared, so the next frame draws the new position without
+    // any reupload
+    pub fn set_vertex_position(&self, index: usize, position: [f32; 3]) {
+        assert!(index < self.vertex_count as usize);
+        unsafe {
+            (*(self.vertex_buffer.contents() as *mut AAPLVertex).add(index))
+                .position = position;
+        }
+    }
+
+    // draws from a ring of per frame buffers from now on, vertex_buffer
+    // keeps the unanimated positions
+    pub fn stream_vertices(
+        &mut self,
+        device: &DeviceRef,
+        vertices: &[AAPLVertex],
+    ) -> Result<(), MetalError> {
+        assert_eq!(vertices.len(), self.vertex_count as usize);
+        let stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => VertexRing::new(device, vertices.len())?,
+        };
+        self.stream.insert(stream).write_vertices(vertices)
+    }
+
+    pub fn stop_streaming(&mut self) {
+        self.stream = None;
+    }
+
+    pub fn signal_on_completion(&mut self, command_buffer: &CommandBufferRef) {
+        if let Some(stream) = &mut self.stream {
+            stream.signal_on_completion(command_buffer);
+        }
+    }
+
+    fn bound_vertex_buffer(&self) -> &BufferRef {
+        match &self.stream {
+            Some(stream) => stream.current_buffer(),
+            None => &self.vertex_buffer,
+        }
+    }
+
+    pub fn set_indirect_instance_count(&self, instance_count: u32) {
+        let arguments = self.indirect_buffer.contents();
+        unsafe {
+            if self.index_buffer.is_some() {
+                (*(arguments
+                    as *mut MTLDrawIndexedPrimitivesIndirectArguments))
+                    .instanceCount = instance_count;
+            } else {
+                (*(arguments as *mut MTLDrawPrimitivesIndirectArguments))
+                    .instanceCount = instance_count;
+            }
+        }
+    }
+
+    pub fn draw(
+        &self,
+        render_encoder: &RenderCommandEncoderRef,
+        index: u64,
+        primitive_type: MTLPrimitiveType,
+        instance_count: u64,
+    ) {
+        render_encoder.set_vertex_buffer(
+            index,
+            Some(self.bound_vertex_buffer()),
+            0,
+        );
+
+        if let Some(index_buffer) = &self.index_buffer {
+            render_encoder.draw_indexed_primitives_instanced(
+                primitive_type,
+                self.index_count,
+                MTLIndexType::UInt16,
+                index_buffer,
+                0,
+                instance_count,
+            );
+        } else {
+            render_encoder.draw_primitives_instanced(
+                primitive_type,
+                0,
+                self.vertex_count,
+                instance_count,
+            );
+        }
+    }
+
+    // every triangle gets its own three vertices, for the barycentric view
+    // which tells corners apart by vertex_id. the copy is rebuilt each call
+    // from what the frame draws, so streamed and dragged vertices show up,
+    // and the command buffer keeps it alive while it's read. nothing is
+    // drawn when the copy can't be allocated
+    pub fn draw_unindexed(
+        &self,
+        device: &DeviceRef,
+        render_encoder: &RenderCommandEncoderRef,
+        index: u64,
+        primitive_type: MTLPrimitiveType,
+        instance_count: u64,
+    ) -> Result<(), MetalError> {
+        let Some(index_buffer) = &self.index_buffer else {
+            self.draw(render_encoder, index, primitive_type, instance_count);
+            return Ok(());
+        };
+        let (vertices, indices) = unsafe {
+            (
+                std::slice::from_raw_parts(
+                    self.bound_vertex_buffer().contents() as *const AAPLVertex,
+                    self.vertex_count as usize,
+                ),
+                std::slice::from_raw_parts(
+                    index_buffer.contents() as *const u16,
+                    self.index_count as usize,
+                ),
+            )
+        };
+        let unindexed = geometry::unindex(vertices, indices);
+        let vertex_buffer = allocate_buffer(
+            device,
+            size_of_val(unindexed.as_slice()) as u64,
+            MTLResourceOptions::StorageModeShared,
+        )?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                unindexed.as_ptr(),
+                vertex_buffer.contents() as *mut AAPLVertex,
+                unindexed.len(),
+            );
+        }
+        render_encoder.set_vertex_buffer(index, Some(&vertex_buffer), 0);
+        render_encoder.draw_primitives_instanced(
+            primitive_type,
+            0,
+            unindexed.len() as u64,
+            instance_count,
+        );
+        Ok(())
+    }
+
+    pub fn draw_indirect(
+        &self,
+        render_encoder: &RenderCommandEncoderRef,
+        index: u64,
+        primitive_type: MTLPrimitiveType,
+    ) {
+        render_encoder.set_vertex_buffer(
+            index,
+            Some(self.bound_vertex_buffer()),
+            0,
+        );
+
+        if let Some(index_buffer) = &self.index_buffer {
+            render_encoder.draw_indexed_primitives_indirect(
+                primitive_type,
+                MTLIndexType::UInt16,
+                index_buffer,
+                0,
+                &self.indirect_buffer,
+                0,
+            );
+        } else {
+            render_encoder.draw_primitives_indirect(
+                primitive_type,
+                &self.indirect_buffer,
+                0,
+            );
+        }
+    }
+}
+
+// a frame still in flight may read a mix of old and new data for one frame,
+// the buffer is never shrunk so its reads stay in bounds
+fn write_or_grow<T: Copy>(
+    device: &DeviceRef,
+    buffer: &mut Buffer,
+    data: &[T],
+) -> Result<(), MetalError> {
+    let length = size_of_val(data) as u64;
+    if length > buffer.length() {
+        *buffer = allocate_buffer(
+            device,
+            length.next_power_of_two(),
+            MTLResourceOptions::StorageModeShared,
+        )?;
+    }
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            data.as_ptr(),
+            buffer.contents() as *mut T,
+            data.len(),
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::path::Path;
+
+use metal_common::MetalError;
+
+use crate::geometry::AAPLVertex;
+use crate::math;
+
+const DEFAULT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const DEFAULT_TEX_COORD: [f32; 2] = [0.0, 0.0];
+// replaced by the face normal when a face doesn't reference any
+const NO_NORMAL: [f32; 3] = [0.0, 0.0, 0.0];
+const FIT_EXTENT: f32 = 0.5;
+
+pub fn load_obj(path: &Path) -> Result<Vec<AAPLVertex>, MetalError> {
+    let source = std::fs::read_to_string(path).map_err(|err| {
+        MetalError::MeshLoad(format!("{}: {}", path.display(), err))
+    })?;
+    let parse_error = |line: usize, msg: &str| {
+        MetalError::MeshLoad(format!("{}:{}: {}", path.display(), line, msg))
+    };
+
+    let mut positions = Vec::new();
+    let mut tex_coords = Vec::new();
+    let mut normals = Vec::new();
+    let mut vertices = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let values = tokens
+                    .map(str::parse::<f32>)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| parse_error(line_number, "invalid vertex"))?;
+                let vertex = match values.as_slice() {
+                    [x, y, z] | [x, y, z, _] => AAPLVertex {
+                        position: [*x, *y, *z],
+                        color: DEFAULT_COLOR,
+                        tex_coord: DEFAULT_TEX_COORD,
+                        normal: NO_NORMAL,
+                    },
+                    [x, y, z, r, g, b] => AAPLVertex {
+                        position: [*x, *y, *z],
+                        color: [*r, *g, *b, 1.0],
+                        tex_coord: DEFAULT_TEX_COORD,
+                        normal: NO_NORMAL,
+                    },
+                    _ => {
+                        return Err(parse_error(line_number, "invalid vertex"));
+                    }
+                };
+                positions.push(vertex);
+            }
+            Some("vt") => {
+                let values = tokens
+                    .map(str::parse::<f32>)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| parse_error(line_number, "invalid uv"))?;
+                match values.as_slice() {
+                    // obj puts v = 0 at the bottom, metal textures at the top
+                    [u, v] | [u, v, _] => tex_coords.push([*u, 1.0 - *v]),
+                    _ => return Err(parse_error(line_number, "invalid uv")),
+                }
+            }
+            Some("vn") => {
+                let values = tokens
+                    .map(str::parse::<f32>)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| parse_error(line_number, "invalid normal"))?;
+                match values.as_slice() {
+                    [x, y, z] => normals.push(math::normalize([*x, *y, *z])),
+                    _ => {
+                        return Err(parse_error(line_number, "invalid normal"));
+                    }
+                }
+            }
+            Some("f") => {
+                let mut face = tokens
+                    .map(|token| {
+                        resolve_corner(token, &positions, &tex_coords, &normals)
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| parse_error(line_number, "invalid face"))?;
+                if face.len() < 3 {
+                    return Err(parse_error(
+                        line_number,
+                        "face needs 3 indices",
+                    ));
+                }
+                if face.iter().any(|vertex| vertex.normal == NO_NORMAL) {
+                    let normal = face_normal(&face);
+                    face.iter_mut().for_each(|vertex| vertex.normal = normal);
+                }
+                for i in 1..face.len() - 1 {
+                    vertices.push(face[0]);
+                    vertices.push(face[i]);
+                    vertices.push(face[i + 1]);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if vertices.is_empty() {
+        return Err(MetalError::MeshLoad(format!(
+            "{}: no faces found",
+            path.display()
+        )));
+    }
+
+    fit_to_extent(&mut vertices);
+    Ok(vertices)
+}
+
+fn resolve_corner(
+    token: &str,
+    positions: &[AAPLVertex],
+    tex_coords: &[[f32; 2]],
+    normals: &[[f32; 3]],
+) -> Option<AAPLVertex> {
+    let mut parts = token.split('/');
+    let mut vertex = positions[resolve_index(parts.next()?, positions.len())?];
+    match parts.next() {
+        None | Some("") => (),
+        Some(part) => {
+            vertex.tex_coord =
+                tex_coords[resolve_index(part, tex_coords.len())?];
+        }
+    }
+    if let Some(part) = parts.next() {
+        vertex.normal = normals[resolve_index(part, normals.len())?];
+    }
+    Some(vertex)
+}
+
+// from the first three corners, counter clockwise faces point towards the
+// viewer as obj expects
+fn face_normal(face: &[AAPLVertex]) -> [f32; 3] {
+    let [a, b, c] = [0, 1, 2].map(|corner| face[corner].position);
+    let ab = [0, 1, 2].map(|axis| b[axis] - a[axis]);
+    let ac = [0, 1, 2].map(|axis| c[axis] - a[axis]);
+    math::normalize(math::cross(ab, ac))
+}
+
+fn resolve_index(token: &str, count: usize) -> Option<usize> {
+    let index: i64 = token.parse().ok()?;
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    (0..count as i64)
+        .contains(&resolved)
+        .then_some(resolved as usize)
+}
+
+// centers the model and scales its largest axis to FIT_EXTENT
+pub fn fit_to_extent(vertices: &mut [AAPLVertex]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for vertex in vertices.iter() {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(vertex.position[axis]);
+            max[axis] = max[axis].max(vertex.position[axis]);
+        }
+    }
+
+    let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+    let half_size = [0, 1, 2]
+        .map(|axis| (max[axis] - min[axis]) / 2.0)
+        .into_iter()
+        .fold(1e-6, f32::max);
+    let scale = FIT_EXTENT / half_size;
+    for vertex in vertices.iter_mut() {
+        for (value, center) in vertex.position.iter_mut().zip(center) {
+            *value = (*value - center) * scale;
+        }
+    }
+}
@@ -0,0 +1,580 @@
+use std::path::Path;
+
+use log::info;
+use metal_common::MetalError;
+
+use crate::geometry::{AAPLVertex, FACING_CAMERA, MAX_INDEXED_VERTICES};
+use crate::math;
+use crate::obj::fit_to_extent;
+
+const DEFAULT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const DEFAULT_TEX_COORD: [f32; 2] = [0.0, 0.0];
+const END_HEADER: &[u8] = b"end_header";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    // unsigned channels span their type's whole range, the rest are meant
+    // to be in [0, 1] already and are only clamped to it
+    fn normalize_color(self, value: f64) -> f32 {
+        let value = match self {
+            Scalar::U8 => value / u8::MAX as f64,
+            Scalar::U16 => value / u16::MAX as f64,
+            Scalar::U32 => value / u32::MAX as f64,
+            _ => value,
+        };
+        value.clamp(0.0, 1.0) as f32
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    // a count followed by that many items, faces store their corners so
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// the body is either whitespace separated text or packed values in the
+// header's byte order
+enum Reader<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl Reader<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, String> {
+        match self {
+            Reader::Ascii(tokens) => {
+                let token = tokens.next().ok_or("unexpected end of data")?;
+                token
+                    .parse()
+                    .map_err(|_| format!("invalid value '{}'", token))
+            }
+            Reader::Binary { .. } => Ok(match scalar {
+                Scalar::I8 => i8::from_le_bytes(self.take()?) as f64,
+                Scalar::U8 => u8::from_le_bytes(self.take()?) as f64,
+                Scalar::I16 => i16::from_le_bytes(self.take()?) as f64,
+                Scalar::U16 => u16::from_le_bytes(self.take()?) as f64,
+                Scalar::I32 => i32::from_le_bytes(self.take()?) as f64,
+                Scalar::U32 => u32::from_le_bytes(self.take()?) as f64,
+                Scalar::F32 => f32::from_le_bytes(self.take()?) as f64,
+                Scalar::F64 => f64::from_le_bytes(self.take()?),
+            }),
+        }
+    }
+
+    // the next N bytes in little endian order
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let Reader::Binary { bytes, big_endian } = self else {
+            unreachable!("only binary bodies are read as bytes");
+        };
+        let (value, rest) = bytes
+            .split_first_chunk::<N>()
+            .ok_or("unexpected end of data")?;
+        let mut value = *value;
+        if *big_endian {
+            value.reverse();
+        }
+        *bytes = rest;
+        Ok(value)
+    }
+
+    fn read_property(
+        &mut self,
+        property: &Property,
+    ) -> Result<Vec<f64>, String> {
+        match property {
+            Property::Scalar(_, scalar) => Ok(vec![self.read(*scalar)?]),
+            Property::List(_, count, item) => {
+                let count = self.read(*count)? as usize;
+                (0..count).map(|_| self.read(*item)).collect()
+            }
+        }
+    }
+}
+
+// vertices with their positions and optional colors and normals, plus the
+// faces fanned into triangles. models too big for u16 indices are expanded
+// into plain triangles, and point clouds without faces come back unindexed
+pub fn load_ply(
+    path: &Path,
+) -> Result<(Vec<AAPLVertex>, Vec<u16>), MetalError> {
+    let bytes = std::fs::read(path).map_err(|err| {
+        MetalError::MeshLoad(format!("{}: {}", path.display(), err))
+    })?;
+    let (mut vertices, indices) = parse_ply(&bytes).map_err(|msg| {
+        MetalError::MeshLoad(format!("{}: {}", path.display(), msg))
+    })?;
+    fit_to_extent(&mut vertices);
+    Ok((vertices, indices))
+}
+
+fn parse_ply(bytes: &[u8]) -> Result<(Vec<AAPLVertex>, Vec<u16>), String> {
+    let (format, elements, body) = parse_header(bytes)?;
+    let mut reader = match format {
+        Format::Ascii => Reader::Ascii(
+            std::str::from_utf8(body)
+                .map_err(|_| "ascii body isn't valid utf-8")?
+                .split_ascii_whitespace(),
+        ),
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => {
+            Reader::Binary {
+                bytes: body,
+                big_endian: format == Format::BinaryBigEndian,
+            }
+        }
+    };
+
+    let mut vertices = Vec::new();
+    let mut has_normals = false;
+    let mut indices = Vec::new();
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                has_normals = ["nx", "ny", "nz"]
+                    .iter()
+                    .all(|name| find(element, name).is_some());
+                vertices = read_vertices(&mut reader, element)?;
+            }
+            "face" => {
+                indices = read_faces(&mut reader, element, vertices.len())?;
+            }
+            _ => {
+                // read past elements nothing here uses
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        reader.read_property(property)?;
+                    }
+                }
+            }
+        }
+    }
+
+    if vertices.is_empty() {
+        return Err("no vertices found".to_string());
+    }
+    if indices.is_empty() {
+        info!(
+            "No faces, --primitive point draws the vertices as a point cloud"
+        );
+        if !has_normals {
+            vertices
+                .iter_mut()
+                .for_each(|vertex| vertex.normal = FACING_CAMERA);
+        }
+        return Ok((vertices, Vec::new()));
+    }
+    if !has_normals {
+        smooth_normals(&mut vertices, &indices);
+    }
+
+    if vertices.len() > MAX_INDEXED_VERTICES {
+        let expanded = indices.iter().map(|&index| vertices[index]).collect();
+        return Ok((expanded, Vec::new()));
+    }
+    Ok((
+        vertices,
+        indices.into_iter().map(|index| index as u16).collect(),
+    ))
+}
+
+fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, &[u8]), String> {
+    let end = bytes
+        .windows(END_HEADER.len())
+        .position(|window| window == END_HEADER)
+        .ok_or("missing end_header")?;
+    // the body starts after the newline that ends the end_header line
+    let body_start = bytes[end..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(bytes.len(), |newline| end + newline + 1);
+    let header = std::str::from_utf8(&bytes[..end])
+        .map_err(|_| "header isn't valid utf-8")?;
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err("not a ply file".to_string());
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        match tokens.as_slice() {
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    other => return Err(format!("unknown format {}", other)),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("invalid {} count", name))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let property = Property::List(
+                    name.to_string(),
+                    scalar(count)?,
+                    scalar(item)?,
+                );
+                elements
+                    .last_mut()
+                    .ok_or("property before any element")?
+                    .properties
+                    .push(property);
+            }
+            ["property", ty, name] => {
+                let property = Property::Scalar(name.to_string(), scalar(ty)?);
+                elements
+                    .last_mut()
+                    .ok_or("property before any element")?
+                    .properties
+                    .push(property);
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => (),
+            _ => return Err(format!("unexpected header line '{}'", line)),
+        }
+    }
+
+    let format = format.ok_or("missing format")?;
+    Ok((format, elements, &bytes[body_start..]))
+}
+
+fn scalar(name: &str) -> Result<Scalar, String> {
+    Scalar::parse(name).ok_or_else(|| format!("unknown property type {}", name))
+}
+
+fn find(element: &Element, name: &str) -> Option<usize> {
+    element
+        .properties
+        .iter()
+        .position(|property| property.name() == name)
+}
+
+fn read_vertices(
+    reader: &mut Reader,
+    element: &Element,
+) -> Result<Vec<AAPLVertex>, String> {
+    let position = ["x", "y", "z"].map(|name| find(element, name));
+    let [Some(x), Some(y), Some(z)] = position else {
+        return Err("vertices need x, y and z".to_string());
+    };
+    let normal = ["nx", "ny", "nz"].map(|name| find(element, name));
+    let color = ["red", "green", "blue", "alpha"].map(|name| {
+        find(element, name).and_then(|index| match element.properties[index] {
+            Property::Scalar(_, scalar) => Some((index, scalar)),
+            Property::List(..) => None,
+        })
+    });
+
+    (0..element.count)
+        .map(|_| {
+            let values = element
+                .properties
+                .iter()
+                .map(|property| {
+                    reader.read_property(property).map(|values| {
+                        values.first().copied().unwrap_or_default()
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut vertex = AAPLVertex {
+                position: [x, y, z].map(|index| values[index] as f32),
+                color: DEFAULT_COLOR,
+                tex_coord: DEFAULT_TEX_COORD,
+                normal: [0.0; 3],
+            };
+            for (channel, color) in vertex.color.iter_mut().zip(color) {
+                if let Some((index, scalar)) = color {
+                    *channel = scalar.normalize_color(values[index]);
+                }
+            }
+            if let [Some(nx), Some(ny), Some(nz)] = normal {
+                vertex.normal = [nx, ny, nz].map(|index| values[index] as f32);
+            }
+            Ok(vertex)
+        })
+        .collect()
+}
+
+// corner indices of every face fanned into triangles
+fn read_faces(
+    reader: &mut Reader,
+    element: &Element,
+    vertex_count: usize,
+) -> Result<Vec<usize>, String> {
+    let corners = find(element, "vertex_indices")
+        .or_else(|| find(element, "vertex_index"))
+        .ok_or("faces need vertex_indices")?;
+
+    let mut indices = Vec::new();
+    for _ in 0..element.count {
+        for (property_index, property) in element.properties.iter().enumerate()
+        {
+            let values = reader.read_property(property)?;
+            if property_index != corners {
+                continue;
+            }
+            let face = values
+                .iter()
+                .map(|&value| value as usize)
+                .collect::<Vec<_>>();
+            if let Some(&index) =
+                face.iter().find(|&&index| index >= vertex_count)
+            {
+                return Err(format!(
+                    "face corner {} past the {} vertices",
+                    index, vertex_count
+                ));
+            }
+            for i in 1..face.len().saturating_sub(1) {
+                indices.extend([face[0], face[i], face[i + 1]]);
+            }
+        }
+    }
+    Ok(indices)
+}
+
+// every vertex gets the sum of the faces around it, weighted by their area
+// since the cross product isn't normalized first
+fn smooth_normals(vertices: &mut [AAPLVertex], indices: &[usize]) {
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] =
+            [0, 1, 2].map(|corner| vertices[triangle[corner]].position);
+        let ab = [0, 1, 2].map(|axis| b[axis] - a[axis]);
+        let ac = [0, 1, 2].map(|axis| c[axis] - a[axis]);
+        let normal = math::cross(ab, ac);
+        for &index in triangle {
+            let sum = &mut vertices[index].normal;
+            for (sum, value) in sum.iter_mut().zip(normal) {
+                *sum += value;
+            }
+        }
+    }
+    // vertices only on degenerate faces have nothing to average
+    for vertex in vertices {
+        vertex.normal = match math::normalize(vertex.normal) {
+            [0.0, 0.0, 0.0] => FACING_CAMERA,
+            normal => normal,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BINARY_HEADER: &str = "element vertex 3\n\
+        property float x\nproperty float y\nproperty float z\n\
+        property ushort red\nproperty ushort green\nproperty ushort blue\n\
+        element face 1\nproperty list uchar int vertex_indices\n\
+        end_header\n";
+
+    // the same triangle as the ascii test, with 16 bit colors
+    fn binary_ply(format: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes =
+            format!("ply\nformat {} 1.0\n{}", format, BINARY_HEADER)
+                .into_bytes();
+        let vertices: [([f32; 3], [u16; 3]); 3] = [
+            ([0.0, 0.0, 0.0], [u16::MAX, 0, 0]),
+            ([1.0, 0.0, 0.0], [0, u16::MAX, 0]),
+            ([0.0, 1.0, 0.0], [0, 0, u16::MAX / 2]),
+        ];
+        for (position, color) in vertices {
+            for value in position {
+                bytes.extend(if big_endian {
+                    value.to_be_bytes()
+                } else {
+                    value.to_le_bytes()
+                });
+            }
+            for value in color {
+                bytes.extend(if big_endian {
+                    value.to_be_bytes()
+                } else {
+                    value.to_le_bytes()
+                });
+            }
+        }
+        bytes.push(3);
+        for index in [0i32, 1, 2] {
+            bytes.extend(if big_endian {
+                index.to_be_bytes()
+            } else {
+                index.to_le_bytes()
+            });
+        }
+        bytes
+    }
+
+    fn assert_triangle(vertices: &[AAPLVertex], indices: &[u16]) {
+        assert_eq!(vertices.len(), 3);
+        assert_eq!(indices, [0, 1, 2]);
+        assert_eq!(vertices[1].position, [1.0, 0.0, 0.0]);
+        assert_eq!(vertices[2].position, [0.0, 1.0, 0.0]);
+        assert_eq!(vertices[0].color, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(vertices[1].color, [0.0, 1.0, 0.0, 1.0]);
+        assert!((vertices[2].color[2] - 0.5).abs() < 1e-4);
+        // no normals in the file, so they come from the face
+        assert_eq!(vertices[0].normal, FACING_CAMERA);
+    }
+
+    #[test]
+    fn ascii_quad_is_fanned_into_two_triangles() {
+        let ply = "ply\nformat ascii 1.0\ncomment a unit quad\n\
+            element vertex 4\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face 1\nproperty list uchar int vertex_indices\n\
+            end_header\n\
+            0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n\
+            4 0 1 2 3\n";
+        let (vertices, indices) = parse_ply(ply.as_bytes()).unwrap();
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(vertices[1].color, [0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(vertices[3].color, [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn binary_little_endian_triangle() {
+        let ply = binary_ply("binary_little_endian", false);
+        let (vertices, indices) = parse_ply(&ply).unwrap();
+        assert_triangle(&vertices, &indices);
+    }
+
+    #[test]
+    fn binary_big_endian_triangle() {
+        let ply = binary_ply("binary_big_endian", true);
+        let (vertices, indices) = parse_ply(&ply).unwrap();
+        assert_triangle(&vertices, &indices);
+    }
+
+    #[test]
+    fn float_colors_are_clamped() {
+        let ply = "ply\nformat ascii 1.0\nelement vertex 1\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property float red\nproperty float green\nproperty float blue\n\
+            end_header\n0 0 0 0.25 2.0 -1.0\n";
+        let (vertices, indices) = parse_ply(ply.as_bytes()).unwrap();
+        assert!(indices.is_empty());
+        assert_eq!(vertices[0].color, [0.25, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn too_many_vertices_for_u16_are_expanded() {
+        let count = MAX_INDEXED_VERTICES + 1;
+        let mut ply = format!(
+            "ply\nformat ascii 1.0\nelement vertex {}\n\
+             property float x\nproperty float y\nproperty float z\n\
+             element face 1\nproperty list uchar int vertex_indices\n\
+             end_header\n",
+            count
+        );
+        for i in 0..count {
+            ply.push_str(&format!("{} 0 0\n", i));
+        }
+        ply.push_str(&format!("3 0 1 {}\n", count - 1));
+        let (vertices, indices) = parse_ply(ply.as_bytes()).unwrap();
+        assert!(indices.is_empty());
+        assert_eq!(vertices.len(), 3);
+        assert_eq!(vertices[2].position[0], (count - 1) as f32);
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let cases: [(&[u8], &str); 7] = [
+            (b"ply\nformat ascii 1.0\n", "missing end_header"),
+            (b"obj\nend_header\n", "not a ply file"),
+            (b"ply\nelement vertex 0\nend_header\n", "missing format"),
+            (
+                b"ply\nformat utf16 1.0\nend_header\n",
+                "unknown format utf16",
+            ),
+            (
+                b"ply\nformat ascii 1.0\nelement vertex many\nend_header\n",
+                "invalid vertex count",
+            ),
+            (
+                b"ply\nformat ascii 1.0\nproperty float x\nend_header\n",
+                "property before any element",
+            ),
+            (
+                b"ply\nformat ascii 1.0\nelement vertex 1\n\
+                  property half x\nend_header\n",
+                "unknown property type half",
+            ),
+        ];
+        for (ply, message) in cases {
+            assert_eq!(parse_ply(ply).err().as_deref(), Some(message));
+        }
+    }
+
+    #[test]
+    fn truncated_bodies_and_bad_faces_are_rejected() {
+        let mut ply = binary_ply("binary_little_endian", false);
+        ply.truncate(ply.len() - 2);
+        assert_eq!(
+            parse_ply(&ply).err().as_deref(),
+            Some("unexpected end of data")
+        );
+
+        let ply = "ply\nformat ascii 1.0\nelement vertex 1\n\
+            property float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\n\
+            end_header\n0 0 0\n3 0 0 5\n";
+        assert_eq!(
+            parse_ply(ply.as_bytes()).err().as_deref(),
+            Some("face corner 5 past the 1 vertices")
+        );
+    }
+}
@@ -0,0 +1,27 @@
+use metal::objc::{msg_send, sel, sel_impl};
+use metal::{CommandBufferRef, DrawableRef};
+
+use crate::args::PresentMode;
+
+// metal-rs only wraps the plain presentDrawable:, the timed variant is sent
+// directly. with a minimum duration the compositor holds the drawable back
+// until the interval since the previous one has passed, pacing frames
+// evenly instead of showing each at whichever vblank it happens to make
+pub fn present(
+    command_buffer: &CommandBufferRef,
+    drawable: &DrawableRef,
+    present_mode: PresentMode,
+) {
+    match present_mode {
+        PresentMode::Scheduled(interval) => unsafe {
+            let () = msg_send![
+                command_buffer,
+                presentDrawable: drawable
+                afterMinimumDuration: interval.as_secs_f64()
+            ];
+        },
+        PresentMode::Vsync | PresentMode::Immediate => {
+            command_buffer.present_drawable(drawable)
+        }
+    }
+}
@@ -3,34 +3,220 @@ using namespace metal;
 
 typedef enum AAPLVertexInputIndex
 {
-    AAPLVertexInputIndexViewportSize = 1,
+    AAPLVertexInputIndexInstances = 1,
+    AAPLVertexInputIndexUniforms = 2,
 } AAPLVertexInputIndex;
 
+typedef enum AAPLFragmentIndex
+{
+    AAPLFragmentIndexTexture = 0,
+    AAPLFragmentIndexSampler = 0,
+    AAPLFragmentIndexUniforms = 0,
+} AAPLFragmentIndex;
+
+typedef enum AAPLPostIndex
+{
+    AAPLPostIndexTexture = 0,
+    AAPLPostIndexTonemap = 0,
+} AAPLPostIndex;
+
+typedef enum AAPLSkyboxIndex
+{
+    AAPLSkyboxIndexTexture = 0,
+    AAPLSkyboxIndexUniforms = 0,
+} AAPLSkyboxIndex;
+
+typedef struct
+{
+    float4x4 model;
+    float4x4 viewProjection;
+    // world space, pointing towards the light
+    float4 lightDirection;
+    float pointSize;
+    uint linearizeColors;
+    uint showBarycentric;
+} Uniforms;
+
 typedef struct
 {
-    float2 position [[attribute(0)]];
+    float4 offsetScale;
+    float4 color;
+} InstanceData;
+
+typedef struct
+{
+    float3 position [[attribute(0)]];
     float4 color [[attribute(1)]];
+    float2 texCoord [[attribute(2)]];
+    float3 normal [[attribute(3)]];
 } VertexIn;
 
 typedef struct
 {
     float4 position [[position]];
+    float pointSize [[point_size]];
     float4 color;
+    float2 texCoord;
+    float3 normal;
+    float3 barycentric;
 } RasterizerData;
 
+// vertex colors are authored in srgb; when the target encodes srgb on write
+// or is read as linear light they have to be linearized first
+static float4 outputColor(float4 color, constant Uniforms& uniforms)
+{
+    if (!uniforms.linearizeColors)
+    {
+        return color;
+    }
+    float3 low = color.rgb / 12.92;
+    float3 high = pow((color.rgb + 0.055) / 1.055, 2.4);
+    return float4(select(high, low, color.rgb <= 0.04045), color.a);
+}
+
 vertex RasterizerData
 vertexShader(VertexIn in [[stage_in]],
-             constant float2& viewportSize [[buffer(AAPLVertexInputIndexViewportSize)]])
+             uint vertexID [[vertex_id]],
+             uint instanceID [[instance_id]],
+             device const InstanceData* instances [[buffer(AAPLVertexInputIndexInstances)]],
+             constant Uniforms& uniforms [[buffer(AAPLVertexInputIndexUniforms)]])
 {
     RasterizerData out;
-    float2 pixelSpacePosition = in.position;
-    out.position = float4(0.0, 0.0, 0.0, 1.0);
-    out.position.xy = pixelSpacePosition / (viewportSize / 2.0);
-    out.color = in.color;
+    InstanceData instance = instances[instanceID];
+    float4 localPosition = float4(in.position * instance.offsetScale.w, 1.0);
+    float4 worldPosition = uniforms.model * localPosition
+                         + float4(instance.offsetScale.xyz, 0.0);
+    out.position = uniforms.viewProjection * worldPosition;
+    // only read by the rasterizer when drawing points
+    out.pointSize = uniforms.pointSize;
+    out.color = outputColor(in.color * instance.color, uniforms);
+    out.texCoord = in.texCoord;
+    // the model matrix is rotation only and instances scale uniformly, so
+    // normals can go through it directly
+    out.normal = (uniforms.model * float4(in.normal, 0.0)).xyz;
+    // one-hot per corner so the rasterizer interpolates the weights. indexed
+    // meshes share vertices between triangles, so while this is shown they
+    // are drawn de-indexed and vertex_id counts corners in order
+    out.barycentric = float3(vertexID % 3 == 0, vertexID % 3 == 1, vertexID % 3 == 2);
+    return out;
+}
+
+// lambert diffuse with an ambient floor so faces turned away stay visible
+constant float ambient = 0.2;
+
+// the lit color goes to the first attachment and the world space normal to
+// the second, packed into [0, 1]
+typedef struct
+{
+    float4 color [[color(0)]];
+    float4 normal [[color(1)]];
+} SceneFragmentOut;
+
+fragment SceneFragmentOut fragmentShader(RasterizerData in [[stage_in]],
+                                         texture2d<float> colorTexture [[texture(AAPLFragmentIndexTexture)]],
+                                         sampler colorSampler [[sampler(AAPLFragmentIndexSampler)]],
+                                         constant Uniforms& uniforms [[buffer(AAPLFragmentIndexUniforms)]])
+{
+    float3 normal = normalize(in.normal);
+    SceneFragmentOut out;
+    out.normal = float4(normal * 0.5 + 0.5, 1.0);
+    if (uniforms.showBarycentric)
+    {
+        out.color = float4(in.barycentric, 1.0);
+        return out;
+    }
+    float diffuse = max(dot(normal, uniforms.lightDirection.xyz), 0.0);
+    float4 color = in.color * colorTexture.sample(colorSampler, in.texCoord);
+    out.color = float4(color.rgb * (ambient + (1.0 - ambient) * diffuse), color.a);
+    return out;
+}
+
+// clip space band the stencil mask pass marks, drawn as a triangle strip
+constant float2 maskCorners[4] = {
+    float2(-1.0, -0.25),
+    float2(1.0, -0.25),
+    float2(-1.0, 0.25),
+    float2(1.0, 0.25),
+};
+
+typedef struct
+{
+    float4 position [[position]];
+} MaskRasterizerData;
+
+vertex MaskRasterizerData
+maskVertexShader(uint vertexID [[vertex_id]])
+{
+    MaskRasterizerData out;
+    out.position = float4(maskCorners[vertexID], 0.0, 1.0);
+    return out;
+}
+
+// color writes are masked off in the pipeline, only the stencil is written
+fragment float4 maskFragmentShader()
+{
+    return float4(0.0);
+}
+
+typedef struct
+{
+    float4 position [[position]];
+    float2 ndc;
+} SkyboxRasterizerData;
+
+// the same full-screen triangle as the post pass, pushed to the far plane
+vertex SkyboxRasterizerData
+skyboxVertexShader(uint vertexID [[vertex_id]])
+{
+    float2 ndc = float2((vertexID << 1) & 2, vertexID & 2) * 2.0 - 1.0;
+    SkyboxRasterizerData out;
+    out.position = float4(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+// equirectangular lookup: longitude is the angle around y measured from -z,
+// latitude runs from +y at the top of the image to -y at the bottom
+fragment float4 skyboxFragmentShader(SkyboxRasterizerData in [[stage_in]],
+                                     texture2d<float> skyTexture [[texture(AAPLSkyboxIndexTexture)]],
+                                     constant float4x4& ndcToDirection [[buffer(AAPLSkyboxIndexUniforms)]])
+{
+    constexpr sampler skySampler(filter::linear, address::repeat);
+    float3 direction = normalize((ndcToDirection * float4(in.ndc, 1.0, 0.0)).xyz);
+    float2 uv = float2(atan2(direction.x, -direction.z) / (2.0 * M_PI_F) + 0.5,
+                       acos(clamp(direction.y, -1.0, 1.0)) / M_PI_F);
+    return skyTexture.sample(skySampler, uv);
+}
+
+typedef struct
+{
+    float4 position [[position]];
+    float2 texCoord;
+} PostRasterizerData;
+
+// one triangle that covers the whole viewport, the corners outside clip
+// space are cut away by the rasterizer
+vertex PostRasterizerData
+postVertexShader(uint vertexID [[vertex_id]])
+{
+    float2 texCoord = float2((vertexID << 1) & 2, vertexID & 2);
+    PostRasterizerData out;
+    out.position = float4(texCoord * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.0, 1.0);
+    out.texCoord = texCoord;
     return out;
 }
 
-fragment float4 fragmentShader(RasterizerData in [[stage_in]])
+// passes the scene through unless it was accumulated past 1.0, then
+// reinhard maps it back into range so dense overlap saturates smoothly
+fragment float4 postFragmentShader(PostRasterizerData in [[stage_in]],
+                                   texture2d<float> sceneTexture [[texture(AAPLPostIndexTexture)]],
+                                   constant uint& tonemap [[buffer(AAPLPostIndexTonemap)]])
 {
-    return in.color;
+    constexpr sampler sceneSampler(filter::nearest);
+    float4 color = sceneTexture.sample(sceneSampler, in.texCoord);
+    if (tonemap)
+    {
+        color.rgb = color.rgb / (1.0 + color.rgb);
+    }
+    return color;
 }
@@ -0,0 +1,86 @@
+use std::ffi::c_void;
+use std::mem::size_of;
+use std::path::Path;
+
+use metal::*;
+use metal_common::{MetalContext, MetalError};
+
+use crate::math::{self, Mat4};
+use crate::{stencil, texture};
+
+const AAPL_SKYBOX_TEXTURE_INDEX: u64 = 0;
+const AAPL_SKYBOX_UNIFORMS_INDEX: u64 = 0;
+
+// an equirectangular image drawn behind the scene, looked up by the
+// direction each pixel faces instead of a flat clear color
+pub struct Skybox {
+    texture: Texture,
+    srgb_texture: Texture,
+    depth_stencil_state: DepthStencilState,
+}
+
+impl Skybox {
+    pub fn load(
+        context: &MetalContext,
+        path: &Path,
+    ) -> Result<Self, MetalError> {
+        // the lookup jumps across the seam, which would pick the smallest mip
+        // along it, so the image is sampled at full resolution only
+        let texture = texture::load_texture(context, path, false)?;
+        let srgb_texture = texture::srgb_view(&texture);
+        Ok(Skybox {
+            texture,
+            srgb_texture,
+            depth_stencil_state: stencil::background_depth_stencil_state(
+                &context.device,
+            ),
+        })
+    }
+
+    // drawn first with the full-screen triangle at the far plane
+    pub fn encode(
+        &self,
+        render_encoder: &RenderCommandEncoderRef,
+        pipeline_state: &RenderPipelineStateRef,
+        ndc_to_direction: &Mat4,
+        srgb: bool,
+    ) {
+        render_encoder.set_render_pipeline_state(pipeline_state);
+        render_encoder.set_depth_stencil_state(&self.depth_stencil_state);
+        render_encoder.set_fragment_texture(
+            AAPL_SKYBOX_TEXTURE_INDEX,
+            Some(if srgb {
+                &self.srgb_texture
+            } else {
+                &self.texture
+            }),
+        );
+        render_encoder.set_fragment_bytes(
+            AAPL_SKYBOX_UNIFORMS_INDEX,
+            size_of::<Mat4>() as u64,
+            ndc_to_direction.as_ptr() as *const c_void,
+        );
+        render_encoder.draw_primitives(MTLPrimitiveType::Triangle, 0, 3);
+    }
+}
+
+// takes (ndc.x, ndc.y, 1, 0) to the direction that pixel looks in: the zoom
+// and projection are undone to get the view ray, then the orbit so the
+// background turns along with the model. the camera only translates, which
+// doesn't change directions
+pub fn ndc_to_direction(
+    projection: &Mat4,
+    zoom: f32,
+    zoom_offset: [f32; 2],
+    orbit: &Mat4,
+) -> Mat4 {
+    let x = 1.0 / (zoom * projection[0][0]);
+    let y = 1.0 / (zoom * projection[1][1]);
+    let view_ray = [
+        [x, 0.0, 0.0, 0.0],
+        [0.0, y, 0.0, 0.0],
+        [-zoom_offset[0] * x, -zoom_offset[1] * y, -1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+    math::mul(&math::transpose(orbit), &view_ray)
+}
@@ -0,0 +1,322 @@
+use crate::geometry::AAPLVertex;
+
+const BYTES_PER_PIXEL: usize = 4;
+
+// interpolates vertex colors over a triangle list, which is what the scene
+// pipeline draws for an unspun, unorbited mesh with an identity view
+// projection, the light straight on so lambert shading leaves the colors
+// alone, a white texture and the default linear output. lighting, texturing
+// and the camera aren't modeled. rows run top to bottom like a texture and
+// pixels no triangle covers stay zero
+pub fn cpu_rasterize(
+    verts: &[AAPLVertex],
+    width: usize,
+    height: usize,
+) -> Vec<u8> {
+    let mut pixels = vec![0; width * height * BYTES_PER_PIXEL];
+    for triangle in verts.chunks_exact(3) {
+        rasterize_triangle(triangle, width, height, &mut pixels);
+    }
+    pixels
+}
+
+fn rasterize_triangle(
+    triangle: &[AAPLVertex],
+    width: usize,
+    height: usize,
+    pixels: &mut [u8],
+) {
+    let corners = triangle
+        .iter()
+        .map(|vertex| to_pixel(vertex.position, width, height))
+        .collect::<Vec<_>>();
+    let area = edge(corners[0], corners[1], corners[2]);
+    if area == 0.0 {
+        return;
+    }
+
+    // only the pixels inside the bounding box can be covered
+    let min_x = corners.iter().map(|c| c[0]).fold(f32::MAX, f32::min);
+    let max_x = corners.iter().map(|c| c[0]).fold(f32::MIN, f32::max);
+    let min_y = corners.iter().map(|c| c[1]).fold(f32::MAX, f32::min);
+    let max_y = corners.iter().map(|c| c[1]).fold(f32::MIN, f32::max);
+    let x_range = pixel_range(min_x, max_x, width);
+    let y_range = pixel_range(min_y, max_y, height);
+
+    for y in y_range {
+        for x in x_range.clone() {
+            // sampled at the pixel center like the gpu rasterizer
+            let point = [x as f32 + 0.5, y as f32 + 0.5];
+            // dividing by the signed area makes the weights positive inside
+            // the triangle whichever way it winds
+            let weights = [
+                edge(corners[1], corners[2], point) / area,
+                edge(corners[2], corners[0], point) / area,
+                edge(corners[0], corners[1], point) / area,
+            ];
+            if weights.iter().any(|&weight| weight < 0.0) {
+                continue;
+            }
+
+            let offset = (y * width + x) * BYTES_PER_PIXEL;
+            let pixel = &mut pixels[offset..offset + BYTES_PER_PIXEL];
+            for (channel, byte) in pixel.iter_mut().enumerate() {
+                let value = weights
+                    .iter()
+                    .zip(triangle)
+                    .map(|(weight, vertex)| weight * vertex.color[channel])
+                    .sum::<f32>();
+                *byte = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+    }
+}
+
+// normalized device coordinates to pixels, with y flipped so row 0 is the top
+fn to_pixel(position: [f32; 3], width: usize, height: usize) -> [f32; 2] {
+    [
+        (position[0] + 1.0) * 0.5 * width as f32,
+        (1.0 - position[1]) * 0.5 * height as f32,
+    ]
+}
+
+fn pixel_range(min: f32, max: f32, size: usize) -> std::ops::Range<usize> {
+    let start = min.floor().max(0.0) as usize;
+    let end = (max.ceil().max(0.0) as usize).min(size);
+    start..end
+}
+
+// twice the signed area of abc, positive when c lies left of ab
+fn edge(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use metal::*;
+    use metal_common::{DevicePreference, MetalContext, ShaderSource};
+
+    use super::*;
+    use crate::geometry::{quad, triangle, unindex};
+    use crate::instances::InstanceData;
+    use crate::texture::{SamplerConfig, white_texture};
+    use crate::{
+        AAPL_FRAGMENT_SAMPLER_INDEX, AAPL_FRAGMENT_TEXTURE_INDEX,
+        AAPL_FRAGMENT_UNIFORMS_INDEX, AAPL_VERTEX_INPUT_INDEX_INSTANCES,
+        AAPL_VERTEX_INPUT_INDEX_UNIFORMS, AAPL_VERTEX_INPUT_INDEX_VERTICES,
+        BlendMode, DEPTH_STENCIL_PIXEL_FORMAT, NORMAL_PIXEL_FORMAT, Uniforms,
+        build_pipeline_state, create_render_target, load_shader_source, math,
+    };
+
+    const COLOR_PIXEL_FORMAT: MTLPixelFormat = MTLPixelFormat::RGBA8Unorm;
+
+    fn pixel(pixels: &[u8], width: usize, x: usize, y: usize) -> &[u8] {
+        let offset = (y * width + x) * BYTES_PER_PIXEL;
+        &pixels[offset..offset + BYTES_PER_PIXEL]
+    }
+
+    #[test]
+    fn triangle_covers_its_center_but_not_the_corners() {
+        let (vertices, _) = triangle();
+        let pixels = cpu_rasterize(&vertices, 64, 64);
+
+        assert_eq!(pixels.len(), 64 * 64 * BYTES_PER_PIXEL);
+        assert_eq!(pixel(&pixels, 64, 0, 0), [0, 0, 0, 0]);
+        assert_eq!(pixel(&pixels, 64, 63, 63), [0, 0, 0, 0]);
+        assert_eq!(pixel(&pixels, 64, 32, 32)[3], 255);
+    }
+
+    #[test]
+    fn colors_are_interpolated_towards_the_nearest_vertex() {
+        let (vertices, _) = triangle();
+        let pixels = cpu_rasterize(&vertices, 64, 64);
+
+        // just inside the red bottom right and green bottom left corners
+        let red = pixel(&pixels, 64, 46, 47);
+        let green = pixel(&pixels, 64, 17, 47);
+        assert!(red[0] > red[1] && red[0] > red[2]);
+        assert!(green[1] > green[0] && green[1] > green[2]);
+    }
+
+    // draws through the scene shaders with the setup cpu_rasterize assumes
+    // and reads the color attachment back as rgba bytes
+    fn gpu_rasterize(
+        context: &MetalContext,
+        vertices: &[AAPLVertex],
+        width: usize,
+        height: usize,
+    ) -> Vec<u8> {
+        let source = load_shader_source();
+        let library = context
+            .load_library(&ShaderSource::Source(&source))
+            .unwrap();
+        let pipeline_state = build_pipeline_state(
+            context,
+            &library,
+            1,
+            BlendMode::Opaque,
+            COLOR_PIXEL_FORMAT,
+        )
+        .unwrap();
+
+        let (width, height) = (width as u32, height as u32);
+        let device = &context.device;
+        let color =
+            create_render_target(device, COLOR_PIXEL_FORMAT, width, height, 1);
+        let normal =
+            create_render_target(device, NORMAL_PIXEL_FORMAT, width, height, 1);
+        let depth_stencil = create_render_target(
+            device,
+            DEPTH_STENCIL_PIXEL_FORMAT,
+            width,
+            height,
+            1,
+        );
+
+        let render_pass_descriptor = RenderPassDescriptor::new();
+        for (index, texture) in [&color, &normal].into_iter().enumerate() {
+            let attachment = render_pass_descriptor
+                .color_attachments()
+                .object_at(index as u64)
+                .unwrap();
+            attachment.set_texture(Some(texture));
+            attachment.set_load_action(MTLLoadAction::Clear);
+            attachment.set_clear_color(MTLClearColor::new(0.0, 0.0, 0.0, 0.0));
+            attachment.set_store_action(MTLStoreAction::Store);
+        }
+        let depth_attachment =
+            render_pass_descriptor.depth_attachment().unwrap();
+        depth_attachment.set_texture(Some(&depth_stencil));
+        depth_attachment.set_load_action(MTLLoadAction::Clear);
+        let stencil_attachment =
+            render_pass_descriptor.stencil_attachment().unwrap();
+        stencil_attachment.set_texture(Some(&depth_stencil));
+        stencil_attachment.set_load_action(MTLLoadAction::Clear);
+
+        let uniforms = Uniforms {
+            model: math::scaling(1.0),
+            view_projection: math::scaling(1.0),
+            light_dir: [0.0, 0.0, 1.0, 0.0],
+            point_size: 1.0,
+            linearize_colors: 0,
+            show_barycentric: 0,
+            _padding: 0.0,
+        };
+        let instance = InstanceData {
+            offset_scale: [0.0, 0.0, 0.0, 1.0],
+            color: [1.0; 4],
+        };
+        let vertex_buffer = context.make_shared_buffer(vertices).unwrap();
+        let instance_buffer = context.make_shared_buffer(&[instance]).unwrap();
+        let uniform_buffer = context.make_shared_buffer(&[uniforms]).unwrap();
+        let texture = white_texture(device);
+        let sampler_state = SamplerConfig::NEAREST.create(device);
+
+        let bytes_per_row = width as u64 * BYTES_PER_PIXEL as u64;
+        let readback_buffer = context
+            .new_buffer(
+                bytes_per_row * height as u64,
+                MTLResourceOptions::StorageModeShared,
+            )
+            .unwrap();
+
+        let command_buffer = context.command_queue.new_command_buffer();
+        let render_encoder =
+            command_buffer.new_render_command_encoder(render_pass_descriptor);
+        render_encoder.set_render_pipeline_state(&pipeline_state);
+        render_encoder.set_vertex_buffer(
+            AAPL_VERTEX_INPUT_INDEX_VERTICES,
+            Some(&vertex_buffer),
+            0,
+        );
+        render_encoder.set_vertex_buffer(
+            AAPL_VERTEX_INPUT_INDEX_INSTANCES,
+            Some(&instance_buffer),
+            0,
+        );
+        render_encoder.set_vertex_buffer(
+            AAPL_VERTEX_INPUT_INDEX_UNIFORMS,
+            Some(&uniform_buffer),
+            0,
+        );
+        render_encoder.set_fragment_buffer(
+            AAPL_FRAGMENT_UNIFORMS_INDEX,
+            Some(&uniform_buffer),
+            0,
+        );
+        render_encoder
+            .set_fragment_texture(AAPL_FRAGMENT_TEXTURE_INDEX, Some(&texture));
+        render_encoder.set_fragment_sampler_state(
+            AAPL_FRAGMENT_SAMPLER_INDEX,
+            Some(&sampler_state),
+        );
+        render_encoder.draw_primitives(
+            MTLPrimitiveType::Triangle,
+            0,
+            vertices.len() as u64,
+        );
+        render_encoder.end_encoding();
+
+        let blit_encoder = command_buffer.new_blit_command_encoder();
+        blit_encoder.copy_from_texture_to_buffer(
+            &color,
+            0,
+            0,
+            MTLOrigin { x: 0, y: 0, z: 0 },
+            MTLSize::new(width as u64, height as u64, 1),
+            &readback_buffer,
+            0,
+            bytes_per_row,
+            bytes_per_row * height as u64,
+            MTLBlitOption::empty(),
+        );
+        blit_encoder.end_encoding();
+        command_buffer.commit();
+        command_buffer.wait_until_completed();
+
+        unsafe {
+            std::slice::from_raw_parts(
+                readback_buffer.contents() as *const u8,
+                readback_buffer.length() as usize,
+            )
+        }
+        .to_vec()
+    }
+
+    #[test]
+    fn matches_the_scene_pipeline_within_tolerance() {
+        let Ok(context) = MetalContext::new(&DevicePreference::default())
+        else {
+            eprintln!("no metal device, skipping");
+            return;
+        };
+        let (size, tolerance) = (64, 2);
+        let (vertices, indices) = quad();
+        let mut triangles = triangle().0;
+        triangles.extend(unindex(&vertices, &indices));
+
+        let expected = cpu_rasterize(&triangles, size, size);
+        let actual = gpu_rasterize(&context, &triangles, size, size);
+        assert_eq!(actual.len(), expected.len());
+
+        // edge pixels can land on either side depending on the fill rule,
+        // everything else has to agree up to rounding
+        let mismatched = expected
+            .chunks_exact(BYTES_PER_PIXEL)
+            .zip(actual.chunks_exact(BYTES_PER_PIXEL))
+            .filter(|(expected, actual)| {
+                expected
+                    .iter()
+                    .zip(actual.iter())
+                    .any(|(&e, &a)| e.abs_diff(a) > tolerance)
+            })
+            .count();
+        assert!(
+            mismatched * 50 <= size * size,
+            "{} of {} pixels differ by more than {}",
+            mismatched,
+            size * size,
+            tolerance
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use metal::*;
+
+// value the mask pass writes; with masking on the scene only draws where the
+// stencil holds it
+pub const MASK_REFERENCE: u32 = 1;
+
+// without depth writes overlapping geometry at the same depth all passes,
+// which additive accumulation needs since it doesn't depend on draw order
+pub fn scene_depth_stencil_state(
+    device: &DeviceRef,
+    depth_write: bool,
+) -> DepthStencilState {
+    let descriptor = DepthStencilDescriptor::new();
+    descriptor.set_depth_compare_function(MTLCompareFunction::Less);
+    descriptor.set_depth_write_enabled(depth_write);
+    // the stencil is cleared to 0 every frame, so a reference of 0 passes
+    // everywhere and only MASK_REFERENCE actually masks anything
+    let stencil = stencil_descriptor(
+        MTLCompareFunction::Equal,
+        MTLStencilOperation::Keep,
+    );
+    descriptor.set_front_face_stencil(Some(&stencil));
+    descriptor.set_back_face_stencil(Some(&stencil));
+    device.new_depth_stencil_state(&descriptor)
+}
+
+pub fn mask_depth_stencil_state(device: &DeviceRef) -> DepthStencilState {
+    let descriptor = DepthStencilDescriptor::new();
+    descriptor.set_depth_compare_function(MTLCompareFunction::Always);
+    descriptor.set_depth_write_enabled(false);
+    let stencil = stencil_descriptor(
+        MTLCompareFunction::Always,
+        MTLStencilOperation::Replace,
+    );
+    descriptor.set_front_face_stencil(Some(&stencil));
+    descriptor.set_back_face_stencil(Some(&stencil));
+    device.new_depth_stencil_state(&descriptor)
+}
+
+// the background sits at the far plane and leaves depth cleared, so the
+// scene drawn after it always lands in front. no stencil test, it fills the
+// masked out area too
+pub fn background_depth_stencil_state(device: &DeviceRef) -> DepthStencilState {
+    let descriptor = DepthStencilDescriptor::new();
+    descriptor.set_depth_compare_function(MTLCompareFunction::LessEqual);
+    descriptor.set_depth_write_enabled(false);
+    device.new_depth_stencil_state(&descriptor)
+}
+
+fn stencil_descriptor(
+    compare_function: MTLCompareFunction,
+    pass_operation: MTLStencilOperation,
+) -> StencilDescriptor {
+    let descriptor = StencilDescriptor::new();
+    descriptor.set_stencil_compare_function(compare_function);
+    descriptor.set_stencil_failure_operation(MTLStencilOperation::Keep);
+    descriptor.set_depth_failure_operation(MTLStencilOperation::Keep);
+    descriptor.set_depth_stencil_pass_operation(pass_operation);
+    descriptor.set_read_mask(0xff);
+    descriptor.set_write_mask(0xff);
+    descriptor
+}
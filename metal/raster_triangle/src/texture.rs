@@ -0,0 +1,138 @@
+use std::ffi::c_void;
+use std::path::Path;
+
+use metal::*;
+use metal_common::{MetalContext, MetalError};
+
+const TEXTURE_PIXEL_FORMAT: MTLPixelFormat = MTLPixelFormat::RGBA8Unorm;
+
+// mipmapped textures get a full chain down to 1x1, filled in on the gpu from
+// the base level
+pub fn load_texture(
+    context: &MetalContext,
+    path: &Path,
+    mipmapped: bool,
+) -> Result<Texture, MetalError> {
+    let image = image::open(path)
+        .map_err(|err| {
+            MetalError::TextureLoad(format!("{}: {}", path.display(), err))
+        })?
+        .into_rgba8();
+    let (width, height) = (image.width(), image.height());
+    let mip_levels = if mipmapped {
+        width.max(height).ilog2() + 1
+    } else {
+        1
+    };
+    let texture = create_texture(
+        &context.device,
+        width,
+        height,
+        mip_levels,
+        image.as_raw(),
+    );
+    if mip_levels > 1 {
+        let command_buffer = context.command_queue.new_command_buffer();
+        let blit_encoder = command_buffer.new_blit_command_encoder();
+        blit_encoder.generate_mipmaps(&texture);
+        blit_encoder.end_encoding();
+        command_buffer.commit();
+        command_buffer.wait_until_completed();
+    }
+    Ok(texture)
+}
+
+pub fn white_texture(device: &DeviceRef) -> Texture {
+    create_texture(device, 1, 1, 1, &[255; 4])
+}
+
+// reinterprets the texels as srgb encoded so sampling returns linear values
+pub fn srgb_view(texture: &TextureRef) -> Texture {
+    texture.new_texture_view(MTLPixelFormat::RGBA8Unorm_sRGB)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplerConfig {
+    pub min_filter: MTLSamplerMinMagFilter,
+    pub mag_filter: MTLSamplerMinMagFilter,
+    pub mip_filter: MTLSamplerMipFilter,
+    // applied to both s and t
+    pub address_mode: MTLSamplerAddressMode,
+}
+
+impl SamplerConfig {
+    // blocky texels up close, for checking what the filtering smooths over
+    pub const NEAREST: SamplerConfig = SamplerConfig {
+        min_filter: MTLSamplerMinMagFilter::Nearest,
+        mag_filter: MTLSamplerMinMagFilter::Nearest,
+        mip_filter: MTLSamplerMipFilter::NotMipmapped,
+        address_mode: MTLSamplerAddressMode::ClampToEdge,
+    };
+
+    pub const LINEAR: SamplerConfig = SamplerConfig {
+        min_filter: MTLSamplerMinMagFilter::Linear,
+        mag_filter: MTLSamplerMinMagFilter::Linear,
+        mip_filter: MTLSamplerMipFilter::NotMipmapped,
+        address_mode: MTLSamplerAddressMode::ClampToEdge,
+    };
+
+    // blends between mip levels as well, only differs from LINEAR on
+    // mipmapped textures
+    pub const TRILINEAR: SamplerConfig = SamplerConfig {
+        min_filter: MTLSamplerMinMagFilter::Linear,
+        mag_filter: MTLSamplerMinMagFilter::Linear,
+        mip_filter: MTLSamplerMipFilter::Linear,
+        address_mode: MTLSamplerAddressMode::ClampToEdge,
+    };
+
+    pub const PRESETS: [SamplerConfig; 3] = [
+        SamplerConfig::LINEAR,
+        SamplerConfig::NEAREST,
+        SamplerConfig::TRILINEAR,
+    ];
+
+    pub fn next_preset(self) -> SamplerConfig {
+        let index = SamplerConfig::PRESETS
+            .iter()
+            .position(|&preset| preset == self)
+            .map_or(0, |index| index + 1);
+        SamplerConfig::PRESETS[index % SamplerConfig::PRESETS.len()]
+    }
+
+    pub fn create(self, device: &DeviceRef) -> SamplerState {
+        let descriptor = SamplerDescriptor::new();
+        descriptor.set_min_filter(self.min_filter);
+        descriptor.set_mag_filter(self.mag_filter);
+        descriptor.set_mip_filter(self.mip_filter);
+        descriptor.set_address_mode_s(self.address_mode);
+        descriptor.set_address_mode_t(self.address_mode);
+        device.new_sampler(&descriptor)
+    }
+}
+
+fn create_texture(
+    device: &DeviceRef,
+    width: u32,
+    height: u32,
+    mip_levels: u32,
+    rgba: &[u8],
+) -> Texture {
+    let descriptor = TextureDescriptor::new();
+    descriptor.set_texture_type(MTLTextureType::D2);
+    descriptor.set_pixel_format(TEXTURE_PIXEL_FORMAT);
+    descriptor.set_width(width as u64);
+    descriptor.set_height(height as u64);
+    descriptor.set_mipmap_level_count(mip_levels as u64);
+    descriptor.set_usage(
+        MTLTextureUsage::ShaderRead | MTLTextureUsage::PixelFormatView,
+    );
+
+    let texture = device.new_texture(&descriptor);
+    texture.replace_region(
+        MTLRegion::new_2d(0, 0, width as u64, height as u64),
+        0,
+        rgba.as_ptr() as *const c_void,
+        width as u64 * 4,
+    );
+    texture
+}
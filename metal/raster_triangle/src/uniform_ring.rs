@@ -0,0 +1,83 @@
+use std::cell::Cell;
+use std::mem::size_of;
+
+use metal::*;
+use metal_common::FrameSemaphore;
+
+use crate::vertex_ring::FRAMES_IN_FLIGHT;
+
+// buffer offsets bound to the constant address space have to be a multiple
+// of this on macos
+const UNIFORM_ALIGNMENT: u64 = 256;
+
+// one shared buffer split into a region per frame in flight, constants for
+// a frame are bumped one after the other into its region so a write never
+// lands where a frame still on the gpu reads
+pub struct UniformRing {
+    buffer: Buffer,
+    region_size: u64,
+    region: usize,
+    // next free byte within the current region
+    offset: Cell<u64>,
+    semaphore: FrameSemaphore,
+    // a region was taken this frame and still needs its completion signal
+    pending_signal: bool,
+}
+
+impl UniformRing {
+    pub fn new(device: &DeviceRef, region_size: u64) -> Self {
+        let region_size = region_size.next_multiple_of(UNIFORM_ALIGNMENT);
+        UniformRing {
+            buffer: device.new_buffer(
+                region_size * FRAMES_IN_FLIGHT as u64,
+                MTLResourceOptions::StorageModeShared,
+            ),
+            region_size,
+            region: 0,
+            offset: Cell::new(0),
+            semaphore: FrameSemaphore::new(FRAMES_IN_FLIGHT),
+            pending_signal: false,
+        }
+    }
+
+    // waits for the oldest frame to finish with its region if all of them
+    // are in flight, then starts allocating from the start of it
+    pub fn begin_frame(&mut self) {
+        if !self.pending_signal {
+            self.semaphore.wait();
+            self.pending_signal = true;
+            self.region = (self.region + 1) % FRAMES_IN_FLIGHT;
+            self.offset.set(0);
+        }
+    }
+
+    // copies value into the current region and returns where to bind it
+    pub fn alloc<T: Copy>(&self, value: &T) -> (Buffer, u64) {
+        let start = self.offset.get().next_multiple_of(UNIFORM_ALIGNMENT);
+        let end = start + size_of::<T>() as u64;
+        assert!(
+            end <= self.region_size,
+            "uniform ring region of {} bytes is full",
+            self.region_size
+        );
+        self.offset.set(end);
+
+        let offset = self.region as u64 * self.region_size + start;
+        unsafe {
+            std::ptr::write(
+                (self.buffer.contents() as *mut u8).add(offset as usize)
+                    as *mut T,
+                *value,
+            );
+        }
+        (self.buffer.clone(), offset)
+    }
+
+    // call before committing the command buffer that reads this frame's
+    // constants
+    pub fn signal_on_completion(&mut self, command_buffer: &CommandBufferRef) {
+        if std::mem::take(&mut self.pending_signal) {
+            self.semaphore.signal_on_completion(command_buffer);
+        }
+    }
+}
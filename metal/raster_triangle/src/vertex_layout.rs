@@ -0,0 +1,47 @@
+use std::mem::size_of;
+
+use metal::*;
+
+// describes one interleaved vertex buffer, numbering attributes in the order
+// they're added so they line up with the shader's [[attribute(n)]]
+pub struct VertexLayoutBuilder {
+    descriptor: VertexDescriptor,
+    buffer_index: u64,
+    attribute_count: u64,
+}
+
+impl VertexLayoutBuilder {
+    pub fn new(buffer_index: u64) -> Self {
+        VertexLayoutBuilder {
+            descriptor: VertexDescriptor::new().to_owned(),
+            buffer_index,
+            attribute_count: 0,
+        }
+    }
+
+    // take offsets from offset_of! so they follow the struct's layout
+    pub fn attribute(mut self, format: MTLVertexFormat, offset: usize) -> Self {
+        let attribute = self
+            .descriptor
+            .attributes()
+            .object_at(self.attribute_count)
+            .unwrap();
+        attribute.set_format(format);
+        attribute.set_offset(offset as u64);
+        attribute.set_buffer_index(self.buffer_index);
+        self.attribute_count += 1;
+        self
+    }
+
+    pub fn build<V>(self) -> VertexDescriptor {
+        let layout = self
+            .descriptor
+            .layouts()
+            .object_at(self.buffer_index)
+            .unwrap();
+        layout.set_stride(size_of::<V>() as u64);
+        layout.set_step_rate(1);
+        layout.set_step_function(MTLVertexStepFunction::PerVertex);
+        self.descriptor
+    }
+}
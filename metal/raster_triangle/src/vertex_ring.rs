@@ -0,0 +1,93 @@
+use std::mem::size_of;
+
+use metal::*;
+use metal_common::{FrameSemaphore, MetalError, allocate_buffer};
+
+use crate::geometry::AAPLVertex;
+
+// one buffer for the frame the cpu is writing and up to two the gpu may
+// still be reading
+pub const FRAMES_IN_FLIGHT: usize = 3;
+
+// vertices rewritten every frame, cycling through buffers so a write never
+// lands in one a frame still in flight reads from
+pub struct VertexRing {
+    device: Device,
+    buffers: Vec<Buffer>,
+    capacity: usize,
+    current: usize,
+    semaphore: FrameSemaphore,
+    // a slot was taken this frame and still needs its completion signal
+    pending_signal: bool,
+}
+
+impl VertexRing {
+    pub fn new(
+        device: &DeviceRef,
+        capacity: usize,
+    ) -> Result<Self, MetalError> {
+        Ok(VertexRing {
+            device: device.to_owned(),
+            buffers: new_buffers(device, capacity)?,
+            capacity,
+            current: 0,
+            semaphore: FrameSemaphore::new(FRAMES_IN_FLIGHT),
+            pending_signal: false,
+        })
+    }
+
+    // waits for the oldest frame to finish with its buffer if all of them
+    // are in flight, then copies the vertices into it. a failed grow keeps
+    // the old buffers and writes nothing
+    pub fn write_vertices(
+        &mut self,
+        vertices: &[AAPLVertex],
+    ) -> Result<(), MetalError> {
+        if !self.pending_signal {
+            self.semaphore.wait();
+            self.pending_signal = true;
+            self.current = (self.current + 1) % FRAMES_IN_FLIGHT;
+        }
+        if vertices.len() > self.capacity {
+            // the old buffers stay alive while frames in flight hold them
+            let capacity = vertices.len().next_power_of_two();
+            self.buffers = new_buffers(&self.device, capacity)?;
+            self.capacity = capacity;
+        }
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                vertices.as_ptr(),
+                self.buffers[self.current].contents() as *mut AAPLVertex,
+                vertices.len(),
+            );
+        }
+        Ok(())
+    }
+
+    pub fn current_buffer(&self) -> &BufferRef {
+        &self.buffers[self.current]
+    }
+
+    // call before committing the command buffer that reads this frame's
+    // vertices
+    pub fn signal_on_completion(&mut self, command_buffer: &CommandBufferRef) {
+        if std::mem::take(&mut self.pending_signal) {
+            self.semaphore.signal_on_completion(command_buffer);
+        }
+    }
+}
+
+fn new_buffers(
+    device: &DeviceRef,
+    capacity: usize,
+) -> Result<Vec<Buffer>, MetalError> {
+    (0..FRAMES_IN_FLIGHT)
+        .map(|_| {
+            allocate_buffer(
+                device,
+                (capacity.max(1) * size_of::<AAPLVertex>()) as u64,
+                MTLResourceOptions::StorageModeShared,
+            )
+        })
+        .collect()
+}